@@ -0,0 +1,1364 @@
+use crate::configs::defaults::*;
+use crate::error::Error;
+use crate::hook::Hook;
+use crate::quirks::Quirks;
+use crate::timing::{vip_cycles, Timing, VipClock};
+#[cfg(feature = "std")]
+use std::fs::File;
+#[cfg(feature = "std")]
+use std::io;
+#[cfg(feature = "std")]
+use std::io::Read;
+
+// Fx0A halts the cpu until a key finishes the wait, nothing is fetched
+// meanwhile and the timers keep running
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyWait {
+    // Waiting for a key to go down, the key goes in Vx
+    Press { x: u8 },
+    // The key went down, with the key_release quirk it has to come back up
+    Release { x: u8, key: u8 },
+}
+
+// Fields are crate visible so state serialization can reach them,
+// everything outside the core goes through the inspection API below
+#[derive(Copy, Clone)]
+pub struct Chip8 {
+    pub(crate) registers: [u8; 16],
+    pub(crate) memory: [u8; 4096],
+    pub(crate) index: usize,
+    pub(crate) pc: usize,
+    pub(crate) stack: [u16; 16],
+    pub(crate) sp: usize,
+    pub(crate) delay_timer: u8,
+    pub(crate) sound_timer: u8,
+    pub(crate) keypad: [bool; 16],
+    pub(crate) vram: [[u8; 64]; 32],
+    pub(crate) vram_change: bool,
+    pub(crate) opcode: u16,
+    pub(crate) key_wait: Option<KeyWait>,
+    pub(crate) rng: u32,
+    pub(crate) last_write: Option<(usize, usize)>,
+    // Configuration rather than state, save states leave these out
+    pub(crate) quirks: Quirks,
+    pub(crate) timing: Timing,
+    pub(crate) clock: VipClock,
+    // A display interrupt happened since take_vblank
+    pub(crate) vblank: bool,
+}
+
+impl Default for Chip8 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Chip8 {
+    pub fn new() -> Self {
+        Self {
+            registers: [0; 16],
+            memory: [0; 4096],
+            index: 0,
+            pc: 0x200,
+            stack: [0; 16],
+            sp: 0,
+            delay_timer: 0,
+            sound_timer: 0,
+            keypad: [false; 16],
+            vram: [[0; 64]; 32],
+            vram_change: false,
+            opcode: 0,
+            key_wait: None,
+            rng: DEFAULT_SEED,
+            last_write: None,
+            quirks: Quirks::CHIP8,
+            timing: Timing::Ticks,
+            clock: VipClock::default(),
+            vblank: false,
+        }
+    }
+
+    // Seed the Cxkk random generator, equal seeds give equal runs
+    pub fn with_seed(seed: u32) -> Self {
+        let mut chip = Self::new();
+        chip.set_seed(seed);
+        chip
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    // Like the reset switch, the cpu starts over at 0x200 with clear
+    // registers, stack, timers and screen but memory stays as the program
+    // left it. Held keys, the random state and the configuration stay too
+    pub fn soft_reset(&mut self) {
+        self.registers = [0; 16];
+        self.index = 0;
+        self.pc = START_ADDRESS as usize;
+        self.stack = [0; 16];
+        self.sp = 0;
+        self.delay_timer = 0;
+        self.sound_timer = 0;
+        self.vram = [[0; 64]; 32];
+        self.vram_change = true;
+        self.opcode = 0;
+        self.key_wait = None;
+        self.last_write = None;
+        self.clock = VipClock::default();
+        self.vblank = false;
+    }
+
+    pub fn timing(&self) -> Timing {
+        self.timing
+    }
+
+    pub fn set_timing(&mut self, timing: Timing) {
+        self.timing = timing;
+        self.clock = VipClock::default();
+    }
+
+    // Whether a display interrupt has happened since the last call, with
+    // VIP timing that's where a frame ends
+    pub fn take_vblank(&mut self) -> bool {
+        core::mem::take(&mut self.vblank)
+    }
+
+    pub fn set_seed(&mut self, seed: u32) {
+        // xorshift never leaves zero, so zero is swapped for the default
+        self.rng = if seed == 0 { DEFAULT_SEED } else { seed };
+    }
+
+    // Run one instruction
+    // On a fault pc is left on the faulting instruction and timers don't tick
+    // With VIP timing the timers only tick in the display interrupt
+    pub fn cycle(&mut self) -> Result<(), Error> {
+        self.cycle_with(&mut ())
+    }
+
+    // Same as cycle, telling the hook about the instruction before and
+    // after it runs. While halted on Fx0A no instruction runs and the hook
+    // hears nothing
+    pub fn cycle_with<H: Hook>(&mut self, hook: &mut H) -> Result<(), Error> {
+        if let Some(wait) = self.key_wait {
+            self.last_write = None;
+            let cost = match self.timing {
+                Timing::Ticks => 0,
+                Timing::Vip => vip_cycles(self, self.opcode),
+            };
+            self.poll_key_wait(wait);
+            self.count_cycle(self.opcode, cost);
+            return Ok(());
+        }
+
+        let pc = self.pc;
+        if pc + 1 >= self.memory.len() {
+            return Err(Error::MemoryOutOfBounds(pc));
+        }
+
+        // Fetch opcode
+        let opcode = ((self.memory[pc] as u16) << 8)
+            | (self.memory[pc + 1]) as u16;
+        hook.before(self, pc, opcode);
+        self.opcode = opcode;
+        let cost = match self.timing {
+            Timing::Ticks => 0,
+            Timing::Vip => vip_cycles(self, opcode),
+        };
+
+        // Increment pc
+        self.pc += 2;
+
+        // Match opcode and execute
+        if let Err(fault) = self.execute() {
+            self.pc = pc;
+            return Err(fault);
+        }
+        hook.after(self, pc, opcode);
+        self.count_cycle(opcode, cost);
+        Ok(())
+    }
+
+    // Decrement sound timer and delay timer
+    fn count_cycle(&mut self, opcode: u16, cost: u32) {
+        match self.timing {
+            Timing::Ticks => self.timer_tick(),
+            Timing::Vip => {
+                for _ in 0..self.clock.charge(opcode, cost) {
+                    self.timer_tick();
+                    self.vblank = true;
+                }
+            }
+        }
+    }
+
+    // With several keys down the highest numbered one counts
+    fn poll_key_wait(&mut self, wait: KeyWait) {
+        let down = (0..16u8).rev().find(|&key| self.keypad[key as usize]);
+        self.key_wait = match wait {
+            KeyWait::Press { x } => match down {
+                Some(key) if self.quirks.key_release => Some(KeyWait::Release { x, key }),
+                Some(key) => {
+                    self.registers[x as usize] = key;
+                    None
+                }
+                None => Some(wait),
+            },
+            KeyWait::Release { x, key } if !self.keypad[key as usize] => {
+                self.registers[x as usize] = key;
+                None
+            }
+            KeyWait::Release { .. } => Some(wait),
+        };
+    }
+
+    // Match opcode and execute
+    // Handlers that can fault return their result, the rest always succeed
+    fn execute(&mut self) -> Result<(), Error> {
+        let nibbles = (
+            ((self.opcode & 0xF000) >> 12) as u8,
+            ((self.opcode & 0x0F00) >> 8) as u8,
+            ((self.opcode & 0x00F0) >> 4) as u8,
+            (self.opcode & 0x000F) as u8,
+        );
+        self.last_write = None;
+        match nibbles {
+            (0x00, 0x00, 0x0e, 0x00) => self.op_00e0(),
+            (0x00, 0x00, 0x0e, 0x0e) => return self.op_00ee(),
+            // 0nnn - SYS addr, machine code routines are ignored
+            (0x00, _, _, _) => (),
+            (0x01, _, _, _) => self.op_1nnn(),
+            (0x02, _, _, _) => return self.op_2nnn(),
+            (0x03, _, _, _) => self.op_3xkk(),
+            (0x04, _, _, _) => self.op_4xkk(),
+            (0x05, _, _, 0x00) => self.op_5xy0(),
+            (0x06, _, _, _) => self.op_6xkk(),
+            (0x07, _, _, _) => self.op_7xkk(),
+            (0x08, _, _, 0x00) => self.op_8xy0(),
+            (0x08, _, _, 0x01) => self.op_8xy1(),
+            (0x08, _, _, 0x02) => self.op_8xy2(),
+            (0x08, _, _, 0x03) => self.op_8xy3(),
+            (0x08, _, _, 0x04) => self.op_8xy4(),
+            (0x08, _, _, 0x05) => self.op_8xy5(),
+            (0x08, _, _, 0x06) => self.op_8xy6(),
+            (0x08, _, _, 0x07) => self.op_8xy7(),
+            (0x08, _, _, 0x0e) => self.op_8xye(),
+            (0x09, _, _, 0x00) => self.op_9xy0(),
+            (0x0a, _, _, _) => self.op_annn(),
+            (0x0b, _, _, _) => self.op_bnnn(),
+            (0x0c, _, _, _) => self.op_ckkk(),
+            (0x0d, _, _, _) => return self.op_dxyn(),
+            (0x0e, _, 0x09, 0x0e) => self.op_ex9e(),
+            (0x0e, _, 0x0a, 0x01) => self.op_exa1(),
+            (0x0f, _, 0x00, 0x07) => self.op_fx07(),
+            (0x0f, _, 0x00, 0x0a) => self.op_fx0a(),
+            (0x0f, _, 0x01, 0x05) => self.op_fx15(),
+            (0x0f, _, 0x01, 0x08) => self.op_fx18(),
+            (0x0f, _, 0x01, 0x0e) => self.op_fx1e(),
+            (0x0f, _, 0x02, 0x09) => self.op_fx29(),
+            (0x0f, _, 0x03, 0x03) => return self.op_fx33(),
+            (0x0f, _, 0x05, 0x05) => return self.op_fx55(),
+            (0x0f, _, 0x06, 0x05) => return self.op_fx65(),
+            _ => return Err(Error::UnknownOpcode(self.opcode)),
+        }
+        Ok(())
+    }
+
+    // Decode and execute a single opcode without fetching it from memory
+    // Timers are left untouched, pc is only changed by the opcode itself
+    pub fn execute_opcode(&mut self, opcode: u16) -> Result<(), Error> {
+        self.opcode = opcode;
+        self.execute()
+    }
+
+    // Check that memory[address..address + len] exists before touching it
+    fn check_range(&self, address: usize, len: usize) -> Result<(), Error> {
+        if address + len > self.memory.len() {
+            return Err(Error::MemoryOutOfBounds(address + len - 1));
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "std")]
+    pub fn load_rom(&mut self, rom:&str) -> io::Result<()> {
+        let mut f = File::open(rom)?;
+        let mut buf: Vec<u8> = Vec::with_capacity(4096);
+        f.read_to_end(&mut buf)?;
+
+        self.load_program(&buf)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    // Copy a rom image to START_ADDRESS and the font to FONT_START_ADDRESS
+    pub fn load_program(&mut self, rom: &[u8]) -> Result<(), Error> {
+        let start = START_ADDRESS as usize;
+        if rom.len() > self.memory.len() - start {
+            return Err(Error::RomTooLarge(rom.len()));
+        }
+        self.memory[start..start + rom.len()].copy_from_slice(rom);
+
+        let font = FONT_START_ADDRESS as usize;
+        self.memory[font..font + FONT_SIZES.len()].copy_from_slice(&FONT_SIZES);
+        Ok(())
+    }
+
+    pub fn set_key(&mut self, key: usize, state: bool) {
+        self.keypad[key] = state;
+    }
+
+    // Release every key, used when a state is stored or restored
+    pub fn clear_keys(&mut self) {
+        self.keypad = [false; 16];
+    }
+
+    // Read API
+    // Everything a debugger or frontend needs to inspect the machine
+
+    pub fn registers(&self) -> &[u8; 16] {
+        &self.registers
+    }
+
+    pub fn register(&self, x: usize) -> u8 {
+        self.registers[x]
+    }
+
+    pub fn memory(&self) -> &[u8; 4096] {
+        &self.memory
+    }
+
+    pub fn peek(&self, address: usize) -> u8 {
+        self.memory[address]
+    }
+
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    pub fn sp(&self) -> usize {
+        self.sp
+    }
+
+    // Return addresses currently on the stack, oldest first
+    pub fn stack_frames(&self) -> &[u16] {
+        &self.stack[..self.sp]
+    }
+
+    // Last opcode fetched by cycle() or passed to execute_opcode()
+    pub fn opcode(&self) -> u16 {
+        self.opcode
+    }
+
+    // Some while halted on Fx0A
+    pub fn key_wait(&self) -> Option<KeyWait> {
+        self.key_wait
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    // (address, length) of memory written by the last instruction, if any
+    pub fn last_write(&self) -> Option<(usize, usize)> {
+        self.last_write
+    }
+
+    pub fn keypad(&self) -> &[bool; 16] {
+        &self.keypad
+    }
+
+    pub fn framebuffer(&self) -> &[[u8; 64]; 32] {
+        &self.vram
+    }
+
+    // True when the framebuffer changed since the last call
+    pub fn take_vram_change(&mut self) -> bool {
+        let changed = self.vram_change;
+        self.vram_change = false;
+        changed
+    }
+
+    // Write API
+    // Addresses and register numbers past the end of memory panic like
+    // any other out of bounds index
+
+    pub fn set_register(&mut self, x: usize, value: u8) {
+        self.registers[x] = value;
+    }
+
+    pub fn poke(&mut self, address: usize, value: u8) {
+        self.memory[address] = value;
+    }
+
+    pub fn set_index(&mut self, index: usize) {
+        self.index = index;
+    }
+
+    pub fn set_pc(&mut self, pc: usize) {
+        self.pc = pc;
+    }
+
+    pub fn set_delay_timer(&mut self, value: u8) {
+        self.delay_timer = value;
+    }
+
+    pub fn set_sound_timer(&mut self, value: u8) {
+        self.sound_timer = value;
+    }
+
+    // xorshift32, kept in the machine state so rewinds and save states
+    // replay the same numbers
+    pub fn gen_rand(&mut self) -> u8 {
+        let mut x = self.rng;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.rng = x;
+        (x >> 24) as u8
+    }
+
+    pub fn timer_tick(&mut self) {
+        if self.delay_timer > 0 {
+            self.delay_timer -= 1;
+        }
+        if self.sound_timer > 0 {
+            // TODO SOUND
+            if self.sound_timer == 1 {
+                // BEEP
+            }
+            self.sound_timer -= 1;
+        }
+    }
+
+    // 00E0 - CLS, clears the display
+    fn op_00e0(&mut self) {
+        self.vram = [[0; 64]; 32];
+        self.vram_change = true;
+    }
+
+    // 00EE - RET, return from subroutine
+    // Gets address from stack
+    fn op_00ee(&mut self) -> Result<(), Error> {
+        if self.sp == 0 {
+            return Err(Error::StackUnderflow);
+        }
+        self.sp -= 1;
+        self.pc = self.stack[self.sp] as usize;
+        Ok(())
+    }
+
+    // 1nnn - JMP addr
+    // Jump to location in memory nnn
+    fn op_1nnn(&mut self) {
+        // What does this do?
+        let address: u16 = self.opcode & 0x0FFF;
+        self.pc = address as usize;
+    }
+
+    // 2nnn - CALL addr
+    // Call subroutine at nnn
+    fn op_2nnn(&mut self) -> Result<(), Error> {
+        let address: u16 = self.opcode & 0x0FFF;
+        if self.sp >= self.stack.len() {
+            return Err(Error::StackOverflow);
+        }
+        self.stack[self.sp] = self.pc as u16;
+        self.sp += 1;
+        self.pc = address as usize;
+        Ok(())
+    }
+
+    // 3xkk - SE vx, Byte
+    // Skip next instruction if vx == kk
+    fn op_3xkk(&mut self) {
+        let vx: u8 = ((self.opcode & 0x0F00) >> 8u8) as u8;
+        let byte: u8 = (self.opcode & 0x00FF) as u8;
+
+        if self.registers[vx as usize] == byte {
+            self.pc += 2;
+        }
+    }
+
+    // 4xkk - SNE vx, Byte
+    // Skip next instruction if vx != kk
+    fn op_4xkk(&mut self) {
+        let vx: u8 = ((self.opcode & 0x0F00) >> 8u8) as u8;
+        let byte: u8 = (self.opcode & 0x00FF) as u8;
+
+        if self.registers[vx as usize] != byte {
+            self.pc += 2;
+        }
+    }
+
+    // 5xy0 - SE vx, vy
+    // Skip next instruction if vx == vy
+    fn op_5xy0(&mut self) {
+        let vx: u8 = ((self.opcode & 0x0F00) >> 8u8) as u8;
+        let vy: u8 = ((self.opcode & 0x00F0) >> 4u8) as u8;
+
+        if self.registers[vx as usize] == self.registers[vy as usize] {
+            self.pc += 2;
+        }
+    }
+
+    // 6xkk - LD vx, Byte
+    // Load kk into vx
+    fn op_6xkk(&mut self) {
+        let vx: u8 = ((self.opcode & 0x0F00) >> 8u8) as u8;
+        let byte: u8 = (self.opcode & 0x00FF) as u8;
+
+        self.registers[vx as usize] = byte;
+    }
+
+    // 7xkk - ADD vx, Byte
+    // Add kk to vx
+    fn op_7xkk(&mut self) {
+        let vx: u8 = ((self.opcode & 0x0F00) >> 8u8) as u8;
+        let byte: u8 = (self.opcode & 0x00FF) as u8;
+
+        let result: u16 = self.registers[vx as usize] as u16 + byte as u16;
+
+        self.registers[vx as usize] = result as u8;
+    }
+
+    // 8xy0 - LD vx, vy
+    // Load vy into vx
+    fn op_8xy0(&mut self) {
+        let vx: u8 = ((self.opcode & 0x0F00) >> 8u8) as u8;
+        let vy: u8 = ((self.opcode & 0x00F0) >> 4u8) as u8;
+
+        self.registers[vx as usize] = self.registers[vy as usize];
+    }
+
+    // 8xy1 - OR vx, vy
+    // Sets vx = vx OR vy
+    fn op_8xy1(&mut self) {
+        let vx: u8 = ((self.opcode & 0x0F00) >> 8u8) as u8;
+        let vy: u8 = ((self.opcode & 0x00F0) >> 4u8) as u8;
+
+        self.registers[vx as usize] |= self.registers[vy as usize];
+        if self.quirks.vf_reset {
+            self.registers[0xF] = 0;
+        }
+    }
+
+    // 8xy2 - AND vx, vy
+    // Sets vx = vx AND vy
+    fn op_8xy2(&mut self) {
+        let vx: u8 = ((self.opcode & 0x0F00) >> 8u8) as u8;
+        let vy: u8 = ((self.opcode & 0x00F0) >> 4u8) as u8;
+
+        self.registers[vx as usize] &= self.registers[vy as usize];
+        if self.quirks.vf_reset {
+            self.registers[0xF] = 0;
+        }
+    }
+
+    // 8xy3 - XOR vx, vy
+    // Sets vx = vx XOR vy
+    fn op_8xy3(&mut self) {
+        let vx: u8 = ((self.opcode & 0x0F00) >> 8u8) as u8;
+        let vy: u8 = ((self.opcode & 0x00F0) >> 4u8) as u8;
+
+        self.registers[vx as usize] ^= self.registers[vy as usize];
+        if self.quirks.vf_reset {
+            self.registers[0xF] = 0;
+        }
+    }
+
+    // 8xy4 - ADD vx, vy
+    // Sets vx = vx + vy
+    fn op_8xy4(&mut self) {
+        let vx: u8 = ((self.opcode & 0x0F00) >> 8u8) as u8;
+        let vy: u8 = ((self.opcode & 0x00F0) >> 4u8) as u8;
+        let x: u16 = self.registers[vx as usize] as u16;
+        let y: u16 = self.registers[vy as usize] as u16;
+        let sum = x + y;
+
+        // VF is written last so the flag wins when x == F
+        self.registers[vx as usize] = sum as u8;
+        self.registers[0xF] = (sum > 0xFF) as u8;
+    }
+
+    // 8xy5 - SUB vx, vy
+    // Sets vx = vx - vy
+    fn op_8xy5(&mut self) {
+        let vx: u8 = ((self.opcode & 0x0F00) >> 8u8) as u8;
+        let vy: u8 = ((self.opcode & 0x00F0) >> 4u8) as u8;
+        let x: u8 = self.registers[vx as usize];
+        let y: u8 = self.registers[vy as usize];
+
+        // VF = NOT borrow, written last so the flag wins when x == F
+        self.registers[vx as usize] = x.wrapping_sub(y);
+        self.registers[0xF] = (x >= y) as u8;
+    }
+
+    // 8xy6
+    // Store the value of register VY shifted right one bit in register VX
+    // Set register VF to the least significant bit prior to the shift
+    // Bitshift right, save LSB in VF
+    fn op_8xy6(&mut self) {
+        let vx: u8 = ((self.opcode & 0x0F00) >> 8u8) as u8;
+        let vy: u8 = ((self.opcode & 0x00F0) >> 4u8) as u8;
+
+        if self.quirks.shift_vy {
+            self.registers[vx as usize] = self.registers[vy as usize];
+        }
+        let lsb = self.registers[vx as usize] & 1;
+
+        self.registers[vx as usize] = self.registers[vx as usize].overflowing_shr(1).0;
+        self.registers[0xF] = lsb;
+    }
+
+    // 8xy7 - SUBN vx, vy
+    // Sets vx = vy - vx
+    fn op_8xy7(&mut self) {
+        let vx: u8 = ((self.opcode & 0x0F00) >> 8u8) as u8;
+        let vy: u8 = ((self.opcode & 0x00F0) >> 4u8) as u8;
+        let x: u8 = self.registers[vx as usize];
+        let y: u8 = self.registers[vy as usize];
+
+        // VF = NOT borrow, written last so the flag wins when x == F
+        self.registers[vx as usize] = y.wrapping_sub(x);
+        self.registers[0xF] = (y >= x) as u8;
+    }
+
+    // 8xyE - SHL vx {, vy}
+    // Bitshift left, save MSB in VF
+    fn op_8xye(&mut self) {
+        let vx: u8 = ((self.opcode & 0x0F00) >> 8u8) as u8;
+        let vy: u8 = ((self.opcode & 0x00F0) >> 4u8) as u8;
+
+        // Read vy before writing vx, they may be the same register
+        let source = if self.quirks.shift_vy { vy } else { vx };
+        let value = self.registers[source as usize];
+
+        self.registers[vx as usize] = value << 1;
+        self.registers[0xF] = (value & 0x80) >> 7;
+    }
+
+    // 9xy0 - SNE vx, vy
+    // Skip next instruction if vx != vy
+    fn op_9xy0(&mut self) {
+        let vx: u8 = ((self.opcode & 0x0F00) >> 8u8) as u8;
+        let vy: u8 = ((self.opcode & 0x00F0) >> 4u8) as u8;
+
+        if self.registers[vx as usize] != self.registers[vy as usize] {
+            self.pc += 2;
+        }
+    }
+
+    // Annn - LD I, addr
+    // Set I = nnn
+    fn op_annn(&mut self) {
+        let address: u16 = self.opcode & 0x0FFF;
+        self.index = address as usize;
+    }
+
+    // Bnnn - JP V0, addr
+    // Jump to nnn + V0, or xnn + Vx with the jump quirk
+    fn op_bnnn(&mut self) {
+        let address: u16 = self.opcode & 0x0FFF;
+        let x = if self.quirks.jump_vx { (self.opcode & 0x0F00) >> 8 } else { 0 };
+        self.pc = (self.registers[x as usize] as u16 + address) as usize;
+    }
+
+    // Cxkk - RND vx, Byte
+    // Set vx = random byte AND kk
+    fn op_ckkk(&mut self) {
+        let vx: u8 = ((self.opcode & 0x0F00) >> 8u8) as u8;
+        let byte: u8 = (self.opcode & 0x00FF) as u8;
+
+        self.registers[vx as usize] = self.gen_rand() & byte;
+    }
+
+    // Dxyn - DRW vx, vy, nibble
+    // Display n-byte sprite, starting at vx, vy
+    // Set VF = collision
+    fn op_dxyn(&mut self) -> Result<(), Error> {
+        let vx: u8 = ((self.opcode & 0x0F00) >> 8) as u8;
+        let vy: u8 = ((self.opcode & 0x00F0) >> 4) as u8;
+        let n: u8 = (self.opcode & 0x000F) as u8;
+        self.check_range(self.index, n as usize)?;
+
+        self.registers[0xF] = 0;
+
+        // The start wraps either way, clipping cuts off what runs past the edge
+        let left = self.registers[vx as usize] as u16 % VIDEO_WIDTH;
+        let top = self.registers[vy as usize] as u16 % VIDEO_HEIGHT;
+        for byte in 0..n {
+            if self.quirks.clip && top + byte as u16 >= VIDEO_HEIGHT {
+                break;
+            }
+            let y = (top + byte as u16) % VIDEO_HEIGHT;
+            for bit in 0..8 {
+                if self.quirks.clip && left + bit >= VIDEO_WIDTH {
+                    break;
+                }
+                let x = (left + bit) % VIDEO_WIDTH;
+                let color = (self.memory[self.index + byte as usize] >> (7 - bit)) & 1;
+                self.registers[0xF] |= color & self.vram[y as usize][x as usize];
+                self.vram[y as usize][x as usize] ^= color;
+            }
+        }
+        self.vram_change = true;
+        Ok(())
+    }
+
+    // Ex9E - SKP vx
+    // Skip next instruction if key of value vx is pressed
+    fn op_ex9e(&mut self) {
+        let vx: u8 = ((self.opcode & 0x0F00) >> 8u8) as u8;
+        let key: u8 = self.registers[vx as usize] & 0xF;
+
+        if self.keypad[key as usize] {
+            self.pc += 2;
+        }
+    }
+
+    // ExA1 - SKNP vx
+    // Skip next instruction if key of value vx is not pressed
+    fn op_exa1(&mut self) {
+        let vx: u8 = ((self.opcode & 0x0F00) >> 8u8) as u8;
+        let key: u8 = self.registers[vx as usize] & 0xF;
+
+        if !self.keypad[key as usize] {
+            self.pc += 2;
+        }
+    }
+
+    // Fx07 - LD vx, DT
+    // Set vx = delay timer value
+    fn op_fx07(&mut self) {
+        let vx: u8 = ((self.opcode & 0x0F00) >> 8u8) as u8;
+        self.registers[vx as usize] = self.delay_timer;
+    }
+
+    // Fx0A - LD vx, k
+    // Wait for a key, store its value in vx. The cpu halts here, see KeyWait
+    fn op_fx0a(&mut self) {
+        let vx: u8 = ((self.opcode & 0x0F00) >> 8u8) as u8;
+        self.key_wait = Some(KeyWait::Press { x: vx });
+    }
+
+    // Fx15 - LD DT, vx
+    // Set delay timer = vx
+    fn op_fx15(&mut self) {
+        let vx: u8 = ((self.opcode & 0x0F00) >> 8u8) as u8;
+        self.delay_timer = self.registers[vx as usize];
+    }
+
+    // Fx18 - LD ST, vx
+    // Set sound timer = vx
+    fn op_fx18(&mut self) {
+        let vx: u8 = ((self.opcode & 0x0F00) >> 8u8) as u8;
+        self.sound_timer = self.registers[vx as usize];
+    }
+
+    // Fx1E - ADD I, vx
+    // Set index = index + vx
+    fn op_fx1e(&mut self) {
+        let vx: u8 = ((self.opcode & 0x0F00) >> 8u8) as u8;
+        self.index += self.registers[vx as usize] as usize;
+    }
+
+    // Fx29 - LD F, vx
+    // Set index = location of sprite for vx
+    // Only the low nibble picks the digit, as on the VIP
+    fn op_fx29(&mut self) {
+        let vx: u8 = ((self.opcode & 0x0F00) >> 8u8) as u8;
+        let digit: u8 = self.registers[vx as usize] & 0xF;
+
+        self.index = (FONT_START_ADDRESS + (5 * digit) as u32) as usize;
+    }
+
+    // Fx33 - LD B, vx
+    // BCD OF vx
+    fn op_fx33(&mut self) -> Result<(), Error> {
+        let vx: u8 = ((self.opcode & 0x0F00) >> 8u8) as u8;
+        let mut value: u8 = self.registers[vx as usize];
+        self.check_range(self.index, 3)?;
+
+        self.memory[self.index + 2] = value % 10;
+        value /= 10;
+
+        self.memory[self.index + 1] = value % 10;
+        value /= 10;
+
+        self.memory[self.index] = value % 10;
+        self.last_write = Some((self.index, 3));
+        Ok(())
+    }
+
+    // Fx55 - LD [I], vx
+    // Store registers V0 .. vx in memory at location I
+    fn op_fx55(&mut self) -> Result<(), Error> {
+        let vx: u8 = ((self.opcode & 0x0F00) >> 8u8) as u8;
+        self.check_range(self.index, vx as usize + 1)?;
+        for i in 0..=vx {
+            self.memory[self.index + i as usize] = self.registers[i as usize];
+        }
+        self.last_write = Some((self.index, vx as usize + 1));
+        if self.quirks.memory_increment {
+            self.index = self.index + 1 + vx as usize;
+        }
+        Ok(())
+    }
+
+    // Fx65 - LD vx, [I]
+    // Load registers V0 .. vx from memory at location I
+    fn op_fx65(&mut self) -> Result<(), Error> {
+        let vx: u8 = ((self.opcode & 0x0F00) >> 8u8) as u8;
+        self.check_range(self.index, vx as usize + 1)?;
+
+        for i in 0..=vx {
+            self.registers[i as usize] = self.memory[self.index + i as usize];
+        }
+        if self.quirks.memory_increment {
+            self.index = self.index + 1 + vx as usize;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn load_program_copies_rom_and_font() {
+        let mut chip = Chip8::new();
+        chip.load_program(&[0x12, 0x34]).unwrap();
+        assert_eq!(chip.peek(0x200), 0x12);
+        assert_eq!(chip.peek(0x201), 0x34);
+        assert_eq!(chip.peek(FONT_START_ADDRESS as usize), 0xF0);
+
+        let too_large = [0u8; 4096 - 0x200 + 1];
+        assert_eq!(chip.load_program(&too_large), Err(Error::RomTooLarge(too_large.len())));
+    }
+
+    #[test]
+    fn faults_leave_pc_on_the_instruction() {
+        let mut chip = Chip8::new();
+        chip.load_program(&[0x00, 0xEE]).unwrap();
+        assert_eq!(chip.cycle(), Err(Error::StackUnderflow));
+        assert_eq!(chip.pc(), 0x200);
+
+        chip.poke(0x200, 0x51);
+        chip.poke(0x201, 0x21);
+        assert_eq!(chip.cycle(), Err(Error::UnknownOpcode(0x5121)));
+        assert_eq!(chip.pc(), 0x200);
+
+        chip.set_pc(0xFFF);
+        assert_eq!(chip.cycle(), Err(Error::MemoryOutOfBounds(0xFFF)));
+    }
+
+    #[test]
+    fn faults_on_stack_and_memory_bounds() {
+        let mut chip = Chip8::new();
+        for _ in 0..16 {
+            chip.execute_opcode(0x2300).unwrap();
+        }
+        assert_eq!(chip.execute_opcode(0x2300), Err(Error::StackOverflow));
+        assert_eq!(chip.sp(), 16);
+
+        chip.set_index(0xFFE);
+        assert_eq!(chip.execute_opcode(0xF033), Err(Error::MemoryOutOfBounds(0x1000)));
+        assert_eq!(chip.execute_opcode(0xF155), Ok(()));
+        assert_eq!(chip.index(), 0x1000);
+        assert_eq!(chip.execute_opcode(0xF255), Err(Error::MemoryOutOfBounds(0x1002)));
+        assert_eq!(chip.execute_opcode(0xF065), Err(Error::MemoryOutOfBounds(0x1000)));
+        assert_eq!(chip.execute_opcode(0xD003), Err(Error::MemoryOutOfBounds(0x1002)));
+        assert_eq!(chip.index(), 0x1000);
+    }
+
+    #[test]
+    fn sys_is_ignored_and_writes_are_reported() {
+        let mut chip = Chip8::new();
+        chip.set_pc(0x300);
+        chip.execute_opcode(0x0123).unwrap();
+        assert_eq!(chip.pc(), 0x300);
+        assert_eq!(chip.last_write(), None);
+
+        chip.set_index(0x400);
+        chip.execute_opcode(0xF233).unwrap();
+        assert_eq!(chip.last_write(), Some((0x400, 3)));
+        chip.execute_opcode(0xF255).unwrap();
+        assert_eq!(chip.last_write(), Some((0x400, 3)));
+        chip.execute_opcode(0x6000).unwrap();
+        assert_eq!(chip.last_write(), None);
+    }
+
+    #[test]
+    fn seeded_random_is_reproducible() {
+        let mut a = Chip8::with_seed(1234);
+        let mut b = Chip8::with_seed(1234);
+        for _ in 0..32 {
+            a.execute_opcode(0xC0FF).unwrap();
+            b.execute_opcode(0xC0FF).unwrap();
+            assert_eq!(a.register(0), b.register(0));
+        }
+    }
+
+    // Chip8 with registers vx and vy preloaded, vy is written first so
+    // vx wins when both nibbles name the same register
+    fn with_registers(opcode: u16, x_value: u8, y_value: u8) -> Chip8 {
+        let mut chip = Chip8::new();
+        let x = ((opcode & 0x0F00) >> 8) as usize;
+        let y = ((opcode & 0x00F0) >> 4) as usize;
+        chip.set_register(y, y_value);
+        chip.set_register(x, x_value);
+        chip
+    }
+
+    // (opcode, vx before, vy before, vx after, vf after)
+    const ALU_CASES: [(u16, u8, u8, u8, u8); 34] = [
+        (0x8010, 0x12, 0x34, 0x34, 0x00),
+        (0x8011, 0xF0, 0x0F, 0xFF, 0x00),
+        (0x8012, 0xF0, 0x3C, 0x30, 0x00),
+        (0x8013, 0xFF, 0x0F, 0xF0, 0x00),
+        (0x8014, 0x10, 0x20, 0x30, 0x00),
+        (0x8014, 0xFF, 0x01, 0x00, 0x01),
+        (0x8014, 0xFF, 0xFF, 0xFE, 0x01),
+        (0x8015, 0x30, 0x10, 0x20, 0x01),
+        (0x8015, 0x10, 0x30, 0xE0, 0x00),
+        (0x8015, 0x42, 0x42, 0x00, 0x01),
+        (0x8016, 0x00, 0x05, 0x02, 0x01),
+        (0x8016, 0x00, 0x04, 0x02, 0x00),
+        (0x8017, 0x10, 0x30, 0x20, 0x01),
+        (0x8017, 0x30, 0x10, 0xE0, 0x00),
+        (0x8017, 0x42, 0x42, 0x00, 0x01),
+        (0x801E, 0x00, 0x81, 0x02, 0x01),
+        (0x801E, 0x00, 0x41, 0x82, 0x00),
+        // x == y, the source is read before it is overwritten
+        (0x8116, 0x03, 0x03, 0x01, 0x01),
+        (0x811E, 0x81, 0x81, 0x02, 0x01),
+        (0x8114, 0x80, 0x80, 0x00, 0x01),
+        // x == F, the flag must win over the result
+        (0x8F11, 0x0F, 0xF0, 0x00, 0x00),
+        (0x8F12, 0x0F, 0xFF, 0x00, 0x00),
+        (0x8F13, 0x0F, 0xF0, 0x00, 0x00),
+        (0x8F14, 0xFF, 0x01, 0x01, 0x01),
+        (0x8F14, 0x01, 0x01, 0x00, 0x00),
+        (0x8F15, 0x05, 0x03, 0x01, 0x01),
+        (0x8F15, 0x03, 0x05, 0x00, 0x00),
+        (0x8F16, 0x00, 0x03, 0x01, 0x01),
+        (0x8F16, 0x00, 0x02, 0x00, 0x00),
+        (0x8F17, 0x03, 0x05, 0x01, 0x01),
+        (0x8F17, 0x05, 0x03, 0x00, 0x00),
+        (0x8F1E, 0x00, 0x80, 0x01, 0x01),
+        (0x8F1E, 0x00, 0x40, 0x00, 0x00),
+        // y == F, VF is an operand and is then replaced by the flag
+        (0x80F4, 0xFF, 0x01, 0x00, 0x01),
+    ];
+
+    #[test]
+    fn alu_opcodes() {
+        for &(opcode, x_value, y_value, vx, vf) in ALU_CASES.iter() {
+            let mut chip = with_registers(opcode, x_value, y_value);
+            chip.execute_opcode(opcode).unwrap();
+
+            let x = ((opcode & 0x0F00) >> 8) as usize;
+            assert_eq!(chip.register(x), vx, "vx of {:04X}", opcode);
+            assert_eq!(chip.register(0xF), vf, "vf of {:04X}", opcode);
+        }
+    }
+
+    #[test]
+    fn alu_property_all_operands() {
+        for x_value in 0..=255u8 {
+            for y_value in 0..=255u8 {
+                let (a, b) = (x_value as u16, y_value as u16);
+                let expected: [(u16, u8, u8); 6] = [
+                    (0x8124, (a + b) as u8, (a + b > 0xFF) as u8),
+                    (0x8125, x_value.wrapping_sub(y_value), (a >= b) as u8),
+                    (0x8126, y_value >> 1, y_value & 1),
+                    (0x8127, y_value.wrapping_sub(x_value), (b >= a) as u8),
+                    (0x812E, y_value << 1, y_value >> 7),
+                    (0x8123, x_value ^ y_value, 0),
+                ];
+
+                for &(opcode, vx, vf) in expected.iter() {
+                    let mut chip = with_registers(opcode, x_value, y_value);
+                    chip.set_register(0xF, 0xAA);
+                    chip.execute_opcode(opcode).unwrap();
+
+                    assert_eq!(chip.register(1), vx, "{:04X} {} {}", opcode, a, b);
+                    assert_eq!(chip.register(2), y_value, "{:04X} {} {}", opcode, a, b);
+                    assert_eq!(chip.register(0xF), vf, "{:04X} {} {}", opcode, a, b);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn add_byte_property_wraps_without_flag() {
+        for value in 0..=255u8 {
+            for byte in 0..=255u8 {
+                let mut chip = Chip8::new();
+                chip.set_register(3, value);
+                chip.set_register(0xF, 0xAA);
+                chip.execute_opcode(0x7300 | byte as u16).unwrap();
+
+                assert_eq!(chip.register(3), value.wrapping_add(byte));
+                assert_eq!(chip.register(0xF), 0xAA);
+            }
+        }
+    }
+
+    #[test]
+    fn load_byte() {
+        let mut chip = Chip8::new();
+        chip.execute_opcode(0x6A42).unwrap();
+        assert_eq!(chip.register(0xA), 0x42);
+    }
+
+    #[test]
+    fn skips() {
+        // (opcode, vx, vy, skipped)
+        let cases: [(u16, u8, u8, bool); 8] = [
+            (0x3142, 0x42, 0x00, true),
+            (0x3142, 0x41, 0x00, false),
+            (0x4142, 0x42, 0x00, false),
+            (0x4142, 0x41, 0x00, true),
+            (0x5120, 0x07, 0x07, true),
+            (0x5120, 0x07, 0x08, false),
+            (0x9120, 0x07, 0x07, false),
+            (0x9120, 0x07, 0x08, true),
+        ];
+
+        for &(opcode, x_value, y_value, skipped) in cases.iter() {
+            let mut chip = with_registers(opcode, x_value, y_value);
+            chip.set_pc(0x300);
+            chip.execute_opcode(opcode).unwrap();
+
+            let expected = if skipped { 0x302 } else { 0x300 };
+            assert_eq!(chip.pc(), expected, "{:04X}", opcode);
+        }
+    }
+
+    #[test]
+    fn jumps_calls_and_returns() {
+        let mut chip = Chip8::new();
+        chip.execute_opcode(0x1ABC).unwrap();
+        assert_eq!(chip.pc(), 0xABC);
+
+        chip.set_pc(0x300);
+        chip.execute_opcode(0x2400).unwrap();
+        assert_eq!(chip.pc(), 0x400);
+        assert_eq!(chip.sp(), 1);
+
+        chip.execute_opcode(0x2500).unwrap();
+        assert_eq!(chip.stack_frames(), &[0x300, 0x400]);
+
+        chip.execute_opcode(0x00EE).unwrap();
+        chip.execute_opcode(0x00EE).unwrap();
+        assert_eq!(chip.pc(), 0x300);
+        assert_eq!(chip.sp(), 0);
+        assert!(chip.stack_frames().is_empty());
+
+        chip.set_register(0, 0x10);
+        chip.execute_opcode(0xB300).unwrap();
+        assert_eq!(chip.pc(), 0x310);
+    }
+
+    #[test]
+    fn cycle_fetches_and_advances_pc() {
+        let mut chip = Chip8::new();
+        chip.poke(0x200, 0x61);
+        chip.poke(0x201, 0x99);
+        chip.set_delay_timer(2);
+        chip.cycle().unwrap();
+
+        assert_eq!(chip.register(1), 0x99);
+        assert_eq!(chip.pc(), 0x202);
+        assert_eq!(chip.opcode(), 0x6199);
+        assert_eq!(chip.delay_timer(), 1);
+    }
+
+    #[test]
+    fn index_opcodes() {
+        let mut chip = Chip8::new();
+        chip.execute_opcode(0xA123).unwrap();
+        assert_eq!(chip.index(), 0x123);
+
+        chip.set_register(4, 0x10);
+        chip.execute_opcode(0xF41E).unwrap();
+        assert_eq!(chip.index(), 0x133);
+
+        chip.set_register(4, 0xA);
+        chip.execute_opcode(0xF429).unwrap();
+        assert_eq!(chip.index(), FONT_START_ADDRESS as usize + 50);
+    }
+
+    #[test]
+    fn random_is_masked() {
+        let mut chip = Chip8::new();
+        for _ in 0..64 {
+            chip.execute_opcode(0xC50F).unwrap();
+            assert_eq!(chip.register(5) & 0xF0, 0);
+        }
+        chip.execute_opcode(0xC500).unwrap();
+        assert_eq!(chip.register(5), 0);
+    }
+
+    #[test]
+    fn clear_and_draw() {
+        let mut chip = Chip8::new();
+        chip.poke(0x300, 0xF0);
+        chip.poke(0x301, 0x90);
+        chip.set_index(0x300);
+
+        chip.execute_opcode(0xD012).unwrap();
+        assert_eq!(chip.framebuffer()[0][..5], [1, 1, 1, 1, 0]);
+        assert_eq!(chip.framebuffer()[1][..5], [1, 0, 0, 1, 0]);
+        assert_eq!(chip.register(0xF), 0);
+        assert!(chip.take_vram_change());
+
+        // Drawing the same sprite again erases it and reports a collision
+        chip.execute_opcode(0xD012).unwrap();
+        assert!(chip.framebuffer().iter().flatten().all(|&p| p == 0));
+        assert_eq!(chip.register(0xF), 1);
+
+        assert!(chip.take_vram_change());
+        assert!(!chip.take_vram_change());
+        chip.execute_opcode(0x00E0).unwrap();
+        assert!(chip.framebuffer().iter().flatten().all(|&p| p == 0));
+        assert!(chip.take_vram_change());
+    }
+
+    #[test]
+    fn draw_wraps_around_screen_edges() {
+        let mut chip = Chip8::new();
+        chip.poke(0x300, 0xFF);
+        chip.poke(0x301, 0xFF);
+        chip.set_index(0x300);
+        chip.set_register(0, 62);
+        chip.set_register(1, 255);
+
+        chip.execute_opcode(0xD012).unwrap();
+        assert_eq!(chip.framebuffer()[31][62], 1);
+        assert_eq!(chip.framebuffer()[31][63], 1);
+        assert_eq!(chip.framebuffer()[31][0], 1);
+        assert_eq!(chip.framebuffer()[0][5], 1);
+        assert_eq!(chip.framebuffer()[31][6], 0);
+    }
+
+    #[test]
+    fn key_skips() {
+        let mut chip = Chip8::new();
+        chip.set_register(2, 0xB);
+        chip.set_pc(0x300);
+
+        chip.execute_opcode(0xE29E).unwrap();
+        assert_eq!(chip.pc(), 0x300);
+        chip.execute_opcode(0xE2A1).unwrap();
+        assert_eq!(chip.pc(), 0x302);
+
+        chip.set_key(0xB, true);
+        chip.execute_opcode(0xE29E).unwrap();
+        assert_eq!(chip.pc(), 0x304);
+        chip.execute_opcode(0xE2A1).unwrap();
+        assert_eq!(chip.pc(), 0x304);
+    }
+
+    #[test]
+    fn wait_for_key_press_and_release() {
+        // LD V3, K; LD V4, 1
+        let mut chip = Chip8::new();
+        chip.load_program(&[0xF3, 0x0A, 0x64, 0x01]).unwrap();
+        chip.set_delay_timer(10);
+
+        // Halted past the Fx0A, timers still running
+        chip.cycle().unwrap();
+        assert_eq!(chip.key_wait(), Some(KeyWait::Press { x: 3 }));
+        for _ in 0..3 {
+            chip.cycle().unwrap();
+        }
+        assert_eq!((chip.pc(), chip.delay_timer()), (0x202, 6));
+
+        chip.set_key(5, true);
+        chip.set_key(9, true);
+        chip.cycle().unwrap();
+        assert_eq!(chip.key_wait(), Some(KeyWait::Release { x: 3, key: 9 }));
+        chip.set_key(9, false);
+        chip.cycle().unwrap();
+        assert_eq!((chip.key_wait(), chip.register(3), chip.register(4)), (None, 9, 0));
+        chip.cycle().unwrap();
+        assert_eq!(chip.register(4), 1);
+
+        // SCHIP goes on as soon as the key is down
+        let mut chip = Chip8::new();
+        chip.set_quirks(Quirks::SCHIP);
+        chip.load_program(&[0xF3, 0x0A]).unwrap();
+        chip.cycle().unwrap();
+        chip.set_key(5, true);
+        chip.cycle().unwrap();
+        assert_eq!((chip.key_wait(), chip.register(3)), (None, 5));
+    }
+
+    #[test]
+    fn timers() {
+        let mut chip = Chip8::new();
+        chip.set_register(6, 30);
+        chip.execute_opcode(0xF615).unwrap();
+        chip.execute_opcode(0xF618).unwrap();
+        assert_eq!(chip.delay_timer(), 30);
+        assert_eq!(chip.sound_timer(), 30);
+
+        chip.timer_tick();
+        chip.execute_opcode(0xF707).unwrap();
+        assert_eq!(chip.register(7), 29);
+        assert_eq!(chip.sound_timer(), 29);
+    }
+
+    #[test]
+    fn bcd() {
+        for (value, digits) in [(254u8, [2, 5, 4]), (7, [0, 0, 7]), (90, [0, 9, 0])] {
+            let mut chip = Chip8::new();
+            chip.set_register(0xF, value);
+            chip.set_index(0x400);
+            chip.execute_opcode(0xFF33).unwrap();
+
+            assert_eq!([chip.peek(0x400), chip.peek(0x401), chip.peek(0x402)], digits);
+            assert_eq!(chip.index(), 0x400);
+        }
+    }
+
+    #[test]
+    fn store_and_load_registers() {
+        let mut chip = Chip8::new();
+        for x in 0..16 {
+            chip.set_register(x, 0x10 + x as u8);
+        }
+        chip.set_index(0x400);
+        chip.execute_opcode(0xF355).unwrap();
+
+        assert_eq!(chip.peek(0x400), 0x10);
+        assert_eq!(chip.peek(0x403), 0x13);
+        assert_eq!(chip.peek(0x404), 0x00);
+        assert_eq!(chip.index(), 0x404);
+
+        // x == F stores and reloads VF as well
+        chip.set_index(0x500);
+        chip.execute_opcode(0xFF55).unwrap();
+        assert_eq!(chip.peek(0x50F), 0x1F);
+        assert_eq!(chip.index(), 0x510);
+
+        let mut other = Chip8::new();
+        other.poke(0x500, 0xAB);
+        other.poke(0x50F, 0xCD);
+        other.set_index(0x500);
+        other.execute_opcode(0xFF65).unwrap();
+        assert_eq!(other.register(0), 0xAB);
+        assert_eq!(other.register(0xF), 0xCD);
+        assert_eq!(other.index(), 0x510);
+    }
+
+    #[test]
+    fn quirk_profiles() {
+        let mut chip = Chip8::new();
+        chip.set_quirks(Quirks::SCHIP);
+        chip.set_register(0xF, 1);
+        chip.set_register(1, 0b101);
+        chip.set_register(2, 0b110);
+        chip.execute_opcode(0x8121).unwrap();
+        assert_eq!(chip.register(0xF), 1);
+
+        // Shifts work on vx in place
+        chip.execute_opcode(0x8126).unwrap();
+        assert_eq!((chip.register(1), chip.register(0xF)), (0b11, 1));
+        chip.execute_opcode(0x812E).unwrap();
+        assert_eq!((chip.register(1), chip.register(0xF)), (0b110, 0));
+
+        // I stays put after a store
+        chip.set_index(0x400);
+        chip.execute_opcode(0xF155).unwrap();
+        assert_eq!(chip.index(), 0x400);
+
+        // B2nn jumps with V2
+        chip.execute_opcode(0xB210).unwrap();
+        assert_eq!(chip.pc(), 0x216);
+
+        // Sprites stop at the right and bottom edges
+        chip.poke(0x300, 0xFF);
+        chip.poke(0x301, 0xFF);
+        chip.set_index(0x300);
+        chip.set_register(3, 62);
+        chip.set_register(4, 31);
+        chip.execute_opcode(0xD342).unwrap();
+        assert_eq!(chip.framebuffer()[31][63], 1);
+        assert_eq!(chip.framebuffer()[31][0], 0);
+        assert_eq!(chip.framebuffer()[0][62], 0);
+    }
+
+    #[test]
+    fn font_digit_uses_the_low_nibble() {
+        let mut chip = Chip8::new();
+        chip.set_register(0, 0xFA);
+        chip.execute_opcode(0xF029).unwrap();
+        assert_eq!(chip.index(), FONT_START_ADDRESS as usize + 5 * 0xA);
+    }
+
+    #[test]
+    fn vip_timing() {
+        // LD V0, 5; LD DT, V0; ADD V1, 1; JP 0x204
+        let mut chip = Chip8::new();
+        chip.load_program(&[0x60, 0x05, 0xF0, 0x15, 0x71, 0x01, 0x12, 0x04]).unwrap();
+        chip.set_timing(Timing::Vip);
+        let mut count = 0;
+        while !chip.take_vblank() {
+            chip.cycle().unwrap();
+            count += 1;
+        }
+        // Timers only move in the interrupt, and the loop runs about as
+        // often as it would on a VIP
+        assert_eq!(chip.delay_timer(), 4);
+        assert!((30..40).contains(&count), "{} instructions in a frame", count);
+
+        // One sprite a frame at most, each waits for the interrupt
+        let mut chip = Chip8::new();
+        chip.load_program(&[0xD0, 0x01, 0x12, 0x00]).unwrap();
+        chip.set_timing(Timing::Vip);
+        for _ in 0..3 {
+            chip.cycle().unwrap();
+            assert!(chip.take_vblank());
+            chip.cycle().unwrap();
+            assert!(!chip.take_vblank());
+        }
+    }
+
+    #[test]
+    fn soft_reset_keeps_memory() {
+        // LD V0, 7; CALL 0x300; 0x300: LD I, 0x400; LD [I], V0; LD V3, K
+        let mut chip = Chip8::new();
+        chip.load_program(&[0x60, 0x07, 0x23, 0x00]).unwrap();
+        chip.poke(0x300, 0xA4);
+        chip.poke(0x302, 0xF0);
+        chip.poke(0x303, 0x55);
+        chip.poke(0x304, 0xF3);
+        chip.poke(0x305, 0x0A);
+        for _ in 0..5 {
+            chip.cycle().unwrap();
+        }
+        chip.execute_opcode(0xD005).unwrap();
+        assert!(chip.key_wait().is_some());
+
+        chip.soft_reset();
+        assert_eq!((chip.pc(), chip.index(), chip.sp(), chip.register(0)), (0x200, 0, 0, 0));
+        assert_eq!(chip.key_wait(), None);
+        assert!(chip.framebuffer().iter().flatten().all(|&p| p == 0));
+        assert_eq!((chip.peek(0x200), chip.peek(0x400)), (0x60, 7));
+    }
+}
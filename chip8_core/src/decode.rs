@@ -0,0 +1,179 @@
+use alloc::vec::Vec;
+use core::fmt;
+
+// Decoded form of a single opcode, register operands are 0x0..=0xF
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    Cls,                  // 00E0
+    Ret,                  // 00EE
+    Sys(u16),             // 0nnn
+    Jp(u16),              // 1nnn
+    Call(u16),            // 2nnn
+    SeByte(u8, u8),       // 3xkk
+    SneByte(u8, u8),      // 4xkk
+    SeReg(u8, u8),        // 5xy0
+    LdByte(u8, u8),       // 6xkk
+    AddByte(u8, u8),      // 7xkk
+    LdReg(u8, u8),        // 8xy0
+    Or(u8, u8),           // 8xy1
+    And(u8, u8),          // 8xy2
+    Xor(u8, u8),          // 8xy3
+    AddReg(u8, u8),       // 8xy4
+    Sub(u8, u8),          // 8xy5
+    Shr(u8, u8),          // 8xy6
+    Subn(u8, u8),         // 8xy7
+    Shl(u8, u8),          // 8xyE
+    SneReg(u8, u8),       // 9xy0
+    LdI(u16),             // Annn
+    JpV0(u16),            // Bnnn
+    Rnd(u8, u8),          // Cxkk
+    Drw(u8, u8, u8),      // Dxyn
+    Skp(u8),              // Ex9E
+    Sknp(u8),             // ExA1
+    LdVxDt(u8),           // Fx07
+    LdVxK(u8),            // Fx0A
+    LdDtVx(u8),           // Fx15
+    LdStVx(u8),           // Fx18
+    AddIVx(u8),           // Fx1E
+    LdFVx(u8),            // Fx29
+    LdBVx(u8),            // Fx33
+    LdIVx(u8),            // Fx55
+    LdVxI(u8),            // Fx65
+    Unknown(u16),
+}
+
+// Same nibble table as Chip8::execute
+pub fn decode(opcode: u16) -> Instruction {
+    let nibbles = (
+        ((opcode & 0xF000) >> 12) as u8,
+        ((opcode & 0x0F00) >> 8) as u8,
+        ((opcode & 0x00F0) >> 4) as u8,
+        (opcode & 0x000F) as u8,
+    );
+    let nnn = opcode & 0x0FFF;
+    let kk = (opcode & 0x00FF) as u8;
+
+    match nibbles {
+        (0x00, 0x00, 0x0e, 0x00) => Instruction::Cls,
+        (0x00, 0x00, 0x0e, 0x0e) => Instruction::Ret,
+        (0x00, _, _, _) => Instruction::Sys(nnn),
+        (0x01, _, _, _) => Instruction::Jp(nnn),
+        (0x02, _, _, _) => Instruction::Call(nnn),
+        (0x03, x, _, _) => Instruction::SeByte(x, kk),
+        (0x04, x, _, _) => Instruction::SneByte(x, kk),
+        (0x05, x, y, 0x00) => Instruction::SeReg(x, y),
+        (0x06, x, _, _) => Instruction::LdByte(x, kk),
+        (0x07, x, _, _) => Instruction::AddByte(x, kk),
+        (0x08, x, y, 0x00) => Instruction::LdReg(x, y),
+        (0x08, x, y, 0x01) => Instruction::Or(x, y),
+        (0x08, x, y, 0x02) => Instruction::And(x, y),
+        (0x08, x, y, 0x03) => Instruction::Xor(x, y),
+        (0x08, x, y, 0x04) => Instruction::AddReg(x, y),
+        (0x08, x, y, 0x05) => Instruction::Sub(x, y),
+        (0x08, x, y, 0x06) => Instruction::Shr(x, y),
+        (0x08, x, y, 0x07) => Instruction::Subn(x, y),
+        (0x08, x, y, 0x0e) => Instruction::Shl(x, y),
+        (0x09, x, y, 0x00) => Instruction::SneReg(x, y),
+        (0x0a, _, _, _) => Instruction::LdI(nnn),
+        (0x0b, _, _, _) => Instruction::JpV0(nnn),
+        (0x0c, x, _, _) => Instruction::Rnd(x, kk),
+        (0x0d, x, y, n) => Instruction::Drw(x, y, n),
+        (0x0e, x, 0x09, 0x0e) => Instruction::Skp(x),
+        (0x0e, x, 0x0a, 0x01) => Instruction::Sknp(x),
+        (0x0f, x, 0x00, 0x07) => Instruction::LdVxDt(x),
+        (0x0f, x, 0x00, 0x0a) => Instruction::LdVxK(x),
+        (0x0f, x, 0x01, 0x05) => Instruction::LdDtVx(x),
+        (0x0f, x, 0x01, 0x08) => Instruction::LdStVx(x),
+        (0x0f, x, 0x01, 0x0e) => Instruction::AddIVx(x),
+        (0x0f, x, 0x02, 0x09) => Instruction::LdFVx(x),
+        (0x0f, x, 0x03, 0x03) => Instruction::LdBVx(x),
+        (0x0f, x, 0x05, 0x05) => Instruction::LdIVx(x),
+        (0x0f, x, 0x06, 0x05) => Instruction::LdVxI(x),
+        _ => Instruction::Unknown(opcode),
+    }
+}
+
+// Decode every opcode in memory[start..end], stepping two bytes at a time
+pub fn disassemble(memory: &[u8], start: usize, end: usize) -> Vec<(usize, u16, Instruction)> {
+    let end = end.min(memory.len());
+    let mut lines = Vec::new();
+    let mut address = start;
+    while address + 1 < end {
+        let opcode = ((memory[address] as u16) << 8) | memory[address + 1] as u16;
+        lines.push((address, opcode, decode(opcode)));
+        address += 2;
+    }
+    lines
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use Instruction::*;
+        match *self {
+            Cls => write!(f, "CLS"),
+            Ret => write!(f, "RET"),
+            Sys(nnn) => write!(f, "SYS {:#05X}", nnn),
+            Jp(nnn) => write!(f, "JP {:#05X}", nnn),
+            Call(nnn) => write!(f, "CALL {:#05X}", nnn),
+            SeByte(x, kk) => write!(f, "SE V{:X}, {:#04X}", x, kk),
+            SneByte(x, kk) => write!(f, "SNE V{:X}, {:#04X}", x, kk),
+            SeReg(x, y) => write!(f, "SE V{:X}, V{:X}", x, y),
+            LdByte(x, kk) => write!(f, "LD V{:X}, {:#04X}", x, kk),
+            AddByte(x, kk) => write!(f, "ADD V{:X}, {:#04X}", x, kk),
+            LdReg(x, y) => write!(f, "LD V{:X}, V{:X}", x, y),
+            Or(x, y) => write!(f, "OR V{:X}, V{:X}", x, y),
+            And(x, y) => write!(f, "AND V{:X}, V{:X}", x, y),
+            Xor(x, y) => write!(f, "XOR V{:X}, V{:X}", x, y),
+            AddReg(x, y) => write!(f, "ADD V{:X}, V{:X}", x, y),
+            Sub(x, y) => write!(f, "SUB V{:X}, V{:X}", x, y),
+            Shr(x, y) => write!(f, "SHR V{:X}, V{:X}", x, y),
+            Subn(x, y) => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            Shl(x, y) => write!(f, "SHL V{:X}, V{:X}", x, y),
+            SneReg(x, y) => write!(f, "SNE V{:X}, V{:X}", x, y),
+            LdI(nnn) => write!(f, "LD I, {:#05X}", nnn),
+            JpV0(nnn) => write!(f, "JP V0, {:#05X}", nnn),
+            Rnd(x, kk) => write!(f, "RND V{:X}, {:#04X}", x, kk),
+            Drw(x, y, n) => write!(f, "DRW V{:X}, V{:X}, {}", x, y, n),
+            Skp(x) => write!(f, "SKP V{:X}", x),
+            Sknp(x) => write!(f, "SKNP V{:X}", x),
+            LdVxDt(x) => write!(f, "LD V{:X}, DT", x),
+            LdVxK(x) => write!(f, "LD V{:X}, K", x),
+            LdDtVx(x) => write!(f, "LD DT, V{:X}", x),
+            LdStVx(x) => write!(f, "LD ST, V{:X}", x),
+            AddIVx(x) => write!(f, "ADD I, V{:X}", x),
+            LdFVx(x) => write!(f, "LD F, V{:X}", x),
+            LdBVx(x) => write!(f, "LD B, V{:X}", x),
+            LdIVx(x) => write!(f, "LD [I], V{:X}", x),
+            LdVxI(x) => write!(f, "LD V{:X}, [I]", x),
+            Unknown(opcode) => write!(f, "DW {:#06X}", opcode),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+
+    #[test]
+    fn decodes_and_formats() {
+        let cases = [
+            (0x00E0, "CLS"),
+            (0x2ABC, "CALL 0xABC"),
+            (0x8AB4, "ADD VA, VB"),
+            (0x6F0A, "LD VF, 0x0A"),
+            (0xD125, "DRW V1, V2, 5"),
+            (0xF355, "LD [I], V3"),
+            (0x5121, "DW 0x5121"),
+        ];
+        for (opcode, text) in cases {
+            assert_eq!(decode(opcode).to_string(), text);
+        }
+    }
+
+    #[test]
+    fn disassembles_pairs() {
+        let lines = disassemble(&[0x00, 0xE0, 0x12, 0x00, 0xFF], 0, 5);
+        assert_eq!(lines, [(0, 0x00E0, Instruction::Cls), (2, 0x1200, Instruction::Jp(0x200))]);
+    }
+}
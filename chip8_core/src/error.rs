@@ -0,0 +1,33 @@
+use core::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    // Rom image does not fit between START_ADDRESS and the end of memory
+    RomTooLarge(usize),
+    // Save state has the wrong magic, version or length
+    BadState,
+    // Opcode that matches no instruction
+    UnknownOpcode(u16),
+    // 2nnn with all 16 stack frames in use
+    StackOverflow,
+    // 00EE with an empty stack
+    StackUnderflow,
+    // Fetch or memory access past the end of memory, holds the address
+    MemoryOutOfBounds(usize),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::RomTooLarge(size) => write!(f, "rom of {} bytes does not fit in memory", size),
+            Error::BadState => write!(f, "invalid save state"),
+            Error::UnknownOpcode(opcode) => write!(f, "unknown opcode {:04X}", opcode),
+            Error::StackOverflow => write!(f, "stack overflow"),
+            Error::StackUnderflow => write!(f, "return with empty stack"),
+            Error::MemoryOutOfBounds(address) => write!(f, "memory access out of bounds at {:#X}", address),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
@@ -0,0 +1,79 @@
+use crate::chip8::Chip8;
+use crate::error::Error;
+use crate::quirks::Quirks;
+use alloc::vec::Vec;
+
+// Instructions each rom gets under each profile
+pub const FUZZ_CYCLES: usize = 2000;
+const CYCLES_PER_FRAME: usize = 10;
+
+// Arbitrary bytes as a rom under every quirk profile, shared by the
+// cargo fuzz target in fuzz/ and the regression tests. Anything the rom
+// does wrong has to come back as an Error, a panic is a bug
+pub fn run(data: &[u8]) {
+    for (_, quirks) in Quirks::PROFILES {
+        let _ = run_with(data, quirks, FUZZ_CYCLES);
+    }
+}
+
+// Cycles run before the first fault
+pub fn run_with(data: &[u8], quirks: Quirks, cycles: usize) -> Result<usize, Error> {
+    let seed = data.iter().take(4).fold(0u32, |seed, &b| (seed << 8) | b as u32);
+    let mut chip = Chip8::with_seed(seed);
+    chip.set_quirks(quirks);
+    chip.load_program(data)?;
+
+    for cycle in 0..cycles {
+        // Press and release keys from the rom bytes so key waits and skips
+        // see some input
+        if let Some(&byte) = data.get(cycle % data.len().max(1)) {
+            chip.set_key((byte & 0xF) as usize, byte & 0x10 != 0);
+        }
+        chip.cycle()?;
+        assert!(chip.sp() <= 16, "stack pointer {} past the stack", chip.sp());
+        if cycle % CYCLES_PER_FRAME == CYCLES_PER_FRAME - 1 {
+            chip.timer_tick();
+        }
+    }
+    Ok(cycles)
+}
+
+// Seeded xorshift64 stream for the sweeps in the tests, the same seed
+// gives the same programs
+pub fn xorshift(seed: u64) -> impl FnMut() -> u64 {
+    // Zero would stay zero
+    let mut state = seed.max(1);
+    move || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state
+    }
+}
+
+// Random bytes mostly stop at the first unknown opcode, so this builds
+// programs from valid instructions only to get deep into each one, for
+// the regression and differential tests
+pub fn random_program(next: &mut impl FnMut() -> u64) -> Vec<u8> {
+    const ALU: [u16; 9] = [0x0, 0x1, 0x2, 0x3, 0x4, 0x5, 0x6, 0x7, 0xE];
+    const KEYS: [u16; 2] = [0x9E, 0xA1];
+    const MISC: [u16; 9] = [0x07, 0x0A, 0x15, 0x18, 0x1E, 0x29, 0x33, 0x55, 0x65];
+
+    let len = (next() % 64) as usize + 1;
+    let mut program = Vec::with_capacity(len * 2);
+    for _ in 0..len {
+        let r = next();
+        let pick = (r >> 20) as usize;
+        let opcode = r as u16;
+        let opcode = match opcode >> 12 {
+            0x0 => [0x00E0, 0x00EE][pick % 2],
+            0x5 | 0x9 => opcode & 0xFFF0,
+            0x8 => (opcode & 0xFFF0) | ALU[pick % ALU.len()],
+            0xE => (opcode & 0xFF00) | KEYS[pick % KEYS.len()],
+            0xF => (opcode & 0xFF00) | MISC[pick % MISC.len()],
+            _ => opcode,
+        };
+        program.extend_from_slice(&opcode.to_be_bytes());
+    }
+    program
+}
@@ -0,0 +1,52 @@
+use crate::chip8::Chip8;
+
+// Observer for Chip8::cycle_with, both calls see the whole machine
+// A faulting instruction gets before() but no after()
+pub trait Hook {
+    // Opcode fetched from pc, nothing executed yet
+    fn before(&mut self, _chip: &Chip8, _pc: usize, _opcode: u16) {}
+
+    // Instruction at pc ran without a fault, timers not ticked yet
+    fn after(&mut self, _chip: &Chip8, _pc: usize, _opcode: u16) {}
+}
+
+// No hook, what Chip8::cycle uses
+impl Hook for () {}
+
+// Optional hooks, a profiler that may or may not be switched on
+impl<H: Hook> Hook for Option<H> {
+    fn before(&mut self, chip: &Chip8, pc: usize, opcode: u16) {
+        if let Some(hook) = self {
+            hook.before(chip, pc, opcode);
+        }
+    }
+
+    fn after(&mut self, chip: &Chip8, pc: usize, opcode: u16) {
+        if let Some(hook) = self {
+            hook.after(chip, pc, opcode);
+        }
+    }
+}
+
+impl<H: Hook + ?Sized> Hook for &mut H {
+    fn before(&mut self, chip: &Chip8, pc: usize, opcode: u16) {
+        (**self).before(chip, pc, opcode);
+    }
+
+    fn after(&mut self, chip: &Chip8, pc: usize, opcode: u16) {
+        (**self).after(chip, pc, opcode);
+    }
+}
+
+// Two hooks on one cycle, first one first
+impl<A: Hook, B: Hook> Hook for (A, B) {
+    fn before(&mut self, chip: &Chip8, pc: usize, opcode: u16) {
+        self.0.before(chip, pc, opcode);
+        self.1.before(chip, pc, opcode);
+    }
+
+    fn after(&mut self, chip: &Chip8, pc: usize, opcode: u16) {
+        self.0.after(chip, pc, opcode);
+        self.1.after(chip, pc, opcode);
+    }
+}
@@ -0,0 +1,25 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+extern crate alloc;
+
+pub mod chip8;
+pub mod configs;
+pub mod decode;
+pub mod error;
+#[cfg(any(test, feature = "fuzzing"))]
+pub mod fuzz;
+pub mod hook;
+pub mod profile;
+pub mod quirks;
+pub mod reference;
+pub mod rewind;
+pub mod sanitize;
+pub mod state;
+pub mod timing;
+pub mod trace;
+
+pub use chip8::{Chip8, KeyWait};
+pub use decode::Instruction;
+pub use error::Error;
+pub use hook::Hook;
+pub use quirks::Quirks;
+pub use timing::Timing;
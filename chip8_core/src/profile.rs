@@ -0,0 +1,238 @@
+use crate::chip8::Chip8;
+use crate::decode::{decode, Instruction};
+use crate::hook::Hook;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::Write;
+
+// Calls into one subroutine
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Subroutine {
+    pub calls: u64,
+    // Instructions run from the CALL up to and including the RET
+    pub inclusive: u64,
+}
+
+// Execution counts per address and opcode class plus a call graph, fed
+// through Chip8::cycle_with
+pub struct Profiler {
+    cycles: u64,
+    counts: Vec<u64>,
+    classes: [u64; 16],
+    subroutines: BTreeMap<u16, Subroutine>,
+    // (subroutine, cycles when it was called), innermost last
+    stack: Vec<(u16, u64)>,
+    // Instructions run with exactly this call stack
+    folded: BTreeMap<Vec<u16>, u64>,
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Hook for Profiler {
+    fn after(&mut self, chip: &Chip8, pc: usize, opcode: u16) {
+        self.cycles += 1;
+        self.counts[pc] += 1;
+        self.classes[(opcode >> 12) as usize] += 1;
+
+        let path: Vec<u16> = self.stack.iter().map(|&(address, _)| address).collect();
+        *self.folded.entry(path).or_insert(0) += 1;
+
+        match decode(opcode) {
+            Instruction::Call(address) => {
+                self.subroutines.entry(address).or_default().calls += 1;
+                self.stack.push((address, self.cycles));
+            }
+            Instruction::Ret => {
+                // A rewind or loaded state can return from a call we never saw
+                if let Some((address, start)) = self.stack.pop() {
+                    let sub = self.subroutines.entry(address).or_default();
+                    sub.inclusive += self.cycles - start + 1;
+                }
+            }
+            _ => (),
+        }
+        // Keep the shadow stack in step with the machine after a restore
+        self.stack.truncate(chip.sp());
+    }
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self {
+            cycles: 0,
+            counts: vec![0; 4096],
+            classes: [0; 16],
+            subroutines: BTreeMap::new(),
+            stack: Vec::new(),
+            folded: BTreeMap::new(),
+        }
+    }
+
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    pub fn count(&self, address: usize) -> u64 {
+        self.counts.get(address).copied().unwrap_or(0)
+    }
+
+    // Executions per opcode high nibble
+    pub fn classes(&self) -> &[u64; 16] {
+        &self.classes
+    }
+
+    // Calls still running count up to now
+    pub fn subroutine(&self, address: u16) -> Option<Subroutine> {
+        let mut sub = *self.subroutines.get(&address)?;
+        for &(open, start) in self.stack.iter() {
+            if open == address {
+                sub.inclusive += self.cycles - start + 1;
+            }
+        }
+        Some(sub)
+    }
+
+    // One "main;sub_2A0;sub_300 count" line per call stack, the format
+    // flamegraph.pl and inferno read
+    pub fn folded(&self) -> String {
+        let mut out = String::new();
+        for (path, count) in self.folded.iter() {
+            out.push_str("main");
+            for address in path {
+                let _ = write!(out, ";sub_{:03X}", address);
+            }
+            let _ = writeln!(out, " {}", count);
+        }
+        out
+    }
+
+    // Summary tables then every executed instruction with its count,
+    // disassembled from memory as it is now
+    pub fn report(&self, chip: &Chip8) -> String {
+        let mut out = String::new();
+        let executed = self.counts.iter().filter(|&&c| c > 0).count();
+        let _ = writeln!(out, "{} instructions, {} distinct addresses", self.cycles, executed);
+
+        let _ = writeln!(out, "\n{:<4} {:>11} {:>6}", "op", "count", "%");
+        for (class, &count) in self.classes.iter().enumerate() {
+            if count > 0 {
+                let _ = writeln!(out, "{:X}xxx {:>11} {:>6.2}", class, count, percent(count, self.cycles));
+            }
+        }
+
+        let mut subs: Vec<(u16, Subroutine)> = self
+            .subroutines
+            .keys()
+            .filter_map(|&address| Some((address, self.subroutine(address)?)))
+            .collect();
+        subs.sort_by(|a, b| b.1.inclusive.cmp(&a.1.inclusive).then(a.0.cmp(&b.0)));
+        if !subs.is_empty() {
+            let _ = writeln!(out, "\n{:<7} {:>10} {:>11} {:>6}", "sub", "calls", "inclusive", "%");
+            for (address, sub) in subs.iter() {
+                let _ = writeln!(
+                    out,
+                    "sub_{:03X} {:>10} {:>11} {:>6.2}",
+                    address,
+                    sub.calls,
+                    sub.inclusive,
+                    percent(sub.inclusive, self.cycles)
+                );
+            }
+        }
+
+        let _ = writeln!(out, "\n{:>10} {:<4} {:<5} instruction", "count", "addr", "op");
+        let mut last = None;
+        for (address, &count) in self.counts.iter().enumerate().filter(|(_, &c)| c > 0) {
+            // Gap in the executed code
+            if last.is_some_and(|l| address > l + 2) {
+                out.push('\n');
+            }
+            if let Some(sub) = self.subroutines.get(&(address as u16)) {
+                let _ = writeln!(out, "sub_{:03X}: {} calls", address, sub.calls);
+            }
+            let opcode = match (chip.memory().get(address), chip.memory().get(address + 1)) {
+                (Some(&high), Some(&low)) => ((high as u16) << 8) | low as u16,
+                _ => 0,
+            };
+            let _ = writeln!(out, "{:>10} {:03X}  {:04X}  {}", count, address, opcode, decode(opcode));
+            last = Some(address);
+        }
+        out
+    }
+}
+
+fn percent(part: u64, whole: u64) -> f64 {
+    if whole == 0 {
+        0.0
+    } else {
+        part as f64 * 100.0 / whole as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 200 CALL 208; 202 CALL 208; 204 JP 204; 208 LD V0, 1; 20A RET
+    const PROGRAM: [u8; 12] = [0x22, 0x08, 0x22, 0x08, 0x12, 0x04, 0x00, 0x00, 0x60, 0x01, 0x00, 0xEE];
+
+    fn profiled(cycles: usize) -> (Chip8, Profiler) {
+        let mut chip = Chip8::new();
+        chip.load_program(&PROGRAM).unwrap();
+        let mut profiler = Profiler::new();
+        for _ in 0..cycles {
+            chip.cycle_with(&mut profiler).unwrap();
+        }
+        (chip, profiler)
+    }
+
+    #[test]
+    fn counts_and_calls() {
+        let (_, profiler) = profiled(9);
+        assert_eq!(profiler.cycles(), 9);
+        assert_eq!(profiler.count(0x204), 3);
+        assert_eq!(profiler.count(0x208), 2);
+        assert_eq!(profiler.classes()[0x2], 2);
+        assert_eq!(profiler.classes()[0x1], 3);
+        // CALL, LD, RET twice
+        assert_eq!(profiler.subroutine(0x208), Some(Subroutine { calls: 2, inclusive: 6 }));
+        assert_eq!(profiler.subroutine(0x204), None);
+    }
+
+    #[test]
+    fn open_calls_count_to_now() {
+        let (_, profiler) = profiled(2);
+        assert_eq!(profiler.subroutine(0x208), Some(Subroutine { calls: 1, inclusive: 2 }));
+    }
+
+    #[test]
+    fn folded_stacks() {
+        let (_, profiler) = profiled(9);
+        assert_eq!(profiler.folded(), "main 5\nmain;sub_208 4\n");
+    }
+
+    #[test]
+    fn annotated_report() {
+        let (chip, profiler) = profiled(9);
+        let report = profiler.report(&chip);
+        assert!(report.starts_with("9 instructions, 5 distinct addresses"));
+        assert!(report.contains("\nsub_208          2           6  66.67\n"));
+        assert!(report.contains("         3 204  1204  JP 0x204\n\nsub_208: 2 calls\n         2 208  6001  LD V0, 0x01\n"));
+    }
+
+    #[test]
+    fn follows_restored_stack() {
+        let (_, mut profiler) = profiled(1);
+        assert_eq!(profiler.stack.len(), 1);
+        // Rewound to a state from before the call
+        let restored = Chip8::new();
+        profiler.after(&restored, 0x208, 0x6001);
+        assert!(profiler.stack.is_empty());
+    }
+}
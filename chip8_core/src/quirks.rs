@@ -0,0 +1,89 @@
+// Behaviours that differ between CHIP-8 interpreters, see
+// https://github.com/Timendus/chip8-test-suite#quirks-test
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quirks {
+    // 8xy1, 8xy2 and 8xy3 clear VF
+    pub vf_reset: bool,
+    // 8xy6 and 8xyE shift vy into vx rather than shifting vx in place
+    pub shift_vy: bool,
+    // Fx55 and Fx65 leave I one past the last register
+    pub memory_increment: bool,
+    // Bnnn jumps to xnn + Vx rather than nnn + V0
+    pub jump_vx: bool,
+    // Sprites are cut off at the screen edges rather than wrapping
+    pub clip: bool,
+    // Fx0A goes on once the key is released rather than when it's pressed
+    pub key_release: bool,
+}
+
+impl Quirks {
+    // What this interpreter has always done, the VIP but with wrapping
+    pub const CHIP8: Quirks = Quirks {
+        vf_reset: true,
+        shift_vy: true,
+        memory_increment: true,
+        jump_vx: false,
+        clip: false,
+        key_release: true,
+    };
+
+    // The original COSMAC VIP interpreter
+    pub const VIP: Quirks = Quirks { clip: true, ..Quirks::CHIP8 };
+
+    // SUPER-CHIP 1.1 on the HP48
+    pub const SCHIP: Quirks = Quirks {
+        vf_reset: false,
+        shift_vy: false,
+        memory_increment: false,
+        jump_vx: true,
+        clip: true,
+        key_release: false,
+    };
+
+    pub const XO_CHIP: Quirks = Quirks {
+        vf_reset: false,
+        shift_vy: true,
+        memory_increment: true,
+        jump_vx: false,
+        clip: false,
+        key_release: true,
+    };
+
+    pub const PROFILES: [(&'static str, Quirks); 4] = [
+        ("chip8", Quirks::CHIP8),
+        ("vip", Quirks::VIP),
+        ("schip", Quirks::SCHIP),
+        ("xochip", Quirks::XO_CHIP),
+    ];
+
+    pub fn by_name(name: &str) -> Option<Quirks> {
+        Self::PROFILES
+            .iter()
+            .find(|(profile, _)| profile.eq_ignore_ascii_case(name.trim()))
+            .map(|&(_, quirks)| quirks)
+    }
+
+    pub fn name(&self) -> Option<&'static str> {
+        Self::PROFILES.iter().find(|(_, quirks)| quirks == self).map(|&(name, _)| name)
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Quirks::CHIP8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn profiles_by_name() {
+        assert_eq!(Quirks::by_name("SCHIP"), Some(Quirks::SCHIP));
+        assert_eq!(Quirks::by_name("vip"), Some(Quirks::VIP));
+        assert_eq!(Quirks::by_name("chip48"), None);
+        assert_eq!(Quirks::default().name(), Some("chip8"));
+        assert_eq!(Quirks { clip: false, ..Quirks::SCHIP }.name(), None);
+    }
+}
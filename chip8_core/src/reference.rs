@@ -0,0 +1,489 @@
+use crate::chip8::{Chip8, KeyWait};
+use crate::configs::defaults::*;
+use crate::decode::{decode, disassemble, Instruction};
+use crate::error::Error;
+use crate::quirks::Quirks;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+const MEMORY_SIZE: usize = 4096;
+const STACK_SIZE: usize = 16;
+const CYCLES_PER_FRAME: u64 = 10;
+// Memory and screen differences listed before the rest are summed up
+const MAX_LISTED: usize = 4;
+
+// A second interpreter written straight from the instruction list, slow
+// and plain on purpose, for checking Chip8 against. It shares the decoder
+// and nothing else
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Reference {
+    pub v: [u8; 16],
+    pub memory: [u8; MEMORY_SIZE],
+    pub i: usize,
+    pub pc: usize,
+    pub stack: Vec<u16>,
+    pub delay: u8,
+    pub sound: u8,
+    pub keys: [bool; 16],
+    pub screen: [[u8; 64]; 32],
+    // Halted on Fx0A
+    pub key_wait: Option<KeyWait>,
+    pub rng: u32,
+    pub quirks: Quirks,
+}
+
+impl Reference {
+    // Same memory layout and state as a fresh Chip8 given the same seed
+    pub fn new(program: &[u8], seed: u32, quirks: Quirks) -> Result<Self, Error> {
+        let mut memory = [0; MEMORY_SIZE];
+        let start = START_ADDRESS as usize;
+        if program.len() > MEMORY_SIZE - start {
+            return Err(Error::RomTooLarge(program.len()));
+        }
+        memory[start..start + program.len()].copy_from_slice(program);
+        let font = FONT_START_ADDRESS as usize;
+        memory[font..font + FONT_SIZES.len()].copy_from_slice(&FONT_SIZES);
+
+        Ok(Self {
+            v: [0; 16],
+            memory,
+            i: 0,
+            pc: start,
+            stack: Vec::new(),
+            delay: 0,
+            sound: 0,
+            keys: [false; 16],
+            screen: [[0; 64]; 32],
+            key_wait: None,
+            rng: if seed == 0 { DEFAULT_SEED } else { seed },
+            quirks,
+        })
+    }
+
+    // One instruction, then the timers count down once
+    // On a fault nothing has changed and pc still points at the instruction
+    // While halted on Fx0A it looks at the keys instead
+    pub fn step(&mut self) -> Result<(), Error> {
+        if let Some(wait) = self.key_wait {
+            self.wait_for_key(wait);
+            self.timer_tick();
+            return Ok(());
+        }
+        let before = self.clone();
+        let result = self.execute();
+        if result.is_err() {
+            *self = before;
+        } else {
+            self.timer_tick();
+        }
+        result
+    }
+
+    pub fn timer_tick(&mut self) {
+        self.delay = self.delay.saturating_sub(1);
+        self.sound = self.sound.saturating_sub(1);
+    }
+
+    fn execute(&mut self) -> Result<(), Error> {
+        let pc = self.pc;
+        if pc + 2 > MEMORY_SIZE {
+            return Err(Error::MemoryOutOfBounds(pc));
+        }
+        let opcode = u16::from_be_bytes([self.memory[pc], self.memory[pc + 1]]);
+        self.pc = pc + 2;
+
+        match decode(opcode) {
+            Instruction::Cls => self.screen = [[0; 64]; 32],
+            Instruction::Ret => self.pc = self.stack.pop().ok_or(Error::StackUnderflow)? as usize,
+            Instruction::Sys(_) => (),
+            Instruction::Jp(nnn) => self.pc = nnn as usize,
+            Instruction::Call(nnn) => {
+                if self.stack.len() == STACK_SIZE {
+                    return Err(Error::StackOverflow);
+                }
+                self.stack.push(self.pc as u16);
+                self.pc = nnn as usize;
+            }
+            Instruction::SeByte(x, kk) => self.skip_if(self.v[x as usize] == kk),
+            Instruction::SneByte(x, kk) => self.skip_if(self.v[x as usize] != kk),
+            Instruction::SeReg(x, y) => self.skip_if(self.v[x as usize] == self.v[y as usize]),
+            Instruction::SneReg(x, y) => self.skip_if(self.v[x as usize] != self.v[y as usize]),
+            Instruction::LdByte(x, kk) => self.v[x as usize] = kk,
+            Instruction::AddByte(x, kk) => self.v[x as usize] = self.v[x as usize].wrapping_add(kk),
+            Instruction::LdReg(x, y) => self.v[x as usize] = self.v[y as usize],
+            Instruction::Or(x, y) => self.logic(x, y, |a, b| a | b),
+            Instruction::And(x, y) => self.logic(x, y, |a, b| a & b),
+            Instruction::Xor(x, y) => self.logic(x, y, |a, b| a ^ b),
+            Instruction::AddReg(x, y) => {
+                let (sum, carry) = self.v[x as usize].overflowing_add(self.v[y as usize]);
+                self.set_with_flag(x, sum, carry);
+            }
+            Instruction::Sub(x, y) => {
+                let (a, b) = (self.v[x as usize], self.v[y as usize]);
+                self.set_with_flag(x, a.wrapping_sub(b), a >= b);
+            }
+            Instruction::Subn(x, y) => {
+                let (a, b) = (self.v[x as usize], self.v[y as usize]);
+                self.set_with_flag(x, b.wrapping_sub(a), b >= a);
+            }
+            Instruction::Shr(x, y) => {
+                let value = self.v[if self.quirks.shift_vy { y } else { x } as usize];
+                self.set_with_flag(x, value >> 1, value & 1 == 1);
+            }
+            Instruction::Shl(x, y) => {
+                let value = self.v[if self.quirks.shift_vy { y } else { x } as usize];
+                self.set_with_flag(x, value << 1, value & 0x80 != 0);
+            }
+            Instruction::LdI(nnn) => self.i = nnn as usize,
+            Instruction::JpV0(nnn) => {
+                let x = if self.quirks.jump_vx { nnn >> 8 } else { 0 };
+                self.pc = nnn as usize + self.v[x as usize] as usize;
+            }
+            Instruction::Rnd(x, kk) => {
+                self.rng ^= self.rng << 13;
+                self.rng ^= self.rng >> 17;
+                self.rng ^= self.rng << 5;
+                self.v[x as usize] = (self.rng >> 24) as u8 & kk;
+            }
+            Instruction::Drw(x, y, n) => self.draw(x, y, n)?,
+            Instruction::Skp(x) => self.skip_if(self.keys[(self.v[x as usize] & 0xF) as usize]),
+            Instruction::Sknp(x) => self.skip_if(!self.keys[(self.v[x as usize] & 0xF) as usize]),
+            Instruction::LdVxDt(x) => self.v[x as usize] = self.delay,
+            Instruction::LdVxK(x) => self.key_wait = Some(KeyWait::Press { x }),
+            Instruction::LdDtVx(x) => self.delay = self.v[x as usize],
+            Instruction::LdStVx(x) => self.sound = self.v[x as usize],
+            Instruction::AddIVx(x) => self.i += self.v[x as usize] as usize,
+            Instruction::LdFVx(x) => self.i = FONT_START_ADDRESS as usize + 5 * (self.v[x as usize] & 0xF) as usize,
+            Instruction::LdBVx(x) => {
+                let value = self.v[x as usize];
+                self.check(self.i, 3)?;
+                self.memory[self.i] = value / 100;
+                self.memory[self.i + 1] = value / 10 % 10;
+                self.memory[self.i + 2] = value % 10;
+            }
+            Instruction::LdIVx(x) => {
+                let count = x as usize + 1;
+                self.check(self.i, count)?;
+                self.memory[self.i..self.i + count].copy_from_slice(&self.v[..count]);
+                if self.quirks.memory_increment {
+                    self.i += count;
+                }
+            }
+            Instruction::LdVxI(x) => {
+                let count = x as usize + 1;
+                self.check(self.i, count)?;
+                self.v[..count].copy_from_slice(&self.memory[self.i..self.i + count]);
+                if self.quirks.memory_increment {
+                    self.i += count;
+                }
+            }
+            Instruction::Unknown(opcode) => return Err(Error::UnknownOpcode(opcode)),
+        }
+        Ok(())
+    }
+
+    fn skip_if(&mut self, condition: bool) {
+        if condition {
+            self.pc += 2;
+        }
+    }
+
+    // VF is written after vx, so the flag wins when x is F
+    fn set_with_flag(&mut self, x: u8, value: u8, flag: bool) {
+        self.v[x as usize] = value;
+        self.v[0xF] = flag as u8;
+    }
+
+    fn logic(&mut self, x: u8, y: u8, op: fn(u8, u8) -> u8) {
+        self.v[x as usize] = op(self.v[x as usize], self.v[y as usize]);
+        if self.quirks.vf_reset {
+            self.v[0xF] = 0;
+        }
+    }
+
+    fn check(&self, address: usize, len: usize) -> Result<(), Error> {
+        if address + len > MEMORY_SIZE {
+            return Err(Error::MemoryOutOfBounds(address + len - 1));
+        }
+        Ok(())
+    }
+
+    fn draw(&mut self, x: u8, y: u8, n: u8) -> Result<(), Error> {
+        self.check(self.i, n as usize)?;
+        let left = self.v[x as usize] as usize % 64;
+        let top = self.v[y as usize] as usize % 32;
+        self.v[0xF] = 0;
+        for row in 0..n as usize {
+            let sprite = self.memory[self.i + row];
+            for column in 0..8 {
+                let (mut px, mut py) = (left + column, top + row);
+                if self.quirks.clip && (px >= 64 || py >= 32) {
+                    continue;
+                }
+                px %= 64;
+                py %= 32;
+                let bit = (sprite >> (7 - column)) & 1;
+                if bit == 1 && self.screen[py][px] == 1 {
+                    self.v[0xF] = 1;
+                }
+                self.screen[py][px] ^= bit;
+            }
+        }
+        Ok(())
+    }
+
+    // A key going down ends the wait, or with the key_release quirk the
+    // same key coming back up. With several down the highest numbered one
+    // counts
+    fn wait_for_key(&mut self, wait: KeyWait) {
+        match wait {
+            KeyWait::Press { x } => {
+                let Some(key) = (0..16u8).rev().find(|&k| self.keys[k as usize]) else {
+                    return;
+                };
+                if self.quirks.key_release {
+                    self.key_wait = Some(KeyWait::Release { x, key });
+                } else {
+                    self.v[x as usize] = key;
+                    self.key_wait = None;
+                }
+            }
+            KeyWait::Release { x, key } => {
+                if !self.keys[key as usize] {
+                    self.v[x as usize] = key;
+                    self.key_wait = None;
+                }
+            }
+        }
+    }
+}
+
+// First point where Chip8 and the reference disagree
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    // Instructions both ran before the one that disagreed
+    pub cycle: u64,
+    pub pc: usize,
+    pub opcode: u16,
+    pub differences: Vec<String>,
+    // Disassembly around pc, the instruction marked with >
+    pub context: String,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "diverged at instruction {}, {:03X} {:04X} {}",
+            self.cycle,
+            self.pc,
+            self.opcode,
+            decode(self.opcode)
+        )?;
+        for difference in self.differences.iter() {
+            writeln!(f, "  {}", difference)?;
+        }
+        write!(f, "{}", self.context)
+    }
+}
+
+// Everything architectural that differs, as "what: chip vs reference"
+pub fn compare(chip: &Chip8, reference: &Reference) -> Vec<String> {
+    let mut out = Vec::new();
+    for x in 0..16 {
+        if chip.registers[x] != reference.v[x] {
+            out.push(format!("V{:X}: {:#04X} vs {:#04X}", x, chip.registers[x], reference.v[x]));
+        }
+    }
+    let mut same = |name: &str, a: usize, b: usize| {
+        if a != b {
+            out.push(format!("{}: {:#X} vs {:#X}", name, a, b));
+        }
+    };
+    same("I", chip.index, reference.i);
+    same("pc", chip.pc, reference.pc);
+    same("DT", chip.delay_timer as usize, reference.delay as usize);
+    same("ST", chip.sound_timer as usize, reference.sound as usize);
+    same("rng", chip.rng as usize, reference.rng as usize);
+
+    if chip.stack_frames() != reference.stack.as_slice() {
+        out.push(format!("stack: {:X?} vs {:X?}", chip.stack_frames(), reference.stack));
+    }
+    if chip.key_wait != reference.key_wait {
+        out.push(format!("key wait: {:?} vs {:?}", chip.key_wait, reference.key_wait));
+    }
+
+    // Whole arrays first, walking them in a debug build for every
+    // instruction is what makes a lockstep run slow
+    if chip.memory != reference.memory {
+        list_memory(chip, reference, &mut out);
+    }
+    if chip.vram != reference.screen {
+        list_pixels(chip, reference, &mut out);
+    }
+    out
+}
+
+fn list_memory(chip: &Chip8, reference: &Reference, out: &mut Vec<String>) {
+    let memory: Vec<usize> = (0..MEMORY_SIZE).filter(|&a| chip.memory[a] != reference.memory[a]).collect();
+    for &address in memory.iter().take(MAX_LISTED) {
+        out.push(format!("memory {:03X}: {:#04X} vs {:#04X}", address, chip.memory[address], reference.memory[address]));
+    }
+    if memory.len() > MAX_LISTED {
+        out.push(format!("... {} bytes of memory in all", memory.len()));
+    }
+}
+
+fn list_pixels(chip: &Chip8, reference: &Reference, out: &mut Vec<String>) {
+    let pixels: Vec<(usize, usize)> = (0..32)
+        .flat_map(|y| (0..64).map(move |x| (x, y)))
+        .filter(|&(x, y)| chip.vram[y][x] != reference.screen[y][x])
+        .collect();
+    for &(x, y) in pixels.iter().take(MAX_LISTED) {
+        out.push(format!("pixel {},{}: {} vs {}", x, y, chip.vram[y][x], reference.screen[y][x]));
+    }
+    if pixels.len() > MAX_LISTED {
+        out.push(format!("... {} pixels in all", pixels.len()));
+    }
+}
+
+// Disassembly from a few instructions before pc to a few after
+fn context(memory: &[u8], pc: usize) -> String {
+    let start = pc.saturating_sub(8);
+    let mut out = String::new();
+    for (address, opcode, instruction) in disassemble(memory, start, pc + 10) {
+        let marker = if address == pc { '>' } else { ' ' };
+        out.push_str(&format!("{} {:03X}  {:04X}  {}\n", marker, address, opcode, instruction));
+    }
+    out
+}
+
+// Run Chip8 and the reference side by side on the same rom, seed and keys,
+// comparing after every instruction. keys gives the keypad before each
+// instruction and both tick their timers every frame of CYCLES_PER_FRAME.
+// Returns how many instructions ran, stopping early if both fault the same
+pub fn lockstep(
+    program: &[u8],
+    quirks: Quirks,
+    seed: u32,
+    cycles: u64,
+    mut keys: impl FnMut(u64) -> [bool; 16],
+) -> Result<u64, Divergence> {
+    let mut chip = Chip8::with_seed(seed);
+    chip.set_quirks(quirks);
+    let loaded = chip.load_program(program);
+    let mut reference = match (Reference::new(program, seed, quirks), loaded) {
+        (Ok(reference), Ok(())) => reference,
+        (Err(a), Err(b)) if a == b => return Ok(0),
+        (a, b) => {
+            return Err(Divergence {
+                cycle: 0,
+                pc: START_ADDRESS as usize,
+                opcode: 0,
+                differences: alloc::vec![format!("loading: {:?} vs {:?}", b, a.map(|_| ()))],
+                context: String::new(),
+            })
+        }
+    };
+
+    for cycle in 0..cycles {
+        let pressed = keys(cycle);
+        for (key, &down) in pressed.iter().enumerate() {
+            chip.set_key(key, down);
+        }
+        reference.keys = pressed;
+
+        let pc = reference.pc;
+        let opcode = match (reference.memory.get(pc), reference.memory.get(pc + 1)) {
+            (Some(&high), Some(&low)) => u16::from_be_bytes([high, low]),
+            _ => 0,
+        };
+        let results = (chip.cycle(), reference.step());
+        let mut differences = compare(&chip, &reference);
+        if results.0 != results.1 {
+            differences.insert(0, format!("result: {:?} vs {:?}", results.0, results.1));
+        }
+        if !differences.is_empty() {
+            return Err(Divergence { cycle, pc, opcode, differences, context: context(&reference.memory, pc) });
+        }
+        if results.0.is_err() {
+            return Ok(cycle);
+        }
+        if cycle % CYCLES_PER_FRAME == CYCLES_PER_FRAME - 1 {
+            chip.timer_tick();
+            reference.timer_tick();
+        }
+    }
+    Ok(cycles)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fuzz;
+    use alloc::string::ToString;
+
+    fn no_keys(_: u64) -> [bool; 16] {
+        [false; 16]
+    }
+
+    #[test]
+    fn agrees_on_random_programs() {
+        let mut next = fuzz::xorshift(0x2545_F491_4F6C_DD1D);
+        for round in 0..100 {
+            let program = fuzz::random_program(&mut next);
+            let seed = next() as u32;
+            // Keys change every few frames
+            let keys = |cycle: u64| {
+                let pattern = (cycle / 37).wrapping_mul(0x9E37_79B9) >> 7;
+                core::array::from_fn(|k| pattern >> k & 1 == 1)
+            };
+            for (name, quirks) in Quirks::PROFILES {
+                if let Err(divergence) = lockstep(&program, quirks, seed, 1000, keys) {
+                    panic!("round {} under {}: {}", round, name, divergence);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn key_wait_matches() {
+        // LD V3, K; JP 0x200
+        let program = [0xF3, 0x0A, 0x12, 0x00];
+        let keys = |cycle: u64| core::array::from_fn(|k| k == 7 && (10..20).contains(&cycle));
+        assert_eq!(lockstep(&program, Quirks::CHIP8, 1, 40, keys), Ok(40));
+    }
+
+    #[test]
+    fn reports_the_first_difference() {
+        // LD V0, 5; LD V1, 6; JP 0x204
+        let program = [0x60, 0x05, 0x61, 0x06, 0x12, 0x04];
+        let mut chip = Chip8::new();
+        chip.load_program(&program).unwrap();
+        let mut reference = Reference::new(&program, DEFAULT_SEED, Quirks::CHIP8).unwrap();
+        assert!(compare(&chip, &reference).is_empty());
+
+        chip.cycle().unwrap();
+        reference.step().unwrap();
+        reference.v[0] = 4;
+        reference.memory[0x300] = 1;
+        assert_eq!(compare(&chip, &reference), ["V0: 0x05 vs 0x04", "memory 300: 0x00 vs 0x01"]);
+
+        let text = context(&reference.memory, 0x202);
+        assert_eq!(text.lines().find(|line| line.starts_with('>')), Some("> 202  6106  LD V1, 0x06"));
+
+        // Both fault the same way, that's agreement
+        assert_eq!(lockstep(&[0x00, 0xEE], Quirks::CHIP8, 1, 10, no_keys), Ok(0));
+        assert_eq!(lockstep(&[0; 4000], Quirks::CHIP8, 1, 10, no_keys), Ok(0));
+        let divergence = Divergence {
+            cycle: 3,
+            pc: 0x202,
+            opcode: 0x6106,
+            differences: alloc::vec!["V1: 0x06 vs 0x07".to_string()],
+            context: text,
+        };
+        assert!(divergence.to_string().starts_with("diverged at instruction 3, 202 6106 LD V1, 0x06\n  V1: 0x06 vs 0x07\n"));
+    }
+}
@@ -0,0 +1,67 @@
+use crate::chip8::Chip8;
+use alloc::collections::VecDeque;
+
+// Last few snapshots of the machine, stepping back restores them newest first
+pub struct Rewind {
+    states: VecDeque<Chip8>,
+    max_states: usize,
+}
+
+impl Default for Rewind {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Rewind {
+    pub fn new() -> Self {
+        Self { 
+            states: VecDeque::new(), 
+            max_states: 5,
+        }
+    }
+
+    pub fn capture(&mut self, chip: &Chip8) {
+        let mut clonedchip = *chip;
+        clonedchip.clear_keys();
+
+        if self.states.len() >= self.max_states {
+            self.states.pop_front();
+            self.states.push_back(clonedchip);
+        } else {
+            self.states.push_back(clonedchip);
+        }
+    }
+
+    pub fn step_back(&mut self) -> Chip8 {
+        if self.states.len() <= 1 {
+            let cloned = self.states[0];
+            self.states.push_back(cloned);
+        }
+        self.states.pop_back().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn steps_back_newest_first() {
+        let mut rewind = Rewind::new();
+        let mut chip = Chip8::new();
+        for value in 0..7 {
+            chip.set_register(0, value);
+            chip.set_key(1, true);
+            rewind.capture(&chip);
+        }
+        assert_eq!(rewind.step_back().register(0), 6);
+        assert_eq!(rewind.step_back().register(0), 5);
+        // Snapshots never keep held keys
+        assert!(!rewind.step_back().keypad()[1]);
+        // The oldest state is kept once the rest are used up
+        rewind.step_back();
+        assert_eq!(rewind.step_back().register(0), 2);
+        assert_eq!(rewind.step_back().register(0), 2);
+    }
+}
@@ -0,0 +1,220 @@
+use crate::chip8::Chip8;
+use crate::configs::defaults::*;
+use crate::decode::{decode, Instruction};
+use crate::hook::Hook;
+use alloc::collections::BTreeSet;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+
+// Where a byte's current value came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shadow {
+    Unwritten,
+    Font,
+    Rom,
+    // Stored by Fx33 or Fx55
+    Written,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Issue {
+    // Dxyn or Fx65 reading a byte nothing ever stored
+    UninitialisedRead(u16),
+    // Opcode fetched from a byte that isn't rom
+    ExecutedUninitialised(u16),
+    ExecutedData(u16),
+    ExecutedFont(u16),
+    // Fx33 or Fx55 storing into the font or interpreter area
+    WriteBelowRom(u16),
+    // Jump, call or return landing on an odd address
+    OddJump(u16),
+}
+
+// An issue and the instruction that caused it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Report {
+    pub cycle: u64,
+    pub pc: u16,
+    pub opcode: u16,
+    pub issue: Issue,
+}
+
+// Shadow state per byte of memory, fed through Chip8::cycle_with
+// Each issue is reported once per instruction address
+pub struct Sanitizer {
+    shadow: Vec<Shadow>,
+    cycle: u64,
+    reports: Vec<Report>,
+    seen: BTreeSet<(u16, Issue)>,
+}
+
+impl Hook for Sanitizer {
+    fn before(&mut self, chip: &Chip8, pc: usize, opcode: u16) {
+        for address in [pc, pc + 1] {
+            let issue = match self.shadow(address) {
+                Shadow::Unwritten => Issue::ExecutedUninitialised(address as u16),
+                Shadow::Written => Issue::ExecutedData(address as u16),
+                Shadow::Font => Issue::ExecutedFont(address as u16),
+                Shadow::Rom => continue,
+            };
+            self.report(pc, opcode, issue);
+            // One report for the opcode, not one per byte
+            break;
+        }
+
+        let index = chip.index();
+        let reads = match decode(opcode) {
+            Instruction::Drw(_, _, n) => n as usize,
+            Instruction::LdVxI(x) => x as usize + 1,
+            _ => 0,
+        };
+        if let Some(address) = (index..index + reads).find(|&a| self.shadow(a) == Shadow::Unwritten) {
+            self.report(pc, opcode, Issue::UninitialisedRead(address as u16));
+        }
+    }
+
+    fn after(&mut self, chip: &Chip8, pc: usize, opcode: u16) {
+        if let Some((start, len)) = chip.last_write() {
+            for address in start..start + len {
+                self.shadow[address] = Shadow::Written;
+            }
+            if start < START_ADDRESS as usize {
+                self.report(pc, opcode, Issue::WriteBelowRom(start as u16));
+            }
+        }
+
+        let jumped = matches!(
+            decode(opcode),
+            Instruction::Jp(_) | Instruction::Call(_) | Instruction::JpV0(_) | Instruction::Ret
+        );
+        if jumped && !chip.pc().is_multiple_of(2) {
+            self.report(pc, opcode, Issue::OddJump(chip.pc() as u16));
+        }
+        self.cycle += 1;
+    }
+}
+
+impl Sanitizer {
+    // Memory as Chip8::load_program leaves it for a rom of rom_len bytes
+    pub fn new(rom_len: usize) -> Self {
+        let mut shadow = vec![Shadow::Unwritten; 4096];
+        let font = FONT_START_ADDRESS as usize;
+        shadow[font..font + FONT_SIZES.len()].fill(Shadow::Font);
+        let start = START_ADDRESS as usize;
+        let end = (start + rom_len).min(shadow.len());
+        shadow[start..end].fill(Shadow::Rom);
+
+        Self { shadow, cycle: 0, reports: Vec::new(), seen: BTreeSet::new() }
+    }
+
+    pub fn shadow(&self, address: usize) -> Shadow {
+        self.shadow.get(address).copied().unwrap_or(Shadow::Unwritten)
+    }
+
+    // Reports since the last call, oldest first
+    pub fn take_reports(&mut self) -> Vec<Report> {
+        core::mem::take(&mut self.reports)
+    }
+
+    fn report(&mut self, pc: usize, opcode: u16, issue: Issue) {
+        if self.seen.insert((pc as u16, issue_kind(issue))) {
+            self.reports.push(Report { cycle: self.cycle, pc: pc as u16, opcode, issue });
+        }
+    }
+}
+
+// The issue with its address zeroed, so a loop reading through
+// uninitialised memory is one report and not one per byte
+fn issue_kind(issue: Issue) -> Issue {
+    match issue {
+        Issue::UninitialisedRead(_) => Issue::UninitialisedRead(0),
+        Issue::ExecutedUninitialised(_) => Issue::ExecutedUninitialised(0),
+        Issue::ExecutedData(_) => Issue::ExecutedData(0),
+        Issue::ExecutedFont(_) => Issue::ExecutedFont(0),
+        Issue::WriteBelowRom(_) => Issue::WriteBelowRom(0),
+        Issue::OddJump(_) => Issue::OddJump(0),
+    }
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Issue::UninitialisedRead(a) => write!(f, "read of uninitialised memory at {:03X}", a),
+            Issue::ExecutedUninitialised(a) => write!(f, "executing uninitialised memory at {:03X}", a),
+            Issue::ExecutedData(a) => write!(f, "executing data the program stored at {:03X}", a),
+            Issue::ExecutedFont(a) => write!(f, "executing font data at {:03X}", a),
+            Issue::WriteBelowRom(a) => write!(f, "write to {:03X}, below the rom", a),
+            Issue::OddJump(a) => write!(f, "jump to odd address {:03X}", a),
+        }
+    }
+}
+
+// cycle pc opcode issue
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:>10} {:03X} {:04X} {}", self.cycle, self.pc, self.opcode, self.issue)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+
+    fn run(program: &[u8], cycles: usize) -> Vec<Report> {
+        let mut chip = Chip8::new();
+        chip.load_program(program).unwrap();
+        let mut sanitizer = Sanitizer::new(program.len());
+        for _ in 0..cycles {
+            chip.cycle_with(&mut sanitizer).unwrap();
+        }
+        sanitizer.take_reports()
+    }
+
+    #[test]
+    fn uninitialised_reads() {
+        // LD I, 0x300; DRW V0, V0, 2; DRW V0, V0, 2; LD I, 0x050; DRW V0, V0, 5
+        let reports = run(&[0xA3, 0x00, 0xD0, 0x02, 0xD0, 0x02, 0xA0, 0x50, 0xD0, 0x05], 5);
+        // Same instruction twice would be one report, these are two
+        assert_eq!(reports.len(), 2);
+        assert_eq!(reports[0].issue, Issue::UninitialisedRead(0x300));
+        assert_eq!(reports[1].pc, 0x204);
+        assert_eq!(reports[0].to_string(), "         1 202 D002 read of uninitialised memory at 300");
+    }
+
+    #[test]
+    fn executing_stored_data() {
+        // LD I, 0x208; LD [I], V1 (stores 2 bytes over 208); JP 0x208
+        let reports = run(&[0xA2, 0x08, 0xF1, 0x55, 0x12, 0x08, 0x00, 0x00, 0x12, 0x08], 4);
+        assert_eq!(
+            reports.iter().map(|r| r.issue).collect::<Vec<_>>(),
+            [Issue::ExecutedData(0x208)]
+        );
+    }
+
+    #[test]
+    fn runs_off_the_rom() {
+        let reports = run(&[0x12, 0x02, 0x00], 1);
+        assert!(reports.is_empty());
+        let reports = run(&[0x12, 0x04, 0x00, 0x00], 2);
+        assert_eq!(reports[0].issue, Issue::ExecutedUninitialised(0x204));
+    }
+
+    #[test]
+    fn low_writes_and_odd_jumps() {
+        // LD I, 0x100; LD B, V0; JP 0x207; (207) CLS
+        let reports = run(&[0xA1, 0x00, 0xF0, 0x33, 0x12, 0x07, 0x00, 0x00, 0xE0], 3);
+        let issues: Vec<Issue> = reports.iter().map(|r| r.issue).collect();
+        assert_eq!(issues, [Issue::WriteBelowRom(0x100), Issue::OddJump(0x207)]);
+    }
+
+    #[test]
+    fn shadow_states() {
+        let sanitizer = Sanitizer::new(4);
+        assert_eq!(sanitizer.shadow(0x050), Shadow::Font);
+        assert_eq!(sanitizer.shadow(0x203), Shadow::Rom);
+        assert_eq!(sanitizer.shadow(0x204), Shadow::Unwritten);
+        assert_eq!(sanitizer.shadow(0x000), Shadow::Unwritten);
+    }
+}
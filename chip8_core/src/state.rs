@@ -0,0 +1,191 @@
+use crate::chip8::{Chip8, KeyWait};
+use crate::error::Error;
+use alloc::vec::Vec;
+
+// Save state layout, all multi byte values little endian
+//   magic "C8ST", version
+//   registers, memory, index, pc, stack, sp
+//   delay timer, sound timer, keypad, vram, vram change
+//   opcode, key wait (0 none, 1 press, 2 release), wait register, wait key, rng
+// Version 1 had a waiting flag and wait key instead, with pc left on the
+// Fx0A, and still loads
+const MAGIC: &[u8; 4] = b"C8ST";
+const VERSION: u8 = 2;
+
+pub const STATE_SIZE: usize = 4 + 1
+    + 16 + 4096 + 2 + 2 + 32 + 1
+    + 1 + 1 + 16 + 64 * 32 + 1
+    + 2 + 1 + 1 + 1 + 4;
+const STATE_SIZE_V1: usize = STATE_SIZE - 1;
+
+pub fn serialize(chip: &Chip8) -> Vec<u8> {
+    let mut out = Vec::with_capacity(STATE_SIZE);
+    out.extend_from_slice(MAGIC);
+    out.push(VERSION);
+
+    out.extend_from_slice(&chip.registers);
+    out.extend_from_slice(&chip.memory);
+    out.extend_from_slice(&(chip.index as u16).to_le_bytes());
+    out.extend_from_slice(&(chip.pc as u16).to_le_bytes());
+    for frame in chip.stack {
+        out.extend_from_slice(&frame.to_le_bytes());
+    }
+    out.push(chip.sp as u8);
+
+    out.push(chip.delay_timer);
+    out.push(chip.sound_timer);
+    out.extend(chip.keypad.iter().map(|&k| k as u8));
+    out.extend(chip.vram.iter().flatten());
+    out.push(chip.vram_change as u8);
+
+    out.extend_from_slice(&chip.opcode.to_le_bytes());
+    let (wait, x, key) = match chip.key_wait {
+        None => (0, 0, 0),
+        Some(KeyWait::Press { x }) => (1, x, 0),
+        Some(KeyWait::Release { x, key }) => (2, x, key),
+    };
+    out.extend_from_slice(&[wait, x, key]);
+    out.extend_from_slice(&chip.rng.to_le_bytes());
+    out
+}
+
+pub fn deserialize(bytes: &[u8]) -> Result<Chip8, Error> {
+    let version = match (bytes.len(), bytes.get(4)) {
+        (STATE_SIZE, Some(&VERSION)) => VERSION,
+        (STATE_SIZE_V1, Some(1)) => 1,
+        _ => return Err(Error::BadState),
+    };
+    if &bytes[..4] != MAGIC {
+        return Err(Error::BadState);
+    }
+    let mut reader = Reader { bytes, pos: 5 };
+    let mut chip = Chip8::new();
+
+    chip.registers.copy_from_slice(reader.take(16));
+    chip.memory.copy_from_slice(reader.take(4096));
+    chip.index = reader.u16() as usize;
+    chip.pc = reader.u16() as usize;
+    for frame in chip.stack.iter_mut() {
+        *frame = reader.u16();
+    }
+    chip.sp = reader.u8() as usize;
+
+    chip.delay_timer = reader.u8();
+    chip.sound_timer = reader.u8();
+    for key in chip.keypad.iter_mut() {
+        *key = reader.u8() != 0;
+    }
+    for row in chip.vram.iter_mut() {
+        row.copy_from_slice(reader.take(64));
+    }
+    chip.vram_change = reader.u8() != 0;
+
+    chip.opcode = reader.u16();
+    chip.key_wait = if version == 1 {
+        let (waiting, key) = (reader.u8() != 0, reader.u8());
+        // Was on the Fx0A with the key down, now past it waiting for the release
+        let x = ((chip.opcode & 0x0F00) >> 8) as u8;
+        if waiting && chip.opcode & 0xF0FF == 0xF00A && chip.pc + 2 < chip.memory.len() {
+            chip.pc += 2;
+            Some(KeyWait::Release { x, key })
+        } else {
+            None
+        }
+    } else {
+        match (reader.u8(), reader.u8(), reader.u8()) {
+            (0, _, _) => None,
+            (1, x, _) => Some(KeyWait::Press { x }),
+            (2, x, key) => Some(KeyWait::Release { x, key }),
+            _ => return Err(Error::BadState),
+        }
+    };
+    chip.rng = u32::from_le_bytes(reader.take(4).try_into().unwrap());
+
+    let bad_wait = match chip.key_wait {
+        Some(KeyWait::Press { x }) => x > 0xF,
+        Some(KeyWait::Release { x, key }) => x > 0xF || key > 0xF,
+        None => false,
+    };
+    if chip.sp > chip.stack.len() || bad_wait || chip.rng == 0 {
+        return Err(Error::BadState);
+    }
+    Ok(chip)
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> &'a [u8] {
+        let slice = &self.bytes[self.pos..self.pos + len];
+        self.pos += len;
+        slice
+    }
+
+    fn u8(&mut self) -> u8 {
+        self.take(1)[0]
+    }
+
+    fn u16(&mut self) -> u16 {
+        u16::from_le_bytes([self.u8(), self.u8()])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut chip = Chip8::with_seed(99);
+        chip.load_program(&[0x61, 0x23, 0x22, 0x10, 0xF1, 0x33]).unwrap();
+        chip.set_index(0x300);
+        chip.set_key(4, true);
+        chip.cycle().unwrap();
+        chip.cycle().unwrap();
+        chip.execute_opcode(0xD015).unwrap();
+
+        let bytes = serialize(&chip);
+        assert_eq!(bytes.len(), STATE_SIZE);
+
+        let restored = deserialize(&bytes).unwrap();
+        assert_eq!(serialize(&restored), bytes);
+        assert_eq!(restored.registers(), chip.registers());
+        assert_eq!(restored.stack_frames(), chip.stack_frames());
+        assert_eq!(restored.framebuffer(), chip.framebuffer());
+    }
+
+    #[test]
+    fn halted_on_a_key_wait() {
+        let mut chip = Chip8::new();
+        chip.load_program(&[0xF3, 0x0A]).unwrap();
+        chip.cycle().unwrap();
+        let restored = deserialize(&serialize(&chip)).unwrap();
+        assert_eq!(restored.key_wait(), Some(KeyWait::Press { x: 3 }));
+        chip.set_key(7, true);
+        chip.cycle().unwrap();
+        let restored = deserialize(&serialize(&chip)).unwrap();
+        assert_eq!(restored.key_wait(), Some(KeyWait::Release { x: 3, key: 7 }));
+
+        // A version 1 state waiting on key 7, pc still on the Fx0A
+        let mut old = serialize(&chip);
+        old[4] = 1;
+        let at = STATE_SIZE - 4 - 3;
+        old.splice(at..at + 3, [1, 7]);
+        old[5 + 16 + 4096 + 2..][..2].copy_from_slice(&0x200u16.to_le_bytes());
+        let restored = deserialize(&old).unwrap();
+        assert_eq!((restored.pc(), restored.key_wait()), (0x202, Some(KeyWait::Release { x: 3, key: 7 })));
+    }
+
+    #[test]
+    fn rejects_bad_input() {
+        let bytes = serialize(&Chip8::new());
+        assert_eq!(deserialize(&bytes[1..]).err(), Some(Error::BadState));
+
+        let mut wrong_magic = bytes.clone();
+        wrong_magic[0] = b'X';
+        assert_eq!(deserialize(&wrong_magic).err(), Some(Error::BadState));
+    }
+}
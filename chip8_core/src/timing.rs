@@ -0,0 +1,158 @@
+use crate::chip8::Chip8;
+use crate::decode::{decode, Instruction};
+
+// Machine cycles in a 60 Hz frame, the VIP's 1.76 MHz clock over 8 clocks
+// a cycle
+pub const VIP_FRAME_CYCLES: u32 = 3668;
+// Taken by the display interrupt each frame, 128 lines of DMA at 14 cycles
+// a line plus getting in and out of the routine
+pub const VIP_INTERRUPT_CYCLES: u32 = 1832;
+// What's left for the interpreter
+pub const VIP_BUDGET: u32 = VIP_FRAME_CYCLES - VIP_INTERRUPT_CYCLES;
+
+// Fetching and decoding, the same for every instruction
+const FETCH: u32 = 40;
+
+// How instructions are paced against frames
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Timing {
+    // Every instruction costs one tick, the timers count down after each
+    // and the frontend decides how many run in a frame
+    #[default]
+    Ticks,
+    // COSMAC VIP machine cycles, the timers only count down in the display
+    // interrupt and Dxyn waits for it before drawing
+    Vip,
+}
+
+impl Timing {
+    pub const MODES: [(&'static str, Timing); 2] = [("ticks", Timing::Ticks), ("vip", Timing::Vip)];
+
+    pub fn by_name(name: &str) -> Option<Timing> {
+        Self::MODES
+            .iter()
+            .find(|(mode, _)| mode.eq_ignore_ascii_case(name.trim()))
+            .map(|&(_, timing)| timing)
+    }
+
+    pub fn name(&self) -> &'static str {
+        Self::MODES.iter().find(|(_, timing)| timing == self).map(|&(name, _)| name).unwrap()
+    }
+}
+
+// Cycles the VIP interpreter spends on an instruction, taken from the chip
+// before it runs. Close to the original interpreter's routines rather than
+// exact to the cycle, skips, page crossings, sprite alignment and the
+// digits of Fx33 cost extra like they do there
+pub fn vip_cycles(chip: &Chip8, opcode: u16) -> u32 {
+    let vx = chip.registers[((opcode & 0x0F00) >> 8) as usize];
+    let vy = chip.registers[((opcode & 0x00F0) >> 4) as usize];
+    let skip = |taken: bool| if taken { 4 } else { 0 };
+    let page = |low: usize, add: u8| if (low & 0xFF) + add as usize > 0xFF { 2 } else { 0 };
+
+    FETCH + match decode(opcode) {
+        Instruction::Cls => 3078,
+        Instruction::Ret => 10,
+        Instruction::Sys(_) | Instruction::Unknown(_) => 0,
+        Instruction::Jp(_) => 12,
+        Instruction::Call(_) => 26,
+        Instruction::SeByte(_, kk) => 10 + skip(vx == kk),
+        Instruction::SneByte(_, kk) => 10 + skip(vx != kk),
+        Instruction::SeReg(..) => 14 + skip(vx == vy),
+        Instruction::SneReg(..) => 14 + skip(vx != vy),
+        Instruction::LdByte(..) => 6,
+        Instruction::AddByte(..) => 10,
+        Instruction::LdReg(..)
+        | Instruction::Or(..)
+        | Instruction::And(..)
+        | Instruction::Xor(..)
+        | Instruction::AddReg(..)
+        | Instruction::Sub(..)
+        | Instruction::Shr(..)
+        | Instruction::Subn(..)
+        | Instruction::Shl(..) => 44,
+        Instruction::LdI(_) => 12,
+        Instruction::JpV0(nnn) => 22 + page(nnn as usize, chip.registers[0]),
+        Instruction::Rnd(..) => 36,
+        // Bytes that don't start on a byte boundary get shifted bit by bit
+        Instruction::Drw(_, _, n) => {
+            let row = if vx.is_multiple_of(8) { 34 } else { 46 + 4 * (vx % 8) as u32 };
+            26 + n as u32 * row
+        }
+        Instruction::Skp(_) => 14 + skip(chip.keypad[(vx & 0xF) as usize]),
+        Instruction::Sknp(_) => 14 + skip(!chip.keypad[(vx & 0xF) as usize]),
+        Instruction::LdVxDt(_) | Instruction::LdDtVx(_) | Instruction::LdStVx(_) => 10,
+        // Each time round the wait
+        Instruction::LdVxK(_) => 18,
+        Instruction::AddIVx(_) => 16 + page(chip.index, vx),
+        Instruction::LdFVx(_) => 16,
+        // Counted out by repeated subtraction
+        Instruction::LdBVx(_) => 84 + 16 * (vx / 100 + vx / 10 % 10 + vx % 10) as u32,
+        Instruction::LdIVx(x) | Instruction::LdVxI(x) => 14 + 14 * (x as u32 + 1),
+    }
+}
+
+// Cycles spent in the current frame
+#[derive(Debug, Clone, Copy, Default)]
+pub struct VipClock {
+    cycles: u32,
+}
+
+impl VipClock {
+    // Charge an instruction that has run, returns how many display
+    // interrupts happened meanwhile. Dxyn waits for the next one, so the
+    // rest of the frame goes and its own cost starts the new one
+    pub fn charge(&mut self, opcode: u16, cost: u32) -> u32 {
+        let mut interrupts = 0;
+        if opcode & 0xF000 == 0xD000 {
+            interrupts += 1;
+            self.cycles = 0;
+        }
+        self.cycles += cost;
+        while self.cycles >= VIP_BUDGET {
+            self.cycles -= VIP_BUDGET;
+            interrupts += 1;
+        }
+        interrupts
+    }
+
+    pub fn cycles(&self) -> u32 {
+        self.cycles
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn instruction_costs() {
+        let mut chip = Chip8::new();
+        chip.set_register(1, 5);
+        assert_eq!(vip_cycles(&chip, 0x6105), FETCH + 6);
+        assert_eq!(vip_cycles(&chip, 0x3105), FETCH + 14);
+        assert_eq!(vip_cycles(&chip, 0x3106), FETCH + 10);
+        assert_eq!(vip_cycles(&chip, 0xF133), FETCH + 84 + 16 * 5);
+        assert_eq!(vip_cycles(&chip, 0xF255), FETCH + 14 + 14 * 3);
+        // Aligned sprites are cheaper
+        chip.set_register(1, 8);
+        chip.set_register(2, 9);
+        assert!(vip_cycles(&chip, 0xD115) < vip_cycles(&chip, 0xD215));
+        assert_eq!(vip_cycles(&chip, 0xD115), FETCH + 26 + 5 * 34);
+        assert_eq!(Timing::by_name("VIP"), Some(Timing::Vip));
+        assert_eq!(Timing::default().name(), "ticks");
+    }
+
+    #[test]
+    fn frames_and_display_wait() {
+        let mut clock = VipClock::default();
+        assert_eq!(clock.charge(0x6000, VIP_BUDGET - 10), 0);
+        assert_eq!(clock.charge(0x6000, 20), 1);
+        assert_eq!(clock.cycles(), 10);
+        // A clear takes more than a frame
+        assert_eq!(clock.charge(0x00E0, 3078 + FETCH), 1);
+        // Drawing gives up the rest of the frame
+        assert_eq!(clock.charge(0xD015, 100), 1);
+        assert_eq!(clock.cycles(), 100);
+    }
+}
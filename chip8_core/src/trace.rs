@@ -0,0 +1,256 @@
+use crate::chip8::Chip8;
+use crate::decode::{decode, Instruction};
+use crate::error::Error;
+use crate::hook::Hook;
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::fmt;
+
+pub const DEFAULT_RING_SIZE: usize = 256;
+
+// One executed instruction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceEntry {
+    pub cycle: u64,
+    pub pc: u16,
+    pub opcode: u16,
+    pub instruction: Instruction,
+    // (register, before, after) for every register the instruction changed
+    pub changes: Vec<(u8, u8, u8)>,
+    pub index: u16,
+    // Start address and bytes written to memory
+    pub writes: Option<(u16, Vec<u8>)>,
+    pub fault: Option<Error>,
+}
+
+// Which entries are written out, every set filter has to match
+// Ranges are inclusive, classes is a bit mask of opcode high nibbles
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TraceFilter {
+    pub addresses: Option<(u16, u16)>,
+    pub classes: Option<u16>,
+    pub cycles: Option<(u64, u64)>,
+}
+
+impl TraceFilter {
+    pub fn matches(&self, entry: &TraceEntry) -> bool {
+        if let Some((start, end)) = self.addresses {
+            if entry.pc < start || entry.pc > end {
+                return false;
+            }
+        }
+        if let Some(classes) = self.classes {
+            if classes & (1 << (entry.opcode >> 12)) == 0 {
+                return false;
+            }
+        }
+        if let Some((start, end)) = self.cycles {
+            if entry.cycle < start || entry.cycle > end {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+// Runs instructions and keeps the last few as a ring buffer for fault dumps
+pub struct Tracer {
+    cycle: u64,
+    ring: VecDeque<TraceEntry>,
+    capacity: usize,
+}
+
+impl Default for Tracer {
+    fn default() -> Self {
+        Self::new(DEFAULT_RING_SIZE)
+    }
+}
+
+impl Tracer {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            cycle: 0,
+            ring: VecDeque::with_capacity(capacity),
+            capacity: capacity.max(1),
+        }
+    }
+
+    // Number of instructions run through step() so far
+    pub fn cycles(&self) -> u64 {
+        self.cycle
+    }
+
+    // Run one cycle and record it, the entry is available from last()
+    pub fn step(&mut self, chip: &mut Chip8) -> Result<(), Error> {
+        self.step_with(chip, &mut ())
+    }
+
+    // Same as step, with a hook for the cycle
+    // Halted on Fx0A nothing runs, so nothing is recorded
+    pub fn step_with<H: Hook>(&mut self, chip: &mut Chip8, hook: &mut H) -> Result<(), Error> {
+        if chip.key_wait().is_some() {
+            return chip.cycle_with(hook);
+        }
+        let before = *chip.registers();
+        let pc = chip.pc();
+        // Read ahead of cycle() so a faulting fetch still has an opcode
+        let opcode = if pc + 1 < chip.memory().len() {
+            ((chip.peek(pc) as u16) << 8) | chip.peek(pc + 1) as u16
+        } else {
+            0
+        };
+        let result = chip.cycle_with(hook);
+
+        let changes = before
+            .iter()
+            .zip(chip.registers().iter())
+            .enumerate()
+            .filter(|(_, (old, new))| old != new)
+            .map(|(x, (&old, &new))| (x as u8, old, new))
+            .collect();
+        let writes = chip
+            .last_write()
+            .filter(|_| result.is_ok())
+            .map(|(start, len)| (start as u16, chip.memory()[start..start + len].to_vec()));
+
+        let entry = TraceEntry {
+            cycle: self.cycle,
+            pc: pc as u16,
+            opcode,
+            instruction: decode(opcode),
+            changes,
+            index: chip.index() as u16,
+            writes,
+            fault: result.err(),
+        };
+        self.cycle += 1;
+
+        if self.ring.len() >= self.capacity {
+            self.ring.pop_front();
+        }
+        self.ring.push_back(entry);
+        result
+    }
+
+    pub fn last(&self) -> Option<&TraceEntry> {
+        self.ring.back()
+    }
+
+    // Buffered entries, oldest first
+    pub fn ring(&self) -> impl Iterator<Item = &TraceEntry> {
+        self.ring.iter()
+    }
+}
+
+// cycle pc opcode mnemonic I changed registers memory writes
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:>10} {:03X} {:04X} {:<18} I={:03X}",
+            self.cycle,
+            self.pc,
+            self.opcode,
+            alloc::format!("{}", self.instruction),
+            self.index
+        )?;
+        for &(x, old, new) in self.changes.iter() {
+            write!(f, " V{:X}={:02X}->{:02X}", x, old, new)?;
+        }
+        if let Some((start, bytes)) = &self.writes {
+            write!(f, " [{:03X}]=", start)?;
+            for (i, byte) in bytes.iter().enumerate() {
+                let separator = if i == 0 { "" } else { "," };
+                write!(f, "{}{:02X}", separator, byte)?;
+            }
+        }
+        if let Some(fault) = self.fault {
+            write!(f, " FAULT: {}", fault)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+
+    #[test]
+    fn records_changes_and_writes() {
+        let mut chip = Chip8::new();
+        chip.load_program(&[0x61, 0x7B, 0xA3, 0x00, 0xF1, 0x33]).unwrap();
+        let mut tracer = Tracer::new(2);
+
+        for _ in 0..3 {
+            tracer.step(&mut chip).unwrap();
+        }
+        assert_eq!(tracer.cycles(), 3);
+        assert_eq!(tracer.ring().count(), 2);
+
+        let last = tracer.last().unwrap();
+        assert_eq!(last.writes, Some((0x300, [1, 2, 3].to_vec())));
+        assert_eq!(
+            last.to_string(),
+            "         2 204 F133 LD B, V1           I=300 [300]=01,02,03"
+        );
+
+        let first = tracer.ring().next().unwrap();
+        assert_eq!(first.changes, [].to_vec());
+        assert_eq!(first.index, 0x300);
+    }
+
+    #[test]
+    fn records_faults() {
+        let mut chip = Chip8::new();
+        chip.load_program(&[0x6F, 0x01, 0x00, 0xEE]).unwrap();
+        let mut tracer = Tracer::default();
+
+        tracer.step(&mut chip).unwrap();
+        assert_eq!(tracer.last().unwrap().changes, [(0xF, 0, 1)].to_vec());
+        assert_eq!(tracer.step(&mut chip), Err(Error::StackUnderflow));
+        assert!(tracer.last().unwrap().to_string().ends_with("FAULT: return with empty stack"));
+    }
+
+    #[test]
+    fn skips_key_waits() {
+        let mut chip = Chip8::new();
+        chip.load_program(&[0xF2, 0x0A, 0x60, 0x01]).unwrap();
+        let mut tracer = Tracer::default();
+        for _ in 0..5 {
+            tracer.step(&mut chip).unwrap();
+        }
+        assert_eq!(tracer.cycles(), 1);
+        chip.set_key(3, true);
+        tracer.step(&mut chip).unwrap();
+        chip.set_key(3, false);
+        tracer.step(&mut chip).unwrap();
+        tracer.step(&mut chip).unwrap();
+        assert_eq!(tracer.cycles(), 2);
+        assert_eq!(tracer.last().unwrap().opcode, 0x6001);
+    }
+
+    #[test]
+    fn filters() {
+        let entry = TraceEntry {
+            cycle: 50,
+            pc: 0x210,
+            opcode: 0xD125,
+            instruction: decode(0xD125),
+            changes: Vec::new(),
+            index: 0,
+            writes: None,
+            fault: None,
+        };
+        assert!(TraceFilter::default().matches(&entry));
+
+        let by_address = TraceFilter { addresses: Some((0x200, 0x20F)), ..Default::default() };
+        assert!(!by_address.matches(&entry));
+
+        let by_class = TraceFilter { classes: Some(1 << 0xD), ..Default::default() };
+        assert!(by_class.matches(&entry));
+
+        let by_cycle = TraceFilter { cycles: Some((0, 49)), ..Default::default() };
+        assert!(!by_cycle.matches(&entry));
+    }
+}
@@ -0,0 +1,25 @@
+// Chip8 against the reference interpreter on the test suite rom, with a
+// key stream that walks through its menu
+use chip8_core::reference::lockstep;
+use chip8_core::Quirks;
+use std::fs;
+use std::path::Path;
+
+const CYCLES: u64 = 200_000;
+
+#[test]
+fn test_suite_rom() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("../roms/chip8-test-suite.ch8");
+    let program = fs::read(&path).unwrap();
+    // Hold a key for a second, let go for a second, a different key each time
+    let keys = |cycle: u64| {
+        let second = cycle / 600;
+        let key = (second / 2 % 16) as usize;
+        std::array::from_fn(|k| k == key && second.is_multiple_of(2))
+    };
+    for (name, quirks) in Quirks::PROFILES {
+        if let Err(divergence) = lockstep(&program, quirks, 1, CYCLES, keys) {
+            panic!("{} under {}: {}", path.display(), name, divergence);
+        }
+    }
+}
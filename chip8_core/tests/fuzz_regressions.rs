@@ -0,0 +1,55 @@
+// Inputs the fuzz target has crashed on, plus a seeded sweep of random
+// programs so the same paths stay covered without cargo fuzz
+use chip8_core::fuzz;
+use chip8_core::{Error, Quirks};
+use std::fs;
+use std::path::Path;
+
+#[test]
+fn saved_crashes() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/regressions");
+    let mut count = 0;
+    for entry in fs::read_dir(&dir).unwrap() {
+        let path = entry.unwrap().path();
+        let data = fs::read(&path).unwrap();
+        for (name, quirks) in Quirks::PROFILES {
+            // A fault is fine, a panic fails the test with the file named
+            let result = std::panic::catch_unwind(|| fuzz::run_with(&data, quirks, fuzz::FUZZ_CYCLES));
+            assert!(result.is_ok(), "{} panicked under {}", path.display(), name);
+        }
+        count += 1;
+    }
+    assert!(count > 0, "no inputs in {}", dir.display());
+}
+
+// Minimized inputs for faults that used to panic, each has to come back
+// as its error under every profile
+#[test]
+fn minimized_faults() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/regressions");
+    let cases = [
+        // CALL 200 calling itself until the stack is full
+        ("2nnn_stack_overflow.ch8", Error::StackOverflow),
+        // LD I, FFF; LD B, V0
+        ("fx33_past_memory.ch8", Error::MemoryOutOfBounds(0x1001)),
+        // LD I, FFF; LD VF, [I]
+        ("fx65_past_memory.ch8", Error::MemoryOutOfBounds(0x100E)),
+    ];
+    for (file, error) in cases {
+        let data = fs::read(dir.join(file)).unwrap();
+        for (name, quirks) in Quirks::PROFILES {
+            assert_eq!(fuzz::run_with(&data, quirks, fuzz::FUZZ_CYCLES), Err(error), "{} under {}", file, name);
+        }
+    }
+}
+
+#[test]
+fn random_programs() {
+    let mut next = fuzz::xorshift(0x9E37_79B9_7F4A_7C15);
+    for _ in 0..200 {
+        let program = fuzz::random_program(&mut next);
+        fuzz::run(&program);
+    }
+    // Bytes that don't fit are refused, not truncated
+    assert!(fuzz::run_with(&[0; 4096], Quirks::CHIP8, 1).is_err());
+}
@@ -0,0 +1,422 @@
+// Libretro core, the machine runs one 60Hz frame per retro_run
+pub mod retro;
+
+use chip8_core::state::{self, STATE_SIZE};
+use chip8_core::Chip8;
+use retro::*;
+use std::os::raw::{c_char, c_uint, c_void};
+use std::slice;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const WIDTH: usize = 64;
+pub const HEIGHT: usize = 32;
+pub const FPS: f64 = 60.0;
+pub const SAMPLE_RATE: f64 = 44100.0;
+pub const SAMPLES_PER_FRAME: usize = 735;
+const TICKS_PER_FRAME: u32 = 9;
+const TONE_HZ: usize = 440;
+const VOLUME: i16 = 0x1800;
+const LIT: u32 = 0x00FF_FFFF;
+
+// RetroPad button id to keypad key, directions on 2/4/6/8 and A on 5
+// like most games expect, the rest fill the remaining keys
+pub const JOYPAD_KEYS: [(c_uint, usize); 16] = [
+    (RETRO_DEVICE_ID_JOYPAD_UP, 0x2),
+    (RETRO_DEVICE_ID_JOYPAD_DOWN, 0x8),
+    (RETRO_DEVICE_ID_JOYPAD_LEFT, 0x4),
+    (RETRO_DEVICE_ID_JOYPAD_RIGHT, 0x6),
+    (RETRO_DEVICE_ID_JOYPAD_A, 0x5),
+    (RETRO_DEVICE_ID_JOYPAD_B, 0x0),
+    (RETRO_DEVICE_ID_JOYPAD_X, 0x9),
+    (RETRO_DEVICE_ID_JOYPAD_Y, 0x7),
+    (RETRO_DEVICE_ID_JOYPAD_L, 0x1),
+    (RETRO_DEVICE_ID_JOYPAD_R, 0x3),
+    (RETRO_DEVICE_ID_JOYPAD_L2, 0xA),
+    (RETRO_DEVICE_ID_JOYPAD_R2, 0xB),
+    (RETRO_DEVICE_ID_JOYPAD_L3, 0xC),
+    (RETRO_DEVICE_ID_JOYPAD_R3, 0xD),
+    (RETRO_DEVICE_ID_JOYPAD_SELECT, 0xE),
+    (RETRO_DEVICE_ID_JOYPAD_START, 0xF),
+];
+
+// RETROK codes are ascii for digits and lower case letters, same layout
+// as the SDL frontend
+pub const KEYBOARD_KEYS: [(c_uint, usize); 16] = [
+    (b'1' as c_uint, 0x1),
+    (b'2' as c_uint, 0x2),
+    (b'3' as c_uint, 0x3),
+    (b'4' as c_uint, 0xC),
+    (b'q' as c_uint, 0x4),
+    (b'w' as c_uint, 0x5),
+    (b'e' as c_uint, 0x6),
+    (b'r' as c_uint, 0xD),
+    (b'a' as c_uint, 0x7),
+    (b's' as c_uint, 0x8),
+    (b'd' as c_uint, 0x9),
+    (b'f' as c_uint, 0xE),
+    (b'z' as c_uint, 0xA),
+    (b'x' as c_uint, 0x0),
+    (b'c' as c_uint, 0xB),
+    (b'v' as c_uint, 0xF),
+];
+
+struct Callbacks {
+    environment: Option<retro_environment_t>,
+    video: Option<retro_video_refresh_t>,
+    audio_batch: Option<retro_audio_sample_batch_t>,
+    input_poll: Option<retro_input_poll_t>,
+    input_state: Option<retro_input_state_t>,
+}
+
+static CALLBACKS: Mutex<Callbacks> = Mutex::new(Callbacks {
+    environment: None,
+    video: None,
+    audio_batch: None,
+    input_poll: None,
+    input_state: None,
+});
+
+struct Core {
+    chip8: Chip8,
+    program: Vec<u8>,
+    // Device plugged into ports 0 and 1
+    devices: [c_uint; 2],
+    frame: Vec<u32>,
+    // Square wave position in samples
+    phase: usize,
+}
+
+// None until a game is loaded
+static CORE: Mutex<Option<Core>> = Mutex::new(None);
+
+fn seeded_chip() -> Chip8 {
+    let seed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or(0);
+    Chip8::with_seed(seed)
+}
+
+// Keys held on the joypads and the keyboard, a port set to
+// RETRO_DEVICE_NONE or the keyboard isn't read as a joypad
+pub fn read_keypad(devices: [c_uint; 2], pressed: impl Fn(c_uint, c_uint, c_uint) -> bool) -> [bool; 16] {
+    let mut keypad = [false; 16];
+    for (port, &device) in devices.iter().enumerate() {
+        if device == RETRO_DEVICE_JOYPAD {
+            for &(id, key) in JOYPAD_KEYS.iter() {
+                keypad[key] |= pressed(port as c_uint, RETRO_DEVICE_JOYPAD, id);
+            }
+        }
+    }
+    for &(id, key) in KEYBOARD_KEYS.iter() {
+        keypad[key] |= pressed(0, RETRO_DEVICE_KEYBOARD, id);
+    }
+    keypad
+}
+
+// One frame of interleaved stereo, a square wave while the sound timer runs
+pub fn tone(playing: bool, phase: &mut usize) -> [i16; SAMPLES_PER_FRAME * 2] {
+    let mut samples = [0; SAMPLES_PER_FRAME * 2];
+    if !playing {
+        *phase = 0;
+        return samples;
+    }
+    let period = SAMPLE_RATE as usize / TONE_HZ;
+    for frame in samples.chunks_mut(2) {
+        let level = if *phase < period / 2 { VOLUME } else { -VOLUME };
+        frame.fill(level);
+        *phase = (*phase + 1) % period;
+    }
+    samples
+}
+
+#[no_mangle]
+pub extern "C" fn retro_api_version() -> c_uint {
+    RETRO_API_VERSION
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_environment(callback: retro_environment_t) {
+    CALLBACKS.lock().unwrap().environment = Some(callback);
+    let mut no_game = false;
+    callback(RETRO_ENVIRONMENT_SET_SUPPORT_NO_GAME, &mut no_game as *mut bool as *mut c_void);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_video_refresh(callback: retro_video_refresh_t) {
+    CALLBACKS.lock().unwrap().video = Some(callback);
+}
+
+// Audio goes out in batches, single samples are never used
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample(_callback: retro_audio_sample_t) {}
+
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample_batch(callback: retro_audio_sample_batch_t) {
+    CALLBACKS.lock().unwrap().audio_batch = Some(callback);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_poll(callback: retro_input_poll_t) {
+    CALLBACKS.lock().unwrap().input_poll = Some(callback);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_state(callback: retro_input_state_t) {
+    CALLBACKS.lock().unwrap().input_state = Some(callback);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_init() {}
+
+#[no_mangle]
+pub extern "C" fn retro_deinit() {
+    *CORE.lock().unwrap() = None;
+}
+
+/// # Safety
+/// `info` must point to a writable retro_system_info
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_info(info: *mut retro_system_info) {
+    *info = retro_system_info {
+        library_name: c"chip8_emu".as_ptr(),
+        library_version: c"0.1.1".as_ptr(),
+        valid_extensions: c"ch8|c8".as_ptr(),
+        need_fullpath: false,
+        block_extract: false,
+    };
+}
+
+/// # Safety
+/// `info` must point to a writable retro_system_av_info
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_av_info(info: *mut retro_system_av_info) {
+    *info = retro_system_av_info {
+        geometry: retro_game_geometry {
+            base_width: WIDTH as c_uint,
+            base_height: HEIGHT as c_uint,
+            max_width: WIDTH as c_uint,
+            max_height: HEIGHT as c_uint,
+            aspect_ratio: 2.0,
+        },
+        timing: retro_system_timing { fps: FPS, sample_rate: SAMPLE_RATE },
+    };
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_controller_port_device(port: c_uint, device: c_uint) {
+    if let Some(core) = CORE.lock().unwrap().as_mut() {
+        if let Some(slot) = core.devices.get_mut(port as usize) {
+            *slot = device;
+        }
+    }
+}
+
+// Back to the freshly loaded program, memory and registers cleared
+#[no_mangle]
+pub extern "C" fn retro_reset() {
+    if let Some(core) = CORE.lock().unwrap().as_mut() {
+        let mut chip8 = seeded_chip();
+        if chip8.load_program(&core.program).is_ok() {
+            core.chip8 = chip8;
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_run() {
+    let (video, audio_batch, input_poll, input_state) = {
+        let callbacks = CALLBACKS.lock().unwrap();
+        (callbacks.video, callbacks.audio_batch, callbacks.input_poll, callbacks.input_state)
+    };
+    let mut guard = CORE.lock().unwrap();
+    let Some(core) = guard.as_mut() else {
+        return;
+    };
+
+    if let Some(poll) = input_poll {
+        poll();
+    }
+    if let Some(state) = input_state {
+        let keypad = read_keypad(core.devices, |port, device, id| state(port, device, 0, id) != 0);
+        for (key, &down) in keypad.iter().enumerate() {
+            core.chip8.set_key(key, down);
+        }
+    }
+
+    // A faulting instruction stays put, the frame repeats it until reset
+    for _ in 0..TICKS_PER_FRAME {
+        if core.chip8.cycle().is_err() {
+            break;
+        }
+    }
+    core.chip8.timer_tick();
+
+    // Always send the frame, duping is left to the frontend
+    core.chip8.take_vram_change();
+    for (y, row) in core.chip8.framebuffer().iter().enumerate() {
+        for (x, &pixel) in row.iter().enumerate() {
+            core.frame[y * WIDTH + x] = if pixel != 0 { LIT } else { 0 };
+        }
+    }
+    if let Some(video) = video {
+        video(core.frame.as_ptr() as *const c_void, WIDTH as c_uint, HEIGHT as c_uint, WIDTH * 4);
+    }
+
+    let samples = tone(core.chip8.sound_timer() > 0, &mut core.phase);
+    if let Some(audio_batch) = audio_batch {
+        audio_batch(samples.as_ptr(), SAMPLES_PER_FRAME);
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_serialize_size() -> usize {
+    STATE_SIZE
+}
+
+/// # Safety
+/// `data` must point to `size` writable bytes
+#[no_mangle]
+pub unsafe extern "C" fn retro_serialize(data: *mut c_void, size: usize) -> bool {
+    let guard = CORE.lock().unwrap();
+    let Some(core) = guard.as_ref() else {
+        return false;
+    };
+    if data.is_null() || size < STATE_SIZE {
+        return false;
+    }
+    let bytes = state::serialize(&core.chip8);
+    slice::from_raw_parts_mut(data as *mut u8, size)[..STATE_SIZE].copy_from_slice(&bytes);
+    true
+}
+
+/// # Safety
+/// `data` must point to `size` readable bytes
+#[no_mangle]
+pub unsafe extern "C" fn retro_unserialize(data: *const c_void, size: usize) -> bool {
+    let mut guard = CORE.lock().unwrap();
+    let Some(core) = guard.as_mut() else {
+        return false;
+    };
+    if data.is_null() || size < STATE_SIZE {
+        return false;
+    }
+    let bytes = slice::from_raw_parts(data as *const u8, size);
+    match state::deserialize(&bytes[..STATE_SIZE]) {
+        Ok(chip8) => {
+            core.chip8 = chip8;
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_cheat_reset() {}
+
+#[no_mangle]
+pub extern "C" fn retro_cheat_set(_index: c_uint, _enabled: bool, _code: *const c_char) {}
+
+/// # Safety
+/// `game` must be null or point to a retro_game_info whose data holds
+/// `size` bytes
+#[no_mangle]
+pub unsafe extern "C" fn retro_load_game(game: *const retro_game_info) -> bool {
+    if game.is_null() || (*game).data.is_null() {
+        return false;
+    }
+    let program = slice::from_raw_parts((*game).data as *const u8, (*game).size).to_vec();
+    let mut chip8 = seeded_chip();
+    if chip8.load_program(&program).is_err() {
+        return false;
+    }
+
+    let environment = CALLBACKS.lock().unwrap().environment;
+    if let Some(environment) = environment {
+        let mut format = RETRO_PIXEL_FORMAT_XRGB8888;
+        if !environment(RETRO_ENVIRONMENT_SET_PIXEL_FORMAT, &mut format as *mut c_uint as *mut c_void) {
+            return false;
+        }
+    }
+
+    *CORE.lock().unwrap() = Some(Core {
+        chip8,
+        program,
+        devices: [RETRO_DEVICE_JOYPAD, RETRO_DEVICE_JOYPAD],
+        frame: vec![0; WIDTH * HEIGHT],
+        phase: 0,
+    });
+    true
+}
+
+#[no_mangle]
+pub extern "C" fn retro_load_game_special(
+    _game_type: c_uint,
+    _info: *const retro_game_info,
+    _num_info: usize,
+) -> bool {
+    false
+}
+
+#[no_mangle]
+pub extern "C" fn retro_unload_game() {
+    *CORE.lock().unwrap() = None;
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_region() -> c_uint {
+    RETRO_REGION_NTSC
+}
+
+// Memory is only reachable through the Chip8 accessors, nothing to expose
+#[no_mangle]
+pub extern "C" fn retro_get_memory_data(_id: c_uint) -> *mut c_void {
+    std::ptr::null_mut()
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_memory_size(_id: c_uint) -> usize {
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_key_is_mapped_once() {
+        for table in [JOYPAD_KEYS, KEYBOARD_KEYS] {
+            let mut keys: Vec<usize> = table.iter().map(|&(_, key)| key).collect();
+            keys.sort();
+            assert_eq!(keys, (0..16).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn keypad_from_devices() {
+        let pressed = |port, device, id| match device {
+            RETRO_DEVICE_JOYPAD => port == 1 && id == RETRO_DEVICE_ID_JOYPAD_A,
+            RETRO_DEVICE_KEYBOARD => id == b'v' as c_uint,
+            _ => false,
+        };
+        let keypad = read_keypad([RETRO_DEVICE_JOYPAD; 2], pressed);
+        assert!(keypad[0x5] && keypad[0xF]);
+        assert_eq!(keypad.iter().filter(|&&k| k).count(), 2);
+
+        // Port 1 unplugged
+        let keypad = read_keypad([RETRO_DEVICE_JOYPAD, RETRO_DEVICE_NONE], pressed);
+        assert!(!keypad[0x5] && keypad[0xF]);
+    }
+
+    #[test]
+    fn tone_only_while_playing() {
+        let mut phase = 0;
+        assert!(tone(false, &mut phase).iter().all(|&s| s == 0));
+        let samples = tone(true, &mut phase);
+        assert_eq!(samples[0], VOLUME);
+        assert_eq!(samples[0], samples[1]);
+        assert!(samples.contains(&-VOLUME));
+        assert_ne!(phase, 0);
+    }
+}
@@ -0,0 +1,135 @@
+// Drives the core through its C entry points the way a frontend would
+use chip8_libretro::retro::*;
+use chip8_libretro::*;
+use std::ffi::CStr;
+use std::os::raw::{c_uint, c_void};
+use std::ptr;
+use std::sync::Mutex;
+
+static FRAME: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+static AUDIO: Mutex<Vec<i16>> = Mutex::new(Vec::new());
+static ENVIRONMENT: Mutex<Vec<(c_uint, c_uint)>> = Mutex::new(Vec::new());
+static QUERIES: Mutex<Vec<(c_uint, c_uint)>> = Mutex::new(Vec::new());
+
+extern "C" fn environment(cmd: c_uint, data: *mut c_void) -> bool {
+    let value = match cmd {
+        RETRO_ENVIRONMENT_SET_PIXEL_FORMAT => unsafe { *(data as *const c_uint) },
+        _ => unsafe { *(data as *const bool) as c_uint },
+    };
+    ENVIRONMENT.lock().unwrap().push((cmd, value));
+    cmd == RETRO_ENVIRONMENT_SET_PIXEL_FORMAT
+}
+
+extern "C" fn video(data: *const c_void, width: c_uint, height: c_uint, pitch: usize) {
+    assert_eq!((width, height, pitch), (64, 32, 256));
+    let pixels = unsafe { std::slice::from_raw_parts(data as *const u32, 64 * 32) };
+    *FRAME.lock().unwrap() = pixels.to_vec();
+}
+
+extern "C" fn audio_sample(_left: i16, _right: i16) {}
+
+extern "C" fn audio_batch(data: *const i16, frames: usize) -> usize {
+    let samples = unsafe { std::slice::from_raw_parts(data, frames * 2) };
+    *AUDIO.lock().unwrap() = samples.to_vec();
+    frames
+}
+
+extern "C" fn input_poll() {}
+
+extern "C" fn input_state(port: c_uint, device: c_uint, _index: c_uint, _id: c_uint) -> i16 {
+    QUERIES.lock().unwrap().push((port, device));
+    0
+}
+
+// LD V0, 0; LD F, V0; DRW V0, V0, 5; LD V1, 10; LD ST, V1; JP 0x20A
+const PROGRAM: [u8; 12] = [0x60, 0x00, 0xF0, 0x29, 0xD0, 0x05, 0x61, 0x0A, 0xF1, 0x18, 0x12, 0x0A];
+
+#[test]
+fn frontend_lifecycle() {
+    assert_eq!(retro_api_version(), RETRO_API_VERSION);
+    retro_set_environment(environment);
+    retro_set_video_refresh(video);
+    retro_set_audio_sample(audio_sample);
+    retro_set_audio_sample_batch(audio_batch);
+    retro_set_input_poll(input_poll);
+    retro_set_input_state(input_state);
+    retro_init();
+
+    unsafe {
+        let mut info = std::mem::zeroed::<retro_system_info>();
+        retro_get_system_info(&mut info);
+        assert_eq!(CStr::from_ptr(info.valid_extensions).to_str(), Ok("ch8|c8"));
+        assert!(!info.need_fullpath);
+
+        let mut av = std::mem::zeroed::<retro_system_av_info>();
+        retro_get_system_av_info(&mut av);
+        assert_eq!((av.geometry.base_width, av.geometry.base_height), (64, 32));
+        assert_eq!(av.timing.fps, FPS);
+    }
+
+    // Nothing loaded yet
+    retro_run();
+    assert!(FRAME.lock().unwrap().is_empty());
+
+    let game = retro_game_info {
+        path: ptr::null(),
+        data: PROGRAM.as_ptr() as *const c_void,
+        size: PROGRAM.len(),
+        meta: ptr::null(),
+    };
+    assert!(unsafe { retro_load_game(&game) });
+    assert!(ENVIRONMENT
+        .lock()
+        .unwrap()
+        .contains(&(RETRO_ENVIRONMENT_SET_PIXEL_FORMAT, RETRO_PIXEL_FORMAT_XRGB8888)));
+
+    retro_run();
+    {
+        // Top row of the font's 0 is 0xF0
+        let frame = FRAME.lock().unwrap();
+        assert!(frame[..4].iter().all(|&p| p != 0));
+        assert_eq!(frame[4], 0);
+        let audio = AUDIO.lock().unwrap();
+        assert_eq!(audio.len(), SAMPLES_PER_FRAME * 2);
+        assert!(audio.iter().any(|&s| s != 0));
+    }
+    let queries = QUERIES.lock().unwrap().clone();
+    assert!(queries.contains(&(0, RETRO_DEVICE_JOYPAD)));
+    assert!(queries.contains(&(0, RETRO_DEVICE_KEYBOARD)));
+
+    // Unplugged port is no longer read as a joypad
+    retro_set_controller_port_device(0, RETRO_DEVICE_NONE);
+    QUERIES.lock().unwrap().clear();
+    retro_run();
+    assert!(!QUERIES.lock().unwrap().contains(&(0, RETRO_DEVICE_JOYPAD)));
+
+    // Save, run on, load and get the same state back
+    let size = retro_serialize_size();
+    let mut saved = vec![0u8; size];
+    assert!(unsafe { retro_serialize(saved.as_mut_ptr() as *mut c_void, size) });
+    assert!(!unsafe { retro_serialize(saved.as_mut_ptr() as *mut c_void, size - 1) });
+    for _ in 0..20 {
+        retro_run();
+    }
+    assert!(AUDIO.lock().unwrap().iter().all(|&s| s == 0));
+
+    assert!(unsafe { retro_unserialize(saved.as_ptr() as *const c_void, size) });
+    let mut again = vec![0u8; size];
+    assert!(unsafe { retro_serialize(again.as_mut_ptr() as *mut c_void, size) });
+    assert_eq!(saved, again);
+
+    let garbage = vec![0u8; size];
+    assert!(!unsafe { retro_unserialize(garbage.as_ptr() as *const c_void, size) });
+
+    // Reset runs the program from the start, sound comes back on
+    for _ in 0..20 {
+        retro_run();
+    }
+    retro_reset();
+    retro_run();
+    assert!(AUDIO.lock().unwrap().iter().any(|&s| s != 0));
+
+    retro_unload_game();
+    assert!(!unsafe { retro_serialize(saved.as_mut_ptr() as *mut c_void, size) });
+    retro_deinit();
+}
@@ -0,0 +1,119 @@
+use std::time::{Duration, Instant};
+
+// Terminals only report presses, a held key shows up as auto repeat
+// Anything not pressed again within this long counts as released, long
+// enough to bridge the delay before auto repeat starts
+pub const HOLD_TIME: Duration = Duration::from_millis(500);
+// Between repeats a key only has to survive the repeat interval
+pub const REPEAT_TIME: Duration = Duration::from_millis(100);
+
+#[derive(Clone, Copy)]
+struct Held {
+    since: Instant,
+    last: Instant,
+}
+
+// Key release heuristic for the 16 key keypad
+#[derive(Default)]
+pub struct Keys {
+    held: [Option<Held>; 16],
+}
+
+impl Keys {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn press(&mut self, key: usize, now: Instant) {
+        self.held[key] = Some(match self.held[key] {
+            Some(held) => Held { since: held.since, last: now },
+            None => Held { since: now, last: now },
+        });
+    }
+
+    // Terminals with the kitty keyboard protocol do report releases
+    pub fn release(&mut self, key: usize) {
+        self.held[key] = None;
+    }
+
+    // Drop keys that timed out, returns the keypad as it stands
+    pub fn update(&mut self, now: Instant) -> [bool; 16] {
+        for slot in self.held.iter_mut() {
+            if let Some(held) = *slot {
+                // One press, or repeats already going
+                let timeout = if held.last == held.since { HOLD_TIME } else { REPEAT_TIME };
+                if now.saturating_duration_since(held.last) > timeout {
+                    *slot = None;
+                }
+            }
+        }
+        self.held.map(|held| held.is_some())
+    }
+}
+
+// Same layout as the SDL frontend
+//   1 2 3 4      1 2 3 C
+//   Q W E R  ->  4 5 6 D
+//   A S D F      7 8 9 E
+//   Z X C V      A 0 B F
+pub fn match_key(c: char) -> Option<usize> {
+    match c.to_ascii_lowercase() {
+        '1' => Some(0x1),
+        '2' => Some(0x2),
+        '3' => Some(0x3),
+        '4' => Some(0xC),
+        'q' => Some(0x4),
+        'w' => Some(0x5),
+        'e' => Some(0x6),
+        'r' => Some(0xD),
+        'a' => Some(0x7),
+        's' => Some(0x8),
+        'd' => Some(0x9),
+        'f' => Some(0xE),
+        'z' => Some(0xA),
+        'x' => Some(0x0),
+        'c' => Some(0xB),
+        'v' => Some(0xF),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn single_press_releases_after_hold_time() {
+        let mut keys = Keys::new();
+        let start = Instant::now();
+        keys.press(5, start);
+        assert!(keys.update(start + HOLD_TIME / 2)[5]);
+        assert!(!keys.update(start + HOLD_TIME + Duration::from_millis(1))[5]);
+    }
+
+    #[test]
+    fn repeats_keep_key_held() {
+        let mut keys = Keys::new();
+        let start = Instant::now();
+        keys.press(5, start);
+        let mut now = start;
+        for _ in 0..20 {
+            now += Duration::from_millis(50);
+            keys.press(5, now);
+            assert!(keys.update(now)[5]);
+        }
+        // Once repeating, a short gap is enough to count as released
+        assert!(!keys.update(now + REPEAT_TIME + Duration::from_millis(1))[5]);
+    }
+
+    #[test]
+    fn explicit_release() {
+        let mut keys = Keys::new();
+        let now = Instant::now();
+        keys.press(0xF, now);
+        keys.release(0xF);
+        assert!(!keys.update(now)[0xF]);
+        assert_eq!(match_key('V'), Some(0xF));
+        assert_eq!(match_key('p'), None);
+    }
+}
@@ -0,0 +1,211 @@
+mod input;
+mod render;
+
+use chip8_core::rewind::Rewind;
+use chip8_core::Chip8;
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::{cursor, queue, style, terminal};
+use input::{match_key, Keys};
+use std::io::{self, Write};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::{env, fs, process, thread};
+
+const FRAME_TIME: Duration = Duration::from_micros(16_667);
+const REWIND_FRAMES: u32 = 30;
+
+// Terminal frontend for ssh sessions, no window or SDL needed
+//   chip8_tui rom [--braille]
+fn main() {
+    let mut rom = None;
+    let mut braille = false;
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--braille" => braille = true,
+            other if other.starts_with("--") => fail(&format!("unknown option {}", other)),
+            _ => rom = Some(arg),
+        }
+    }
+    let Some(rom) = rom else {
+        fail("usage: chip8_tui rom [--braille]");
+    };
+
+    let program = fs::read(&rom).unwrap_or_else(|e| fail(&format!("Unable to read {}: {}", rom, e)));
+    let seed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or(0);
+    let mut chip8 = Chip8::with_seed(seed);
+    if let Err(e) = chip8.load_program(&program) {
+        fail(&format!("Unable to load {}: {}", rom, e));
+    }
+
+    let result = Terminal::enter().and_then(|_terminal| Frontend::new(chip8, braille).run());
+    if let Err(e) = result {
+        fail(&format!("Terminal error: {}", e));
+    }
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("{}", message);
+    process::exit(2);
+}
+
+// Raw mode and the alternate screen for as long as this lives, restored
+// on drop so a panic doesn't leave the shell unusable
+struct Terminal;
+
+impl Terminal {
+    fn enter() -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        let mut out = io::stdout();
+        queue!(out, terminal::EnterAlternateScreen, cursor::Hide, terminal::Clear(terminal::ClearType::All))?;
+        out.flush()?;
+        Ok(Terminal)
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let mut out = io::stdout();
+        let _ = queue!(out, cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = out.flush();
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+struct Frontend {
+    chip8: Chip8,
+    keys: Keys,
+    rewind: Rewind,
+    braille: bool,
+    paused: bool,
+    fault: Option<String>,
+    ticks_per_frame: u16,
+}
+
+impl Frontend {
+    fn new(chip8: Chip8, braille: bool) -> Self {
+        Self {
+            chip8,
+            keys: Keys::new(),
+            rewind: Rewind::new(),
+            braille,
+            paused: false,
+            fault: None,
+            ticks_per_frame: 9,
+        }
+    }
+
+    fn run(&mut self) -> io::Result<()> {
+        let mut out = io::stdout();
+        let mut frames = 0;
+        let mut redraw = true;
+        self.rewind.capture(&self.chip8);
+
+        loop {
+            let frame_start = Instant::now();
+            while event::poll(Duration::ZERO)? {
+                match event::read()? {
+                    Event::Key(key) => {
+                        if !self.handle_key(key.code, key.modifiers, key.kind) {
+                            return Ok(());
+                        }
+                        redraw = true;
+                    }
+                    Event::Resize(..) => {
+                        queue!(out, terminal::Clear(terminal::ClearType::All))?;
+                        redraw = true;
+                    }
+                    _ => (),
+                }
+            }
+
+            let keypad = self.keys.update(Instant::now());
+            if !self.paused {
+                for (key, &down) in keypad.iter().enumerate() {
+                    self.chip8.set_key(key, down);
+                }
+                for _ in 0..self.ticks_per_frame {
+                    if let Err(fault) = self.chip8.cycle() {
+                        self.fault = Some(fault.to_string());
+                        self.paused = true;
+                        redraw = true;
+                        break;
+                    }
+                }
+                self.chip8.timer_tick();
+
+                frames += 1;
+                if frames >= REWIND_FRAMES {
+                    self.rewind.capture(&self.chip8);
+                    frames = 0;
+                }
+            }
+
+            if self.chip8.take_vram_change() || redraw {
+                self.draw(&mut out)?;
+                redraw = false;
+            }
+            thread::sleep(FRAME_TIME.saturating_sub(frame_start.elapsed()));
+        }
+    }
+
+    // False to quit
+    fn handle_key(&mut self, code: KeyCode, modifiers: KeyModifiers, kind: KeyEventKind) -> bool {
+        if kind == KeyEventKind::Release {
+            if let KeyCode::Char(c) = code {
+                if let Some(key) = match_key(c) {
+                    self.keys.release(key);
+                }
+            }
+            return true;
+        }
+
+        match code {
+            KeyCode::Esc => return false,
+            KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => return false,
+            KeyCode::Char(' ') => self.paused = !self.paused,
+            KeyCode::Left | KeyCode::Backspace => {
+                self.chip8 = self.rewind.step_back();
+                self.fault = None;
+            }
+            KeyCode::Up => self.ticks_per_frame += 1,
+            KeyCode::Down if self.ticks_per_frame > 1 => self.ticks_per_frame -= 1,
+            KeyCode::Char(c) => {
+                if let Some(key) = match_key(c) {
+                    self.keys.press(key, Instant::now());
+                }
+            }
+            _ => (),
+        }
+        true
+    }
+
+    fn draw(&self, out: &mut impl Write) -> io::Result<()> {
+        let lines = if self.braille {
+            render::braille(self.chip8.framebuffer())
+        } else {
+            render::half_blocks(self.chip8.framebuffer())
+        };
+        for (row, line) in lines.iter().enumerate() {
+            queue!(out, cursor::MoveTo(0, row as u16), style::Print(line))?;
+        }
+
+        let state = match (&self.fault, self.paused) {
+            (Some(fault), _) => format!("fault: {}", fault),
+            (None, true) => "paused".to_string(),
+            (None, false) => "running".to_string(),
+        };
+        let status = format!(
+            "{} ticks/frame  {}  space pause, left rewind, esc quit",
+            self.ticks_per_frame, state
+        );
+        queue!(
+            out,
+            cursor::MoveTo(0, lines.len() as u16),
+            terminal::Clear(terminal::ClearType::CurrentLine),
+            style::Print(status)
+        )?;
+        out.flush()
+    }
+}
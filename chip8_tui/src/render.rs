@@ -0,0 +1,81 @@
+// Framebuffer to text, one String per terminal row
+// Works on any W x H pixel grid, 64x32 or 128x64
+
+// Two pixels per cell stacked vertically, W x H/2 cells
+pub fn half_blocks<const W: usize>(pixels: &[[u8; W]]) -> Vec<String> {
+    pixels
+        .chunks(2)
+        .map(|pair| {
+            (0..W)
+                .map(|x| {
+                    let top = pair[0][x] != 0;
+                    let bottom = pair.get(1).is_some_and(|row| row[x] != 0);
+                    match (top, bottom) {
+                        (true, true) => '█',
+                        (true, false) => '▀',
+                        (false, true) => '▄',
+                        (false, false) => ' ',
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+// Braille dot bits, indexed [row][column] inside a 2x4 cell
+const DOTS: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+
+// Eight pixels per cell, W/2 x H/4 cells, small enough for a split pane
+pub fn braille<const W: usize>(pixels: &[[u8; W]]) -> Vec<String> {
+    pixels
+        .chunks(4)
+        .map(|rows| {
+            (0..W.div_ceil(2))
+                .map(|cx| {
+                    let mut bits = 0;
+                    for (row, dots) in rows.iter().zip(DOTS.iter()) {
+                        for (dx, dot) in dots.iter().enumerate() {
+                            let x = cx * 2 + dx;
+                            if x < W && row[x] != 0 {
+                                bits |= dot;
+                            }
+                        }
+                    }
+                    char::from_u32(0x2800 + bits).unwrap()
+                })
+                .collect()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn half_block_cells() {
+        let mut pixels = [[0u8; 64]; 32];
+        pixels[0][0] = 1;
+        pixels[1][1] = 1;
+        pixels[0][2] = 1;
+        pixels[1][2] = 1;
+        let lines = half_blocks(&pixels);
+        assert_eq!(lines.len(), 16);
+        assert!(lines[0].starts_with("▀▄█ "));
+        assert_eq!(lines[0].chars().count(), 64);
+        assert!(lines[1].chars().all(|c| c == ' '));
+    }
+
+    #[test]
+    fn braille_cells() {
+        let mut pixels = [[0u8; 128]; 64];
+        pixels[0][0] = 1;
+        pixels[3][1] = 1;
+        pixels[4][2] = 1;
+        let lines = braille(&pixels);
+        assert_eq!(lines.len(), 16);
+        assert_eq!(lines[0].chars().count(), 64);
+        assert!(lines[0].starts_with("⢁⠀"));
+        assert!(lines[1].starts_with("⠀⠁"));
+    }
+}
@@ -0,0 +1,8 @@
+#![no_main]
+// Every input is a rom run under every quirk profile, crashes go in
+// chip8_core/tests/regressions
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    chip8_core::fuzz::run(data);
+});
@@ -0,0 +1,220 @@
+use chip8_core::Quirks;
+use chip8_core::trace::TraceFilter;
+
+// Command line options
+//   chip8_emu [rom] [--trace file] [--trace-pc 200-2FF]
+//             [--trace-class 8,D] [--trace-cycles 1000-2000]
+//             [--profile prefix] [--sanitize] [--gdb port]
+//             [--script file.rhai] [--headless]
+//   chip8_emu sprites rom [--from 200] [--len 100] [--height 8]
+//             [--cycles 60000] [--png sheet.png]
+//   chip8_emu diff rom [--cycles 1000000] [--quirks vip] [--seed 1]
+#[derive(Default)]
+pub struct Args {
+    pub rom: Option<String>,
+    pub trace: Option<String>,
+    pub trace_filter: TraceFilter,
+    // Writes prefix.txt and prefix.folded on exit
+    pub profile: Option<String>,
+    // Report uninitialised reads, executed data and the like
+    pub sanitize: bool,
+    // Serve the gdb remote protocol on localhost
+    pub gdb: Option<u16>,
+    pub script: Option<String>,
+    // Run the script without a window
+    pub headless: bool,
+    pub sprites: Option<SpriteArgs>,
+    pub diff: Option<DiffArgs>,
+}
+
+// Sprite inspector subcommand, runs the rom headless to find out what
+// gets drawn, len defaults to the rest of the rom
+pub struct SpriteArgs {
+    pub from: usize,
+    pub len: Option<usize>,
+    pub height: usize,
+    pub cycles: u64,
+    pub png: Option<String>,
+}
+
+impl Default for SpriteArgs {
+    fn default() -> Self {
+        Self { from: 0x200, len: None, height: 8, cycles: 60000, png: None }
+    }
+}
+
+// Differential test subcommand, runs the rom against the reference
+// interpreter, under every quirk profile unless one is given
+pub struct DiffArgs {
+    pub cycles: u64,
+    pub quirks: Option<Quirks>,
+    // Cxkk seed and the random key presses
+    pub seed: u32,
+}
+
+impl Default for DiffArgs {
+    fn default() -> Self {
+        Self { cycles: 1_000_000, quirks: None, seed: 1 }
+    }
+}
+
+impl Args {
+    pub fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Self, String> {
+        let mut parsed = Args::default();
+        let mut first = true;
+        while let Some(arg) = args.next() {
+            if std::mem::take(&mut first) {
+                match arg.as_str() {
+                    "sprites" => {
+                        parsed.sprites = Some(SpriteArgs::default());
+                        continue;
+                    }
+                    "diff" => {
+                        parsed.diff = Some(DiffArgs::default());
+                        continue;
+                    }
+                    _ => (),
+                }
+            }
+            let mut value = |name: &str| {
+                args.next().ok_or_else(|| format!("{} needs a value", name))
+            };
+            match arg.as_str() {
+                "--trace" => parsed.trace = Some(value("--trace")?),
+                "--profile" => parsed.profile = Some(value("--profile")?),
+                "--sanitize" => parsed.sanitize = true,
+                "--script" => parsed.script = Some(value("--script")?),
+                "--headless" => parsed.headless = true,
+                "--gdb" => {
+                    let port = value("--gdb")?;
+                    parsed.gdb = Some(port.parse().map_err(|_| format!("bad port {}", port))?);
+                }
+                "--trace-pc" => {
+                    let (start, end) = parse_range(&value("--trace-pc")?, 16)?;
+                    parsed.trace_filter.addresses = Some((start as u16, end as u16));
+                }
+                "--trace-class" => {
+                    let mut mask = 0u16;
+                    for class in value("--trace-class")?.split(',') {
+                        let nibble = u8::from_str_radix(class.trim(), 16)
+                            .ok()
+                            .filter(|&n| n <= 0xF)
+                            .ok_or_else(|| format!("bad opcode class {}", class))?;
+                        mask |= 1 << nibble;
+                    }
+                    parsed.trace_filter.classes = Some(mask);
+                }
+                "--trace-cycles" => {
+                    parsed.trace_filter.cycles = Some(parse_range(&value("--trace-cycles")?, 10)?);
+                }
+                "--from" | "--len" | "--height" | "--cycles" | "--png" if parsed.sprites.is_some() => {
+                    let text = value(&arg)?;
+                    let number = |radix| {
+                        usize::from_str_radix(text.trim_start_matches("0x"), radix)
+                            .map_err(|_| format!("bad number {}", text))
+                    };
+                    let sprites = parsed.sprites.as_mut().unwrap();
+                    match arg.as_str() {
+                        "--from" => sprites.from = number(16)?,
+                        "--len" => sprites.len = Some(number(16)?),
+                        "--height" => sprites.height = number(10)?.clamp(1, 15),
+                        "--cycles" => sprites.cycles = number(10)? as u64,
+                        _ => sprites.png = Some(text),
+                    }
+                }
+                "--cycles" | "--quirks" | "--seed" if parsed.diff.is_some() => {
+                    let text = value(&arg)?;
+                    let diff = parsed.diff.as_mut().unwrap();
+                    match arg.as_str() {
+                        "--cycles" => diff.cycles = text.parse().map_err(|_| format!("bad number {}", text))?,
+                        "--quirks" => {
+                            let quirks = Quirks::by_name(&text).ok_or_else(|| format!("unknown quirk profile {}", text))?;
+                            diff.quirks = Some(quirks);
+                        }
+                        _ => diff.seed = text.parse().map_err(|_| format!("bad seed {}", text))?,
+                    }
+                }
+                other if other.starts_with("--") => return Err(format!("unknown option {}", other)),
+                _ => parsed.rom = Some(arg),
+            }
+        }
+        Ok(parsed)
+    }
+}
+
+// "start-end" inclusive, a single number is a range of one
+fn parse_range(text: &str, radix: u32) -> Result<(u64, u64), String> {
+    let number = |s: &str| {
+        u64::from_str_radix(s.trim().trim_start_matches("0x"), radix)
+            .map_err(|_| format!("bad range {}", text))
+    };
+    let (start, end) = match text.split_once('-') {
+        Some((start, end)) => (number(start)?, number(end)?),
+        None => (number(text)?, number(text)?),
+    };
+    if start > end {
+        return Err(format!("bad range {}", text));
+    }
+    Ok((start, end))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> Result<Args, String> {
+        Args::parse(line.split_whitespace().map(String::from))
+    }
+
+    #[test]
+    fn trace_options() {
+        let args = parse("roms/pong.ch8 --trace out.log --trace-pc 200-2FF --trace-class 8,d --trace-cycles 10-20").unwrap();
+        assert_eq!(args.rom.as_deref(), Some("roms/pong.ch8"));
+        assert_eq!(args.trace.as_deref(), Some("out.log"));
+        assert_eq!(args.trace_filter.addresses, Some((0x200, 0x2FF)));
+        assert_eq!(args.trace_filter.classes, Some((1 << 8) | (1 << 0xD)));
+        assert_eq!(args.trace_filter.cycles, Some((10, 20)));
+        assert!(args.profile.is_none());
+        assert_eq!(parse("--profile out/pong").unwrap().profile.as_deref(), Some("out/pong"));
+        assert!(!args.sanitize);
+        assert!(parse("pong.ch8 --sanitize").unwrap().sanitize);
+        assert_eq!(parse("pong.ch8 --gdb 1234").unwrap().gdb, Some(1234));
+        let args = parse("pong.ch8 --script bot.rhai --headless").unwrap();
+        assert_eq!(args.script.as_deref(), Some("bot.rhai"));
+        assert!(args.headless);
+    }
+
+    #[test]
+    fn sprites_subcommand() {
+        let args = parse("sprites roms/pong.ch8 --from 2A0 --len 40 --height 5 --png out.png").unwrap();
+        assert_eq!(args.rom.as_deref(), Some("roms/pong.ch8"));
+        let sprites = args.sprites.unwrap();
+        assert_eq!((sprites.from, sprites.len, sprites.height), (0x2A0, Some(0x40), 5));
+        assert_eq!(sprites.cycles, 60000);
+        assert_eq!(sprites.png.as_deref(), Some("out.png"));
+
+        // Only after the subcommand, and a rom called sprites still runs
+        assert!(parse("pong.ch8 --height 5").is_err());
+        assert!(parse("roms/sprites").unwrap().sprites.is_none());
+    }
+
+    #[test]
+    fn diff_subcommand() {
+        let args = parse("diff roms/pong.ch8 --cycles 5000 --quirks SCHIP --seed 7").unwrap();
+        assert_eq!(args.rom.as_deref(), Some("roms/pong.ch8"));
+        let diff = args.diff.unwrap();
+        assert_eq!((diff.cycles, diff.quirks, diff.seed), (5000, Some(Quirks::SCHIP), 7));
+        assert_eq!(parse("diff pong.ch8").unwrap().diff.unwrap().quirks, None);
+        assert!(parse("diff pong.ch8 --quirks chip48").is_err());
+        assert!(parse("diff pong.ch8 --height 5").is_err());
+    }
+
+    #[test]
+    fn rejects_bad_options() {
+        assert!(parse("--trace").is_err());
+        assert!(parse("--trace-pc 300-200").is_err());
+        assert!(parse("--trace-class 10").is_err());
+        assert!(parse("--bogus").is_err());
+        assert!(parse("--gdb 70000").is_err());
+    }
+}
@@ -0,0 +1,110 @@
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+
+// The beep while the sound timer runs, a square wave at tone Hz
+pub struct SquareWave {
+    rate: f32,
+    tone: u32,
+    volume: f32,
+    on: bool,
+    phase: f32,
+}
+
+impl SquareWave {
+    fn new(rate: i32) -> Self {
+        Self { rate: rate as f32, tone: 440, volume: 0.0, on: false, phase: 0.0 }
+    }
+}
+
+impl AudioCallback for SquareWave {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        let step = self.tone as f32 / self.rate;
+        for sample in out.iter_mut() {
+            *sample = match (self.on, self.phase < 0.5) {
+                (false, _) => 0.0,
+                (true, true) => self.volume,
+                (true, false) => -self.volume,
+            };
+            self.phase = (self.phase + step) % 1.0;
+        }
+    }
+}
+
+// Plays the beep, silent if the system has no audio device
+pub struct AudioDriver {
+    device: Option<AudioDevice<SquareWave>>,
+    // Percent, 0 turns the beep off
+    volume: u8,
+    tone: u32,
+}
+
+impl AudioDriver {
+    pub fn new(sdl_context: &sdl2::Sdl) -> Self {
+        let spec = AudioSpecDesired { freq: Some(44100), channels: Some(1), samples: Some(512) };
+        let device = sdl_context
+            .audio()
+            .and_then(|audio| audio.open_playback(None, &spec, |spec| SquareWave::new(spec.freq)))
+            .map_err(|e| println!("No sound: {}", e))
+            .ok();
+        if let Some(device) = &device {
+            device.resume();
+        }
+        let mut driver = Self { device, volume: 0, tone: 0 };
+        driver.set_volume(50);
+        driver.set_tone(440);
+        driver
+    }
+
+    pub fn volume(&self) -> u8 {
+        self.volume
+    }
+
+    pub fn set_volume(&mut self, volume: u8) {
+        self.volume = volume.min(100);
+        if let Some(device) = &mut self.device {
+            // Full volume is a quarter of full scale, a square wave is loud
+            device.lock().volume = self.volume as f32 / 400.0;
+        }
+    }
+
+    pub fn tone(&self) -> u32 {
+        self.tone
+    }
+
+    pub fn set_tone(&mut self, tone: u32) {
+        self.tone = tone.clamp(100, 2000);
+        if let Some(device) = &mut self.device {
+            device.lock().tone = self.tone;
+        }
+    }
+
+    pub fn set_beeping(&mut self, on: bool) {
+        if let Some(device) = &mut self.device {
+            device.lock().on = on;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn square_wave() {
+        let mut wave = SquareWave::new(8000);
+        wave.volume = 0.25;
+        let mut out = [1.0; 40];
+        wave.callback(&mut out);
+        assert!(out.iter().all(|&s| s == 0.0));
+
+        // 500 Hz at 8000 samples a second, 8 high then 8 low
+        wave.on = true;
+        wave.tone = 500;
+        wave.phase = 0.0;
+        wave.callback(&mut out);
+        assert_eq!(out[..8], [0.25; 8]);
+        assert_eq!(out[8..16], [-0.25; 8]);
+        assert_eq!(out[16], 0.25);
+    }
+}
@@ -0,0 +1,279 @@
+use crate::drivers::configs::defaults::*;
+use chip8_core::Chip8;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+// How a search narrows its candidates against the last snapshot
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compare {
+    Equal(u8),
+    Changed,
+    Unchanged,
+    Increased,
+    Decreased,
+}
+
+impl Compare {
+    // "= 05", "c", "u", "+" or "-"
+    pub fn parse(words: &[&str]) -> Result<Self, String> {
+        match words {
+            ["=", value] | ["==", value] => u8::from_str_radix(value.trim_start_matches("0x"), 16)
+                .map(Compare::Equal)
+                .map_err(|_| format!("bad byte {}", value)),
+            ["c"] => Ok(Compare::Changed),
+            ["u"] => Ok(Compare::Unchanged),
+            ["+"] => Ok(Compare::Increased),
+            ["-"] => Ok(Compare::Decreased),
+            _ => Err(format!("unknown comparison {}", words.join(" "))),
+        }
+    }
+
+    fn matches(self, old: u8, new: u8) -> bool {
+        match self {
+            Compare::Equal(value) => new == value,
+            Compare::Changed => new != old,
+            Compare::Unchanged => new == old,
+            Compare::Increased => new > old,
+            Compare::Decreased => new < old,
+        }
+    }
+}
+
+// Iterative RAM search, every address is a candidate until filtered out
+pub struct Search {
+    snapshot: Vec<u8>,
+    candidates: Vec<usize>,
+}
+
+impl Search {
+    pub fn new(memory: &[u8]) -> Self {
+        Self { snapshot: memory.to_vec(), candidates: (0..memory.len()).collect() }
+    }
+
+    // Keep the candidates that pass, then snapshot memory as it is now
+    pub fn filter(&mut self, memory: &[u8], compare: Compare) -> usize {
+        let snapshot = &self.snapshot;
+        self.candidates.retain(|&a| compare.matches(snapshot[a], memory[a]));
+        self.snapshot.copy_from_slice(memory);
+        self.candidates.len()
+    }
+
+    pub fn candidates(&self) -> &[usize] {
+        &self.candidates
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cheat {
+    pub address: usize,
+    pub value: u8,
+    pub name: String,
+    pub enabled: bool,
+}
+
+// Frozen bytes written back every frame, kept in a file per rom
+#[derive(Debug)]
+pub struct Cheats {
+    list: Vec<Cheat>,
+    // Off switches every cheat without forgetting them
+    pub enabled: bool,
+    path: Option<String>,
+    // Why saving is refused, the file on disk didn't parse and would be lost
+    locked: Option<String>,
+}
+
+impl Default for Cheats {
+    fn default() -> Self {
+        Self { list: Vec::new(), enabled: true, path: None, locked: None }
+    }
+}
+
+impl Cheats {
+    // Cheat file for a rom, the file name is its CRC32 so a renamed rom
+    // keeps its cheats
+    pub fn path_for(program: &[u8]) -> String {
+        format!("{}/{:08X}.cht", CHEAT_DIR, crc32(program))
+    }
+
+    // No cheats yet, saves go to path
+    pub fn empty(path: &str) -> Self {
+        Self { path: Some(path.to_string()), ..Self::default() }
+    }
+
+    // Stands in for a file that didn't parse, saving is refused until it's
+    // fixed so the cheats in it aren't overwritten
+    pub fn broken(path: &str) -> Self {
+        let reason = format!("Not saving, {} has errors, fix or remove it first", path);
+        Self { locked: Some(reason), ..Self::default() }
+    }
+
+    // Empty if the file doesn't exist yet, saves go to path
+    pub fn load(path: &str) -> Result<Self, String> {
+        if !Path::new(path).exists() {
+            return Ok(Self::empty(path));
+        }
+        let text = fs::read_to_string(path).map_err(|e| format!("Unable to read {}: {}", path, e))?;
+        let mut cheats = Self::parse(&text).map_err(|e| format!("{}: {}", path, e))?;
+        cheats.path = Some(path.to_string());
+        Ok(cheats)
+    }
+
+    pub fn save(&self) -> Result<String, String> {
+        if let Some(reason) = &self.locked {
+            return Err(reason.clone());
+        }
+        let path = self.path.as_deref().ok_or("no rom loaded")?;
+        if let Some(dir) = Path::new(path).parent() {
+            fs::create_dir_all(dir).map_err(|e| format!("Unable to create {}: {}", dir.display(), e))?;
+        }
+        fs::write(path, self.to_text()).map_err(|e| format!("Unable to save {}: {}", path, e))?;
+        Ok(path.to_string())
+    }
+
+    // One cheat a line, "3F0 03 Infinite lives", a leading - leaves it
+    // off, # starts a comment
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut list = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (enabled, line) = match line.strip_prefix('-') {
+                Some(rest) => (false, rest.trim_start()),
+                None => (true, line),
+            };
+            let mut words = line.splitn(3, char::is_whitespace);
+            let address = words.next().and_then(|w| usize::from_str_radix(w, 16).ok()).filter(|&a| a < 4096);
+            let value = words.next().and_then(|w| u8::from_str_radix(w, 16).ok());
+            let (Some(address), Some(value)) = (address, value) else {
+                return Err(format!("line {}: expected an address and a byte", number + 1));
+            };
+            let name = words.next().unwrap_or("").trim().to_string();
+            list.push(Cheat { address, value, name, enabled });
+        }
+        Ok(Self { list, ..Self::default() })
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        for cheat in self.list.iter() {
+            let off = if cheat.enabled { "" } else { "-" };
+            let line = format!("{}{:03X} {:02X} {}", off, cheat.address, cheat.value, cheat.name);
+            let _ = writeln!(text, "{}", line.trim_end());
+        }
+        text
+    }
+
+    pub fn list(&self) -> &[Cheat] {
+        &self.list
+    }
+
+    // Replaces any cheat already on the address
+    pub fn freeze(&mut self, address: usize, value: u8, name: &str) {
+        self.list.retain(|c| c.address != address);
+        self.list.push(Cheat { address, value, name: name.to_string(), enabled: true });
+    }
+
+    pub fn unfreeze(&mut self, address: usize) -> bool {
+        let before = self.list.len();
+        self.list.retain(|c| c.address != address);
+        self.list.len() != before
+    }
+
+    // Turn one cheat on or off, returns its new state
+    pub fn toggle(&mut self, address: usize) -> Option<bool> {
+        let cheat = self.list.iter_mut().find(|c| c.address == address)?;
+        cheat.enabled = !cheat.enabled;
+        Some(cheat.enabled)
+    }
+
+    pub fn apply(&self, chip: &mut Chip8) {
+        if self.enabled {
+            for cheat in self.list.iter().filter(|c| c.enabled) {
+                chip.poke(cheat.address, cheat.value);
+            }
+        }
+    }
+}
+
+// CRC-32 as zip and the libretro databases use it
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn narrows_a_search() {
+        let mut memory = vec![5u8; 8];
+        let mut search = Search::new(&memory);
+        memory[2] = 4;
+        memory[3] = 6;
+        assert_eq!(search.filter(&memory, Compare::Changed), 2);
+        memory[2] = 3;
+        assert_eq!(search.filter(&memory, Compare::Decreased), 1);
+        assert_eq!(search.candidates(), [2]);
+        assert_eq!(search.filter(&memory, Compare::Equal(3)), 1);
+        assert_eq!(search.filter(&memory, Compare::Changed), 0);
+
+        assert_eq!(Compare::parse(&["=", "0A"]), Ok(Compare::Equal(10)));
+        assert_eq!(Compare::parse(&["+"]), Ok(Compare::Increased));
+        assert!(Compare::parse(&["?"]).is_err());
+    }
+
+    #[test]
+    fn cheat_files() {
+        let text = "# pong\n3F0 03 Infinite lives\n-3f1 09\n";
+        let cheats = Cheats::parse(text).unwrap();
+        assert_eq!(
+            cheats.list()[0],
+            Cheat { address: 0x3F0, value: 3, name: "Infinite lives".to_string(), enabled: true }
+        );
+        assert!(!cheats.list()[1].enabled);
+        assert_eq!(cheats.to_text(), "3F0 03 Infinite lives\n-3F1 09\n");
+        assert_eq!(Cheats::parse(&cheats.to_text()).unwrap().list(), cheats.list());
+
+        assert_eq!(Cheats::parse("3F0\n").unwrap_err(), "line 1: expected an address and a byte");
+        assert!(Cheats::parse("1000 01\n").is_err());
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(Cheats::path_for(b"123456789"), format!("{}/CBF43926.cht", CHEAT_DIR));
+        // Standing in for a file that didn't parse, nothing gets written
+        assert!(Cheats::broken("cheats/bad.cht").save().unwrap_err().contains("cheats/bad.cht"));
+    }
+
+    #[test]
+    fn freezes_memory() {
+        let mut chip = Chip8::new();
+        let mut cheats = Cheats::default();
+        cheats.freeze(0x300, 7, "lives");
+        cheats.freeze(0x301, 1, "");
+        cheats.freeze(0x300, 9, "more lives");
+        assert_eq!(cheats.list().len(), 2);
+
+        cheats.apply(&mut chip);
+        assert_eq!((chip.peek(0x300), chip.peek(0x301)), (9, 1));
+        assert_eq!(cheats.toggle(0x301), Some(false));
+        chip.poke(0x301, 0);
+        cheats.enabled = false;
+        chip.poke(0x300, 0);
+        cheats.apply(&mut chip);
+        assert_eq!(chip.peek(0x300), 0);
+        cheats.enabled = true;
+        cheats.apply(&mut chip);
+        assert_eq!((chip.peek(0x300), chip.peek(0x301)), (9, 0));
+        assert!(cheats.unfreeze(0x300));
+        assert!(!cheats.unfreeze(0x300));
+        assert_eq!(cheats.toggle(0x300), None);
+    }
+}
@@ -1,34 +1,33 @@
 pub mod defaults {
-    pub const START_ADDRESS: u32 = 0x200;
-    pub const FONT_START_ADDRESS: u32 = 0x50;
-    
-    pub const FONT_SIZES: [u8; 80] = [
-        0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
-        0x20, 0x60, 0x20, 0x20, 0x70, // 1
-        0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
-        0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
-        0x90, 0x90, 0xF0, 0x10, 0x10, // 4
-        0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
-        0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
-        0xF0, 0x10, 0x20, 0x40, 0x40, // 7
-        0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
-        0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
-        0xF0, 0x90, 0xF0, 0x90, 0x90, // A
-        0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
-        0xF0, 0x80, 0x80, 0x80, 0xF0, // C
-        0xE0, 0x90, 0x90, 0x90, 0xE0, // D
-        0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
-        0xF0, 0x80, 0xF0, 0x80, 0x80, // F
-    ]; 
-
-    pub const VIDEO_WIDTH:u16 = 64;
-    pub const VIDEO_HEIGHT:u16 = 32;
+    pub use chip8_core::configs::defaults::*;
 
     pub const SCALE_FACTOR:u32 = 20;
-    pub const SDL_WIDTH:u32 = (64 as u32) * SCALE_FACTOR;
-    pub const SDL_HEIGHT:u32 = (32 as u32) * SCALE_FACTOR;
+    pub const SDL_WIDTH:u32 = 64 * SCALE_FACTOR;
+    pub const SDL_HEIGHT:u32 = 32 * SCALE_FACTOR;
 
     pub const HZ:u32 = 500;
     pub const BUFFER_DELAY:u64 = 16;
     pub const TICKS_PER_FRAME:u16 = 10;
+
+    pub const FAULT_TRACE_FILE:&str = "fault_trace.log";
+    pub const CONFIG_FILE:&str = "chip8.cfg";
+    pub const WINDOW_TITLE:&str = "CHIP-8";
+    // Cheat files, one per rom named by its CRC32
+    pub const CHEAT_DIR:&str = "cheats";
+    // Listed by the rom browser and at startup without a rom
+    pub const ROM_DIR:&str = "roms";
+    // Save slot states, <rom>.<slot>.state
+    pub const STATE_DIR:&str = "states";
+    pub const SAVE_SLOTS:usize = 4;
+
+    // How often the rom file is checked for a new build
+    pub const RELOAD_POLL_MS:u64 = 250;
+    // Cycles a reloaded rom gets to find its way back to the old pc
+    pub const RELOAD_RUN_CYCLES:u64 = 1_000_000;
+
+    // Share of brightness a pixel keeps per frame when persistence is on
+    pub const PERSISTENCE_DECAY:f32 = 0.6;
+
+    // Upscale factor before post processing effects run
+    pub const POSTFX_SCALE:usize = 8;
 }
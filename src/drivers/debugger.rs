@@ -0,0 +1,536 @@
+use crate::drivers::cheats::{Cheats, Compare, Search};
+use crate::drivers::sprites::{self, DrawLog};
+use chip8_core::decode::{decode, Instruction};
+use chip8_core::{Chip8, KeyWait};
+use std::collections::VecDeque;
+use std::io::{self, BufRead};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+// Bytes per dump row, four rows of a sprite fit one row of braille
+pub const ROW_BYTES: usize = 4;
+const DEFAULT_LENGTH: usize = 0x40;
+const RECENT_WRITES: usize = 8;
+const MAX_MATCHES: usize = 32;
+const SPRITE_HEIGHT: usize = 8;
+
+const INDEX_COLOR: &str = "\x1b[30;43m";
+const WRITE_COLOR: &str = "\x1b[30;41m";
+const RESET: &str = "\x1b[0m";
+
+const HELP: &str = "\
+r                  registers, timers and whether the cpu is halted
+m [addr] [len]     hex dump, addr defaults to I, len to 40
+f <bytes>          find a byte pattern, ?? matches any byte
+w <addr> <bytes>   write bytes, only while paused
+s [addr] [len] [h] sprites h rows high, * marks ones Dxyn has drawn
+sp <file> [addr] [len] [h]
+                   save sprites as a PNG sheet
+cs [= xx|c|u|+|-]  cheat search, no argument starts over, then keep bytes
+                   equal to xx, changed, unchanged, increased or decreased
+cz [addr byte name] freeze a byte every frame, no argument lists them
+cu <addr>          unfreeze
+ct <addr>          turn one frozen byte off or on
+cw                 save frozen bytes to the rom's cheat file
+p                  pause or resume
+h                  this help
+addresses and bytes are hex, addr may also be i or pc";
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Mark {
+    Plain,
+    Index,
+    Written,
+}
+
+// Commands typed into the terminal the emulator was started from
+pub struct Debugger {
+    lines: Receiver<String>,
+    // (start, len) of the last few Fx33 / Fx55 stores, newest last
+    writes: VecDeque<(usize, usize)>,
+    drawn: DrawLog,
+    search: Option<Search>,
+    cheats: Cheats,
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Debugger {
+    // Reads stdin on its own thread so the emulator never blocks on it
+    pub fn new() -> Self {
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in io::stdin().lock().lines().map_while(Result::ok) {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        Self { lines, writes: VecDeque::new(), drawn: DrawLog::new(), search: None, cheats: Cheats::default() }
+    }
+
+    // Frozen bytes, the emulator applies them every frame
+    pub fn cheats(&mut self) -> &mut Cheats {
+        &mut self.cheats
+    }
+
+    // Next typed line, if there is one
+    pub fn poll(&self) -> Option<String> {
+        self.lines.try_recv().ok()
+    }
+
+    // Call after every executed instruction to keep track of stores
+    pub fn observe(&mut self, chip: &Chip8) {
+        self.drawn.observe(chip);
+        if let Some(write) = chip.last_write() {
+            if self.writes.len() >= RECENT_WRITES {
+                self.writes.pop_front();
+            }
+            self.writes.push_back(write);
+        }
+    }
+
+    // How a byte is shown, recent writes win over the index region
+    pub fn mark(&self, chip: &Chip8, address: usize) -> Mark {
+        let index = chip.index();
+        if self.writes.iter().any(|&(start, len)| address >= start && address < start + len) {
+            Mark::Written
+        } else if address >= index && address < index + index_span(chip) {
+            Mark::Index
+        } else {
+            Mark::Plain
+        }
+    }
+
+    // Run one command line, returns what to print
+    pub fn command(&mut self, line: &str, chip: &mut Chip8, paused: &mut bool) -> String {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let result = match words.as_slice() {
+            [] => Ok(String::new()),
+            ["r"] => Ok(registers(chip)),
+            ["m", args @ ..] => self.dump_command(chip, args),
+            ["f", pattern @ ..] => find_command(chip, pattern),
+            ["w", address, bytes @ ..] => write_command(chip, *paused, address, bytes),
+            ["s", args @ ..] => self.sprite_command(chip, args).map(|(start, len, height)| {
+                sprites::render_text(chip.memory(), start, len, height, &self.drawn, true).join("\n")
+            }),
+            ["sp", path, args @ ..] => self.sprite_command(chip, args).and_then(|(start, len, height)| {
+                sprites::render_sheet(chip.memory(), start, len, height, &self.drawn)
+                    .save_png(path)
+                    .map(|_| format!("saved {}", path))
+                    .map_err(|e| e.to_string())
+            }),
+            ["cs"] => {
+                self.search = Some(Search::new(chip.memory()));
+                Ok(format!("{} candidates", chip.memory().len()))
+            }
+            ["cs", compare @ ..] => self.search_command(chip, compare),
+            ["cz"] => Ok(self.cheat_list()),
+            [command @ ("cz" | "cu" | "ct"), args @ ..] => self.cheat_command(chip, command, args),
+            ["cw"] => self.cheats.save().map(|path| format!("saved {}", path)),
+            ["p"] => {
+                *paused = !*paused;
+                Ok(if *paused { "paused" } else { "running" }.to_string())
+            }
+            ["h"] | ["help"] => Ok(HELP.to_string()),
+            _ => Err(format!("unknown command {}, h for help", line.trim())),
+        };
+        result.unwrap_or_else(|e| format!("error: {}", e))
+    }
+
+    fn search_command(&mut self, chip: &Chip8, words: &[&str]) -> Result<String, String> {
+        let compare = Compare::parse(words)?;
+        let search = self.search.as_mut().ok_or("start a search with cs")?;
+        let count = search.filter(chip.memory(), compare);
+        let mut text = format!("{} candidates", count);
+        if count > 0 && count <= MAX_MATCHES {
+            let values: Vec<String> =
+                search.candidates().iter().map(|&a| format!("{:03X}={:02X}", a, chip.peek(a))).collect();
+            text.push('\n');
+            text.push_str(&values.join(" "));
+        }
+        Ok(text)
+    }
+
+    // cz addr byte [name], cu addr and ct addr
+    fn cheat_command(&mut self, chip: &mut Chip8, command: &str, args: &[&str]) -> Result<String, String> {
+        let address = parse_address(chip, args.first().ok_or(format!("{} needs an address", command))?)?;
+        match (command, &args[1..]) {
+            ("cz", [value, name @ ..]) => {
+                let value = u8::try_from(parse_hex(value)?).map_err(|_| format!("bad byte {}", value))?;
+                self.cheats.freeze(address, value, &name.join(" "));
+                self.cheats.apply(chip);
+                Ok(format!("froze {:03X} at {:02X}", address, value))
+            }
+            ("cu", []) if self.cheats.unfreeze(address) => Ok(format!("unfroze {:03X}", address)),
+            ("ct", []) => match self.cheats.toggle(address) {
+                Some(enabled) => Ok(format!("{:03X} {}", address, if enabled { "on" } else { "off" })),
+                None => Err(format!("{:03X} isn't frozen", address)),
+            },
+            ("cu", []) => Err(format!("{:03X} isn't frozen", address)),
+            _ => Err(format!("bad arguments to {}, h for help", command)),
+        }
+    }
+
+    fn cheat_list(&self) -> String {
+        if self.cheats.list().is_empty() {
+            return "nothing frozen".to_string();
+        }
+        let state = if self.cheats.enabled { "on" } else { "off, F9 to turn on" };
+        let mut lines = vec![format!("cheats {}", state)];
+        for cheat in self.cheats.list() {
+            let off = if cheat.enabled { "" } else { " (off)" };
+            lines.push(format!("{:03X}={:02X} {}{}", cheat.address, cheat.value, cheat.name, off));
+        }
+        lines.join("\n")
+    }
+
+    fn dump_command(&self, chip: &Chip8, args: &[&str]) -> Result<String, String> {
+        let start = match args.first() {
+            Some(arg) => parse_address(chip, arg)?,
+            None => chip.index(),
+        };
+        let len = match args.get(1) {
+            Some(arg) => parse_hex(arg)?,
+            None => DEFAULT_LENGTH,
+        };
+        let lines = hex_dump(chip.memory(), start, len, |a| self.mark(chip, a), true);
+        Ok(lines.join("\n"))
+    }
+
+    // (start, len, height), one line of sprites from I by default
+    fn sprite_command(&self, chip: &Chip8, args: &[&str]) -> Result<(usize, usize, usize), String> {
+        let start = match args.first() {
+            Some(arg) => parse_address(chip, arg)?,
+            None => chip.index(),
+        };
+        let height = match args.get(2) {
+            Some(arg) => parse_hex(arg)?.clamp(1, 15),
+            None => SPRITE_HEIGHT,
+        };
+        let len = match args.get(1) {
+            Some(arg) => parse_hex(arg)?,
+            None => height * sprites::COLUMNS,
+        };
+        Ok((start, len, height))
+    }
+}
+
+// Bytes from I the instruction at pc is going to use
+fn index_span(chip: &Chip8) -> usize {
+    let pc = chip.pc();
+    if pc + 1 >= chip.memory().len() {
+        return 1;
+    }
+    let opcode = ((chip.peek(pc) as u16) << 8) | chip.peek(pc + 1) as u16;
+    match decode(opcode) {
+        Instruction::Drw(_, _, n) => (n as usize).max(1),
+        Instruction::LdBVx(_) => 3,
+        Instruction::LdIVx(x) | Instruction::LdVxI(x) => x as usize + 1,
+        _ => 1,
+    }
+}
+
+// addr  hex bytes  |ascii|  sprite
+// Rows start on a ROW_BYTES boundary, bytes outside the range are blank
+pub fn hex_dump(
+    memory: &[u8],
+    start: usize,
+    len: usize,
+    mark: impl Fn(usize) -> Mark,
+    color: bool,
+) -> Vec<String> {
+    let end = start.saturating_add(len).min(memory.len());
+    let first_row = start - start % ROW_BYTES;
+    let mut lines = Vec::new();
+
+    for row in (first_row..end).step_by(ROW_BYTES) {
+        let mut hex = String::new();
+        let mut ascii = String::new();
+        let mut sprite = [0u8; ROW_BYTES];
+        for (i, address) in (row..row + ROW_BYTES).enumerate() {
+            if address < start || address >= end {
+                hex.push_str("   ");
+                ascii.push(' ');
+                continue;
+            }
+            let byte = memory[address];
+            let text = format!("{:02X}", byte);
+            match (color, mark(address)) {
+                (true, Mark::Index) => hex.push_str(&format!("{}{}{}", INDEX_COLOR, text, RESET)),
+                (true, Mark::Written) => hex.push_str(&format!("{}{}{}", WRITE_COLOR, text, RESET)),
+                _ => hex.push_str(&text),
+            }
+            hex.push(' ');
+            ascii.push(if (0x20..0x7F).contains(&byte) { byte as char } else { '.' });
+            sprite[i] = byte;
+        }
+        lines.push(format!("{:03X}  {} |{}|  {}", row, hex, ascii, braille_rows(&sprite)));
+    }
+    lines
+}
+
+// Four sprite rows as braille, two pixels wide per character
+fn braille_rows(rows: &[u8; ROW_BYTES]) -> String {
+    const DOTS: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+    (0..4)
+        .map(|cell| {
+            let mut bits = 0;
+            for (byte, dots) in rows.iter().zip(DOTS.iter()) {
+                for (dx, dot) in dots.iter().enumerate() {
+                    if byte & (0x80 >> (cell * 2 + dx)) != 0 {
+                        bits |= dot;
+                    }
+                }
+            }
+            char::from_u32(0x2800 + bits).unwrap()
+        })
+        .collect()
+}
+
+// Every address where the pattern starts, None in the pattern is a wildcard
+pub fn find(memory: &[u8], pattern: &[Option<u8>]) -> Vec<usize> {
+    if pattern.is_empty() || pattern.len() > memory.len() {
+        return Vec::new();
+    }
+    memory
+        .windows(pattern.len())
+        .enumerate()
+        .filter(|(_, window)| {
+            window
+                .iter()
+                .zip(pattern.iter())
+                .all(|(byte, want)| want.is_none_or(|w| w == *byte))
+        })
+        .map(|(address, _)| address)
+        .collect()
+}
+
+fn registers(chip: &Chip8) -> String {
+    let v: Vec<String> = chip.registers().iter().enumerate().map(|(x, v)| format!("V{:X}={:02X}", x, v)).collect();
+    let stack: Vec<String> = chip.stack_frames().iter().map(|frame| format!("{:03X}", frame)).collect();
+    let state = match chip.key_wait() {
+        None => "running".to_string(),
+        Some(KeyWait::Press { x }) => format!("halted, waiting for a key into V{:X}", x),
+        Some(KeyWait::Release { x, key }) => format!("halted, waiting for key {:X} to come up into V{:X}", key, x),
+    };
+    format!(
+        "{}\n{}\nI={:03X} PC={:03X} DT={:02X} ST={:02X} stack [{}]\n{}",
+        v[..8].join(" "),
+        v[8..].join(" "),
+        chip.index(),
+        chip.pc(),
+        chip.delay_timer(),
+        chip.sound_timer(),
+        stack.join(" "),
+        state
+    )
+}
+
+fn find_command(chip: &Chip8, words: &[&str]) -> Result<String, String> {
+    let pattern = parse_bytes(words)?;
+    if pattern.is_empty() {
+        return Err("f needs a pattern".to_string());
+    }
+    let matches = find(chip.memory(), &pattern);
+    if matches.is_empty() {
+        return Ok("not found".to_string());
+    }
+    let mut text = matches
+        .iter()
+        .take(MAX_MATCHES)
+        .map(|a| format!("{:03X}", a))
+        .collect::<Vec<_>>()
+        .join(" ");
+    if matches.len() > MAX_MATCHES {
+        text.push_str(&format!(" ... {} matches", matches.len()));
+    }
+    Ok(text)
+}
+
+fn write_command(chip: &mut Chip8, paused: bool, address: &str, words: &[&str]) -> Result<String, String> {
+    if !paused {
+        return Err("pause first, p or space".to_string());
+    }
+    let start = parse_address(chip, address)?;
+    let bytes = parse_bytes(words)?
+        .into_iter()
+        .collect::<Option<Vec<u8>>>()
+        .ok_or("wildcards can't be written")?;
+    if bytes.is_empty() || start.saturating_add(bytes.len()) > chip.memory().len() {
+        return Err("nothing to write or past the end of memory".to_string());
+    }
+    for (i, &byte) in bytes.iter().enumerate() {
+        chip.poke(start + i, byte);
+    }
+    Ok(format!("wrote {} bytes at {:03X}", bytes.len(), start))
+}
+
+fn parse_address(chip: &Chip8, word: &str) -> Result<usize, String> {
+    let address = match word.to_ascii_lowercase().as_str() {
+        "i" => chip.index(),
+        "pc" => chip.pc(),
+        _ => parse_hex(word)?,
+    };
+    if address >= chip.memory().len() {
+        return Err(format!("{:X} is past the end of memory", address));
+    }
+    Ok(address)
+}
+
+fn parse_hex(word: &str) -> Result<usize, String> {
+    usize::from_str_radix(word.trim_start_matches("0x"), 16).map_err(|_| format!("bad number {}", word))
+}
+
+// "12 34 ??" or "1234??", ?? is a wildcard
+fn parse_bytes(words: &[&str]) -> Result<Vec<Option<u8>>, String> {
+    let digits: String = words.concat();
+    if let Some(bad) = digits.chars().find(|c| !c.is_ascii_hexdigit() && *c != '?') {
+        return Err(format!("bad hex digit {}", bad));
+    }
+    if !digits.len().is_multiple_of(2) {
+        return Err(format!("odd number of hex digits in {}", digits));
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| match &digits[i..i + 2] {
+            "??" => Ok(None),
+            pair => u8::from_str_radix(pair, 16)
+                .map(Some)
+                .map_err(|_| format!("bad byte {}", pair)),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn debugger() -> Debugger {
+        // No stdin thread in tests
+        let (_, lines) = mpsc::channel();
+        Debugger {
+            lines,
+            writes: VecDeque::new(),
+            drawn: DrawLog::new(),
+            search: None,
+            cheats: Cheats::default(),
+        }
+    }
+
+    #[test]
+    fn dump_rows() {
+        let mut memory = [0u8; 16];
+        memory[4..8].copy_from_slice(&[0xF0, 0x41, 0x90, 0xF0]);
+        let lines = hex_dump(&memory, 5, 3, |_| Mark::Plain, false);
+        assert_eq!(lines, ["004     41 90 F0  | A..|  ⣔⣠⠀⠐".to_string()]);
+        assert_eq!(hex_dump(&memory, 12, usize::MAX, |_| Mark::Plain, false).len(), 1);
+    }
+
+    #[test]
+    fn marks_index_and_writes() {
+        let mut chip = Chip8::new();
+        // LD I, 0x300; LD B, V0; DRW V0, V0, 2
+        chip.load_program(&[0xA3, 0x00, 0xF0, 0x33, 0xD0, 0x02]).unwrap();
+        let mut debugger = debugger();
+        chip.cycle().unwrap();
+        debugger.observe(&chip);
+        assert_eq!(debugger.mark(&chip, 0x300), Mark::Index);
+        assert_eq!(debugger.mark(&chip, 0x303), Mark::Plain);
+
+        chip.cycle().unwrap();
+        debugger.observe(&chip);
+        assert_eq!(debugger.mark(&chip, 0x302), Mark::Written);
+        // Dxyn at pc now reads two bytes from I
+        chip.set_index(0x310);
+        assert_eq!(debugger.mark(&chip, 0x311), Mark::Index);
+        assert_eq!(debugger.mark(&chip, 0x312), Mark::Plain);
+
+        let lines = hex_dump(chip.memory(), 0x300, 4, |a| debugger.mark(&chip, a), true);
+        assert!(lines[0].starts_with("300  \x1b[30;41m00\x1b[0m"));
+    }
+
+    #[test]
+    fn shows_registers_and_key_waits() {
+        let mut chip = Chip8::new();
+        let mut debugger = debugger();
+        let mut paused = false;
+        chip.load_program(&[0xF3, 0x0A]).unwrap();
+        chip.set_register(0xA, 0x42);
+        let text = debugger.command("r", &mut chip, &mut paused);
+        assert!(text.contains("V8=00 V9=00 VA=42"));
+        assert!(text.ends_with("I=000 PC=200 DT=00 ST=00 stack []\nrunning"));
+
+        chip.cycle().unwrap();
+        assert!(debugger.command("r", &mut chip, &mut paused).ends_with("halted, waiting for a key into V3"));
+        chip.set_key(0xC, true);
+        chip.cycle().unwrap();
+        assert!(debugger.command("r", &mut chip, &mut paused).ends_with("waiting for key C to come up into V3"));
+    }
+
+    #[test]
+    fn finds_patterns() {
+        let memory = [0x12, 0x34, 0x56, 0x12, 0x99, 0x56];
+        assert_eq!(find(&memory, &[Some(0x12), None, Some(0x56)]), [0, 3]);
+        assert_eq!(find(&memory, &[Some(0x34)]), [1]);
+        assert!(find(&memory, &[]).is_empty());
+        assert_eq!(parse_bytes(&["12", "??56"]), Ok(vec![Some(0x12), None, Some(0x56)]));
+        assert!(parse_bytes(&["123"]).is_err());
+        assert!(parse_bytes(&["aé1"]).is_err());
+        assert!(parse_bytes(&["+1"]).is_err());
+    }
+
+    #[test]
+    fn writes_only_while_paused() {
+        let mut chip = Chip8::new();
+        let mut debugger = debugger();
+        let mut paused = false;
+        assert!(debugger.command("w 300 AB", &mut chip, &mut paused).starts_with("error"));
+
+        debugger.command("p", &mut chip, &mut paused);
+        assert!(paused);
+        assert_eq!(debugger.command("w 300 AB CD", &mut chip, &mut paused), "wrote 2 bytes at 300");
+        assert_eq!(chip.peek(0x301), 0xCD);
+        assert_eq!(debugger.command("f abcd", &mut chip, &mut paused), "300");
+        assert!(debugger.command("w FFF 01 02", &mut chip, &mut paused).starts_with("error"));
+    }
+
+    #[test]
+    fn sprite_commands() {
+        let mut chip = Chip8::new();
+        let mut debugger = debugger();
+        let mut paused = true;
+        chip.load_program(&[]).unwrap();
+        let font = debugger.command("s 50 a 5", &mut chip, &mut paused);
+        assert!(font.starts_with("050       055\n█▀▀█       ▄█"), "{}", font);
+        assert!(debugger.command("sp", &mut chip, &mut paused).starts_with("error"));
+    }
+
+    #[test]
+    fn cheat_commands() {
+        let mut chip = Chip8::new();
+        let mut debugger = debugger();
+        let mut paused = false;
+        assert!(debugger.command("cs +", &mut chip, &mut paused).starts_with("error"));
+        assert_eq!(debugger.command("cs", &mut chip, &mut paused), "4096 candidates");
+        chip.poke(0x3F0, 3);
+        chip.poke(0x3F1, 3);
+        assert_eq!(debugger.command("cs = 3", &mut chip, &mut paused), "2 candidates\n3F0=03 3F1=03");
+        chip.poke(0x3F0, 2);
+        assert_eq!(debugger.command("cs -", &mut chip, &mut paused), "1 candidates\n3F0=02");
+
+        assert_eq!(debugger.command("cz 3f0 5 Infinite lives", &mut chip, &mut paused), "froze 3F0 at 05");
+        assert_eq!(chip.peek(0x3F0), 5);
+        assert_eq!(debugger.command("ct 3f0", &mut chip, &mut paused), "3F0 off");
+        assert_eq!(debugger.command("cz", &mut chip, &mut paused), "cheats on\n3F0=05 Infinite lives (off)");
+        assert_eq!(debugger.command("cu 3f0", &mut chip, &mut paused), "unfroze 3F0");
+        assert!(debugger.command("cu 3f0", &mut chip, &mut paused).starts_with("error"));
+        assert!(debugger.command("cz 3f0 100", &mut chip, &mut paused).starts_with("error"));
+        // No rom, no cheat file
+        assert!(debugger.command("cw", &mut chip, &mut paused).starts_with("error"));
+    }
+}
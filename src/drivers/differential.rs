@@ -0,0 +1,54 @@
+use crate::drivers::args::DiffArgs;
+use crate::drivers::configs::defaults::*;
+use chip8_core::reference::lockstep;
+use chip8_core::Quirks;
+use std::fs;
+
+// How long each random key is held or let go
+const KEY_SPAN: u64 = 30 * TICKS_PER_FRAME as u64;
+
+// The keypad for a cycle, half the spans hold one key picked from the
+// seed and the span number, the rest press nothing
+fn random_keys(seed: u32, cycle: u64) -> [bool; 16] {
+    // splitmix64, neighbouring spans need unrelated keys
+    let mut x = ((seed as u64) << 32 | (cycle / KEY_SPAN)).wrapping_add(0x9E37_79B9_7F4A_7C15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    x ^= x >> 31;
+    let key = (x >> 8) as usize % 16;
+    std::array::from_fn(|k| k == key && x >> 40 & 1 == 1)
+}
+
+// The diff subcommand, the rom against the reference interpreter with
+// the same seed and key presses, stopping at the first disagreement
+pub fn run(rom: &str, args: &DiffArgs) -> Result<(), String> {
+    let program = fs::read(rom).map_err(|e| format!("Unable to read {}: {}", rom, e))?;
+    let profiles: Vec<(&str, Quirks)> = match args.quirks {
+        Some(quirks) => vec![(quirks.name().unwrap_or("custom"), quirks)],
+        None => Quirks::PROFILES.to_vec(),
+    };
+    for (name, quirks) in profiles {
+        match lockstep(&program, quirks, args.seed, args.cycles, |cycle| random_keys(args.seed, cycle)) {
+            Ok(cycles) if cycles < args.cycles => println!("{}: agree, both stopped after {} instructions", name, cycles),
+            Ok(cycles) => println!("{}: agree for {} instructions", name, cycles),
+            Err(divergence) => return Err(format!("{}: {}", name, divergence)),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_stream() {
+        assert_eq!(random_keys(1, 0), random_keys(1, KEY_SPAN - 1));
+        let spans: Vec<[bool; 16]> = (0..64).map(|span| random_keys(1, span * KEY_SPAN)).collect();
+        // Never more than one key, and some spans press one
+        assert!(spans.iter().all(|keys| keys.iter().filter(|&&k| k).count() <= 1));
+        assert!(spans.iter().any(|keys| keys.contains(&true)));
+        assert!(spans.iter().any(|keys| !keys.contains(&true)));
+        assert_ne!(spans, (0..64).map(|span| random_keys(2, span * KEY_SPAN)).collect::<Vec<_>>());
+    }
+}
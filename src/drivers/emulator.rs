@@ -1,57 +1,865 @@
 extern crate sdl2;
-use crate::drivers::chip8::Chip8;
+use chip8_core::profile::Profiler;
+use chip8_core::rewind::Rewind;
+use chip8_core::sanitize::Sanitizer;
+use chip8_core::state;
+use chip8_core::trace::{TraceFilter, Tracer};
+use chip8_core::{Chip8, Error, Quirks, Timing};
+use crate::drivers::audio_driver::AudioDriver;
 use crate::drivers::configs::defaults::*;
+use crate::drivers::cheats::Cheats;
+use crate::drivers::debugger::Debugger;
+use crate::drivers::gdb::{self, GdbStub, Request};
 use crate::drivers::input_driver::InputDriver;
+use crate::drivers::keymap::{self, Keymap};
+use crate::drivers::menu::{self, Input, Menu, Page};
+use crate::drivers::palette::{Palette, Palettes};
+use crate::drivers::postfx::Effects;
+use crate::drivers::reload::{self, RomWatcher};
+use crate::drivers::script::{Action, Script};
+use crate::drivers::settings::{self, Settings};
 use crate::drivers::video_driver::VideoDriver;
-use sdl2::event::Event;
+use sdl2::controller::Button;
+use sdl2::event::{Event, WindowEvent};
 use sdl2::keyboard::Keycode;
-use std::time::Instant;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 use std::{thread, time};
-use super::rewind::Rewind;
 
 pub struct Emulator {
     chip8: Chip8,
     video_driver: VideoDriver,
     input_driver: InputDriver,
+    audio_driver: AudioDriver,
+    keymap: Keymap,
     paused: bool,
     rewind: Rewind,
+    tracer: Tracer,
+    trace: Option<(BufWriter<File>, TraceFilter)>,
+    settings: Settings,
+    palettes: Palettes,
+    rom_name: String,
+    rom_path: String,
+    // Open menu pages, each with the selection to go back to
+    pages: Vec<(Page, usize)>,
+    // Keypad key waiting for a keyboard key on the keys page
+    binding: Option<usize>,
+    // Paths behind the rom page's items
+    rom_list: Vec<String>,
+    faulted: bool,
+    ticks_per_frame: u16,
+    // Instructions and frames run in the last second
+    rates: (u32, u32),
+    debugger: Debugger,
+    // Output prefix and the profiler, when --profile is given
+    profile: Option<String>,
+    profiler: Option<Profiler>,
+    // Built in run() once the rom size is known, when --sanitize is given
+    sanitize: bool,
+    sanitizer: Option<Sanitizer>,
+    gdb: Option<GdbStub>,
+    // Loaded in run() after the rom so its top level can patch memory
+    script_path: Option<String>,
+    script: Option<Script>,
+    // Picks up new builds of the rom, unless hot_reload = false
+    watcher: Option<RomWatcher>,
+}
+
+impl Default for Emulator {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Emulator {
     pub fn new() -> Self {
         let sdl_context = sdl2::init().unwrap();
+        let settings = Settings::load(CONFIG_FILE).unwrap_or_else(|e| {
+            println!("Unable to read {}: {}", CONFIG_FILE, e);
+            Settings::default()
+        });
+        let user_palettes = settings
+            .with_prefix("palette.")
+            .filter_map(|(name, colors)| {
+                let palette = Palette::from_hex(name, colors);
+                if palette.is_none() {
+                    println!("Ignoring palette {}: expected 2 to 4 hex colours", name);
+                }
+                palette
+            })
+            .collect();
+        let palettes = Palettes::new(user_palettes);
+
+        let mut audio_driver = AudioDriver::new(&sdl_context);
+        if let Some(volume) = settings.get(None, "volume").and_then(|v| v.parse().ok()) {
+            audio_driver.set_volume(volume);
+        }
+        if let Some(tone) = settings.get(None, "tone").and_then(|v| v.parse().ok()) {
+            audio_driver.set_tone(tone);
+        }
+
         Self {
-            chip8: Chip8::new(),
-            video_driver: VideoDriver::new(&sdl_context),
+            chip8: Chip8::with_seed(time_seed()),
+            video_driver: VideoDriver::new(&sdl_context, palettes.current().clone()),
             input_driver: InputDriver::new(&sdl_context),
+            audio_driver,
+            keymap: Keymap::from_settings(&settings),
             paused: false,
             rewind: Rewind::new(),
+            tracer: Tracer::default(),
+            trace: None,
+            settings,
+            palettes,
+            rom_name: String::new(),
+            rom_path: String::new(),
+            pages: Vec::new(),
+            binding: None,
+            rom_list: Vec::new(),
+            faulted: false,
+            ticks_per_frame: 9,
+            rates: (0, 0),
+            debugger: Debugger::new(),
+            profile: None,
+            profiler: None,
+            sanitize: false,
+            sanitizer: None,
+            gdb: None,
+            script_path: None,
+            script: None,
+            watcher: None,
+        }
+    }
+
+    // Window title with rom name and run state
+    fn update_title(&mut self) {
+        let state = if self.faulted {
+            "fault"
+        } else if self.paused {
+            "paused"
+        } else {
+            "running"
+        };
+        let title = format!(
+            "{} - {} [{}] {}, {}",
+            WINDOW_TITLE,
+            self.rom_name,
+            state,
+            self.speed(),
+            self.palettes.current().name
+        );
+        self.video_driver.set_title(&title);
+    }
+
+    fn speed(&self) -> String {
+        match self.chip8.timing() {
+            Timing::Ticks => format!("{} ticks/frame", self.ticks_per_frame),
+            Timing::Vip => "VIP timing".to_string(),
+        }
+    }
+
+    fn settings_f32(&self, key: &str) -> Option<f32> {
+        let value = self.settings.get(Some(&self.rom_name), key)?;
+        match value.parse() {
+            Ok(number) => Some(number),
+            Err(_) => {
+                println!("Ignoring {} = {}: expected a number", key, value);
+                None
+            }
+        }
+    }
+
+    // Save the current frame as <rom>-<n>.png with the first free n
+    fn screenshot(&mut self) {
+        let stem = Path::new(&self.rom_name)
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "screenshot".to_string());
+        let path = (1..)
+            .map(|n| format!("{}-{}.png", stem, n))
+            .find(|p| !Path::new(p).exists())
+            .unwrap();
+
+        match self.video_driver.screenshot(&path) {
+            Ok(()) => self.notify(&format!("Saved {}", path)),
+            Err(e) => self.error(&format!("Unable to save {}: {}", path, e)),
+        }
+    }
+
+    // Printed and shown on screen for a few seconds
+    fn notify(&mut self, message: &str) {
+        println!("{}", message);
+        self.video_driver.overlay().notify(message);
+    }
+
+    fn error(&mut self, message: &str) {
+        println!("{}", message);
+        self.video_driver.overlay().error(message);
+    }
+
+    // Overlay status line, speed and measured rates
+    fn update_status(&mut self) {
+        let state = if self.faulted {
+            " FAULT"
+        } else if self.paused {
+            " PAUSED"
+        } else {
+            ""
+        };
+        let speed = match self.chip8.timing() {
+            Timing::Ticks => format!("{} T/F", self.ticks_per_frame),
+            Timing::Vip => "VIP".to_string(),
+        };
+        let status = format!("{} {} HZ {} FPS{}", speed, self.rates.0, self.rates.1, state);
+        self.video_driver.overlay().set_status(&status);
+    }
+
+    fn apply_palette(&mut self) {
+        self.video_driver.set_palette(self.palettes.current());
+        self.video_driver.draw(self.chip8.framebuffer());
+    }
+
+    // Write every traced instruction that passes the filter to a file
+    pub fn trace_to(&mut self, path: &str, filter: TraceFilter) -> io::Result<()> {
+        self.trace = Some((BufWriter::new(File::create(path)?), filter));
+        Ok(())
+    }
+
+    // Count every instruction, reports are written when run() returns
+    pub fn profile_to(&mut self, prefix: &str) {
+        self.profile = Some(prefix.to_string());
+        self.profiler = Some(Profiler::new());
+    }
+
+    // Shadow every byte of memory and report suspicious accesses as they happen
+    pub fn sanitize(&mut self) {
+        self.sanitize = true;
+    }
+
+    pub fn script(&mut self, path: &str) {
+        self.script_path = Some(path.to_string());
+    }
+
+    // A failing script is reported and dropped, the game carries on
+    fn script_hook(&mut self, call: fn(&mut Script, &mut Chip8) -> Result<(), String>) {
+        if let Some(script) = self.script.as_mut() {
+            if let Err(e) = call(script, &mut self.chip8) {
+                self.error(&format!("Script: {}", e));
+                self.script = None;
+            }
+        }
+    }
+
+    // Returns true when the script wants to quit
+    fn script_actions(&mut self) -> bool {
+        let actions = self.script.as_mut().map(|s| s.take_actions()).unwrap_or_default();
+        for action in actions {
+            match action {
+                Action::Screenshot(path) => {
+                    if let Err(e) = self.video_driver.screenshot(&path) {
+                        self.error(&format!("Unable to save {}: {}", path, e));
+                    }
+                }
+                Action::Exit => return true,
+            }
+        }
+        false
+    }
+
+    // Wait for a gdb client on localhost, it halts the game when it attaches
+    pub fn gdb_listen(&mut self, port: u16) -> io::Result<()> {
+        let stub = GdbStub::bind(port)?;
+        println!("gdb server on {}", stub.local_addr()?);
+        self.gdb = Some(stub);
+        Ok(())
+    }
+
+    // Attach, detach and resume requests from the gdb client
+    fn poll_gdb(&mut self) {
+        let request = match self.gdb.as_mut() {
+            Some(stub) => stub.poll(&mut self.chip8),
+            None => return,
+        };
+        match request {
+            Some(Request::Halt) => {
+                self.paused = true;
+                self.notify(&format!("gdb halted at {:03X}", self.chip8.pc()));
+            }
+            Some(Request::Continue) => {
+                self.paused = false;
+                self.faulted = false;
+            }
+            Some(Request::Step) => {
+                self.step();
+                let signal = if self.faulted { gdb::SIGILL } else { gdb::SIGTRAP };
+                if let Some(stub) = self.gdb.as_mut() {
+                    stub.stopped(signal);
+                }
+                self.video_driver.draw(self.chip8.framebuffer());
+            }
+            None => return,
         }
+        self.update_title();
+        self.update_status();
+    }
+
+    // <prefix>.txt annotated disassembly, <prefix>.folded for flamegraphs
+    fn write_profile(&self) -> io::Result<()> {
+        if let (Some(prefix), Some(profiler)) = (&self.profile, &self.profiler) {
+            std::fs::write(format!("{}.txt", prefix), profiler.report(&self.chip8))?;
+            std::fs::write(format!("{}.folded", prefix), profiler.folded())?;
+            println!("Profile written to {}.txt and {}.folded", prefix, prefix);
+        }
+        Ok(())
+    }
+
+    // Run one instruction, on a fault dump the trace ring and pause
+    fn step(&mut self) {
+        // Halted on Fx0A nothing gets fetched, so there's no instruction to
+        // run callbacks for or trace
+        let halted = self.chip8.key_wait().is_some();
+        if !halted {
+            self.script_hook(Script::before);
+        }
+        let mut hooks = (&mut self.profiler, &mut self.sanitizer);
+        let result = self.tracer.step_with(&mut self.chip8, &mut hooks);
+        self.debugger.observe(&self.chip8);
+        self.script_hook(Script::after);
+
+        let reports = self.sanitizer.as_mut().map(|s| s.take_reports()).unwrap_or_default();
+        for report in reports {
+            self.error(&format!("Sanitizer: {}", report));
+        }
+
+        if let (Some((file, filter)), Some(entry)) = (self.trace.as_mut(), self.tracer.last().filter(|_| !halted)) {
+            if filter.matches(entry) {
+                let _ = writeln!(file, "{}", entry);
+            }
+        }
+
+        self.faulted = result.is_err();
+        if let Err(fault) = result {
+            self.error(&format!("Fault: {}", fault));
+            match self.dump_trace() {
+                Ok(()) => println!("Last instructions written to {}", FAULT_TRACE_FILE),
+                Err(e) => self.error(&format!("Unable to write {}: {}", FAULT_TRACE_FILE, e)),
+            }
+            self.paused = true;
+            self.update_title();
+            self.update_status();
+        }
+    }
+
+    fn dump_trace(&mut self) -> io::Result<()> {
+        if let Some((file, _)) = self.trace.as_mut() {
+            file.flush()?;
+        }
+        let mut out = BufWriter::new(File::create(FAULT_TRACE_FILE)?);
+        for entry in self.tracer.ring() {
+            writeln!(out, "{}", entry)?;
+        }
+        out.flush()
+    }
+
+    // Per rom state that goes with the program bytes. The profile starts
+    // over too, its report disassembles the counts against the rom in
+    // memory at exit
+    fn rom_loaded(&mut self, program: &[u8]) {
+        if self.sanitize {
+            self.sanitizer = Some(Sanitizer::new(program.len()));
+        }
+        if self.profiler.is_some() {
+            self.profiler = Some(Profiler::new());
+        }
+        // A broken file leaves the rom without cheats rather than keeping
+        // the last rom's, and isn't saved over
+        let path = Cheats::path_for(program);
+        let cheats = Cheats::load(&path).unwrap_or_else(|e| {
+            println!("{}", e);
+            Cheats::broken(&path)
+        });
+        if !cheats.list().is_empty() {
+            println!("Loaded {} cheats, F9 turns them off", cheats.list().len());
+        }
+        *self.debugger.cheats() = cheats;
+    }
+
+    // A fresh machine running program, the script loads again since its
+    // top level may patch the rom
+    fn restart(&mut self, program: &[u8], quirks: Quirks, timing: Timing) -> Result<(), Error> {
+        let mut chip = Chip8::with_seed(time_seed());
+        chip.set_quirks(quirks);
+        chip.set_timing(timing);
+        chip.load_program(program)?;
+        self.chip8 = chip;
+        self.rom_loaded(program);
+        self.faulted = false;
+        self.rewind = Rewind::new();
+        if let Some(path) = self.script_path.clone() {
+            match Script::load(&path, &mut self.chip8) {
+                Ok(script) => self.script = Some(script),
+                Err(e) => self.error(&format!("Script {}: {}", path, e)),
+            }
+        }
+        Ok(())
+    }
+
+    // A new build of the rom, the machine starts over with the same quirks
+    // and timing. Breakpoints live in the gdb stub and stay, and with
+    // reload_to_cursor = true the new build runs until it's back at the
+    // old pc
+    fn reload(&mut self, program: &[u8]) {
+        let cursor = self.chip8.pc();
+        if let Err(e) = self.restart(program, self.chip8.quirks(), self.chip8.timing()) {
+            self.error(&format!("Not reloading: {}", e));
+            return;
+        }
+
+        let message = if self.settings.get(Some(&self.rom_name), "reload_to_cursor") == Some("true") {
+            match reload::run_to(&mut self.chip8, cursor, RELOAD_RUN_CYCLES) {
+                Ok(true) => format!("Reloaded, ran to {:03X}", cursor),
+                Ok(false) => format!("Reloaded, {:03X} not reached", cursor),
+                Err(fault) => {
+                    self.faulted = true;
+                    self.paused = true;
+                    format!("Reloaded, faulted before {:03X}: {}", cursor, fault)
+                }
+            }
+        } else {
+            format!("Reloaded {}", self.rom_name)
+        };
+        self.rewind.capture(&self.chip8);
+        self.video_driver.draw(self.chip8.framebuffer());
+        if self.faulted {
+            self.error(&message);
+        } else {
+            self.notify(&message);
+        }
+        self.update_title();
+        self.update_status();
+    }
+
+    // Start a rom from a fresh machine with its own settings, used at
+    // startup and by the rom browser
+    fn open(&mut self, rom: &str) -> Result<(), String> {
+        let program = fs::read(rom).map_err(|e| format!("Unable to read {}: {}", rom, e))?;
+        let name = settings::rom_name(rom);
+        let (quirks, timing) = (self.settings.quirks(&name), self.settings.timing(&name));
+        self.restart(&program, quirks, timing)
+            .map_err(|e| format!("Unable to load {}: {}", name, e))?;
+        self.rom_name = name;
+        self.rom_path = rom.to_string();
+        self.apply_rom_settings();
+        self.rewind.capture(&self.chip8);
+        Ok(())
+    }
+
+    // Display settings and the file watcher for the current rom
+    fn apply_rom_settings(&mut self) {
+        if let Some(speed) = self.settings.get(Some(&self.rom_name), "speed").and_then(|s| s.parse().ok()) {
+            self.ticks_per_frame = u16::max(speed, 1);
+        }
+        self.watcher = match self.settings.get(Some(&self.rom_name), "hot_reload") {
+            Some("false") => None,
+            _ => Some(RomWatcher::new(&self.rom_path)),
+        };
+
+        // Rom section of the settings file may pick its own palette
+        if let Some(name) = self.settings.get(Some(&self.rom_name), "palette") {
+            if !self.palettes.select(name) {
+                println!("Unknown palette {}, available: {:?}", name, self.palettes.names().collect::<Vec<_>>());
+            }
+        }
+        self.apply_palette();
+
+        // Pixel persistence, a decay of 0 or no key leaves it off
+        let decay = self.settings_f32("persistence").filter(|&d| d > 0.0);
+        self.video_driver.set_persistence(decay);
+        let integer_scale = self.settings.get(Some(&self.rom_name), "integer_scale") != Some("false");
+        self.video_driver.set_integer_scale(integer_scale);
+        let effects = match self.settings.get(Some(&self.rom_name), "effects") {
+            Some(effects) => Effects::parse(effects).unwrap_or_else(|e| {
+                println!("Ignoring effects = {}: {}", effects, e);
+                Effects::default()
+            }),
+            None => Effects::default(),
+        };
+        self.video_driver.set_effects(effects);
+    }
+
+    // Back to the start of the rom, soft keeps memory as the program left
+    // it, hard reads the rom again and starts a fresh machine
+    fn reset(&mut self, hard: bool) {
+        if hard {
+            let restarted = fs::read(&self.rom_path)
+                .map_err(|e| e.to_string())
+                .and_then(|program| {
+                    self.restart(&program, self.chip8.quirks(), self.chip8.timing())
+                        .map_err(|e| e.to_string())
+                });
+            if let Err(e) = restarted {
+                self.error(&format!("Not resetting: {}", e));
+                return;
+            }
+            self.rewind.capture(&self.chip8);
+            self.notify("Hard reset");
+        } else {
+            self.chip8.soft_reset();
+            self.faulted = false;
+            self.notify("Soft reset");
+        }
+        self.video_driver.draw(self.chip8.framebuffer());
+    }
+
+    fn menu_open(&self) -> bool {
+        !self.pages.is_empty()
+    }
+
+    // Change a setting and write the file back. Per rom keys go in the
+    // rom's section, others only when the section already overrides them
+    fn save_setting(&mut self, key: &str, value: &str, per_rom: bool) {
+        let rom = (per_rom || self.settings.overrides(&self.rom_name, key)).then_some(self.rom_name.as_str());
+        self.settings.set(rom, key, value);
+        if let Err(e) = self.settings.save(CONFIG_FILE) {
+            self.error(&format!("Unable to save {}: {}", CONFIG_FILE, e));
+        }
+    }
+
+    // Bindings that differ from the current ones are saved
+    fn set_keymap(&mut self, keymap: Keymap) {
+        let old = std::mem::replace(&mut self.keymap, keymap);
+        for k in (0..16).filter(|&k| old.get(k) != keymap.get(k)) {
+            self.save_setting(&format!("key.{:x}", k), &keymap.get(k).name(), false);
+        }
+    }
+
+    fn slot_path(&self, slot: usize) -> PathBuf {
+        Path::new(STATE_DIR).join(format!("{}.{}.state", self.rom_name, slot))
+    }
+
+    fn save_slot(&mut self, slot: usize) {
+        let path = self.slot_path(slot);
+        let saved = fs::create_dir_all(STATE_DIR).and_then(|_| fs::write(&path, state::serialize(&self.chip8)));
+        match saved {
+            Ok(()) => self.notify(&format!("Saved slot {}", slot)),
+            Err(e) => self.error(&format!("Unable to save {}: {}", path.display(), e)),
+        }
+    }
+
+    // States don't hold the settings, the current quirks and timing stay
+    fn load_slot(&mut self, slot: usize) -> bool {
+        let path = self.slot_path(slot);
+        let loaded = fs::read(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|bytes| state::deserialize(&bytes).map_err(|e| e.to_string()));
+        match loaded {
+            Ok(mut chip) => {
+                chip.set_quirks(self.chip8.quirks());
+                chip.set_timing(self.chip8.timing());
+                self.chip8 = chip;
+                self.faulted = false;
+                self.rewind.capture(&self.chip8);
+                self.notify(&format!("Loaded slot {}", slot));
+                true
+            }
+            Err(e) => {
+                self.error(&format!("Unable to load {}: {}", path.display(), e));
+                false
+            }
+        }
+    }
+
+    // Title, items and the item showing the current value
+    fn page_items(&self, page: Page) -> (String, Vec<String>, usize) {
+        match page {
+            Page::Main => ("Menu".to_string(), Page::SETTINGS.iter().map(|(name, _)| name.to_string()).collect(), 0),
+            Page::Speed => {
+                let speed = match self.chip8.timing() {
+                    Timing::Ticks => format!("Ticks per frame  {}", self.ticks_per_frame),
+                    Timing::Vip => "Ticks per frame  fixed".to_string(),
+                };
+                ("Speed".to_string(), vec![speed, format!("Timing  {}", self.chip8.timing().name())], 0)
+            }
+            Page::Quirks => {
+                let quirks = self.chip8.quirks();
+                let current = Quirks::PROFILES.iter().position(|&(_, profile)| profile == quirks);
+                (
+                    format!("Quirks: {}", quirks.name().unwrap_or("custom")),
+                    Quirks::PROFILES.iter().map(|(name, _)| name.to_string()).collect(),
+                    current.unwrap_or(0),
+                )
+            }
+            Page::Palette => {
+                let current = self.palettes.current().name.clone();
+                let names: Vec<String> = self.palettes.names().map(str::to_string).collect();
+                let selected = names.iter().position(|name| *name == current).unwrap_or(0);
+                (format!("Palette: {}", current), names, selected)
+            }
+            Page::Keys => {
+                let title = match self.binding {
+                    Some(k) => format!("Press a key for {:X}, Esc cancels", k),
+                    None => "Keys".to_string(),
+                };
+                let mut items: Vec<String> =
+                    keymap::LAYOUT.iter().map(|&k| format!("{:X}  {}", k, self.keymap.get(k).name())).collect();
+                items.push("Defaults".to_string());
+                (title, items, 0)
+            }
+            Page::Audio => {
+                let volume = match self.audio_driver.volume() {
+                    0 => "Volume  off".to_string(),
+                    volume => format!("Volume  {}%", volume),
+                };
+                ("Audio".to_string(), vec![volume, format!("Tone  {} Hz", self.audio_driver.tone())], 0)
+            }
+            Page::Slots => {
+                let items = (1..=SAVE_SLOTS)
+                    .flat_map(|slot| {
+                        let empty = if self.slot_path(slot).is_file() { "" } else { " (empty)" };
+                        [format!("Save to slot {}", slot), format!("Load slot {}{}", slot, empty)]
+                    })
+                    .collect();
+                ("Save slots".to_string(), items, 0)
+            }
+            Page::Roms => {
+                let names = self
+                    .rom_list
+                    .iter()
+                    .map(|file| Path::new(file).file_name().map_or(file.clone(), |name| name.to_string_lossy().into_owned()))
+                    .collect();
+                let current = self.rom_list.iter().position(|file| Path::new(file) == Path::new(&self.rom_path));
+                ("Roms".to_string(), names, current.unwrap_or(0))
+            }
+        }
+    }
+
+    // Open a page over the current one, the game stops while a menu is up
+    fn open_page(&mut self, page: Page) {
+        if page == Page::Roms {
+            self.rom_list = match menu::rom_files(ROM_DIR) {
+                Ok(files) if !files.is_empty() => files,
+                Ok(_) => return self.error(&format!("No roms in {}", ROM_DIR)),
+                Err(e) => return self.error(&format!("Unable to list {}: {}", ROM_DIR, e)),
+            };
+        }
+        if let (Some(top), Some(menu)) = (self.pages.last_mut(), self.video_driver.overlay().menu()) {
+            top.1 = menu.selected();
+        }
+        let (title, items, current) = self.page_items(page);
+        let mut menu = Menu::new(&title, items);
+        menu.select(current);
+        self.pages.push((page, current));
+        self.video_driver.overlay().open_menu(menu);
+        self.audio_driver.set_beeping(false);
+    }
+
+    // Items again after a change, the selection stays
+    fn refresh_page(&mut self) {
+        if let Some(&(page, _)) = self.pages.last() {
+            let (title, items, _) = self.page_items(page);
+            if let Some(menu) = self.video_driver.overlay().menu() {
+                menu.set_items(&title, items);
+            }
+        }
+    }
+
+    // Back to the page before, closing the menu from the first
+    fn back(&mut self) {
+        self.binding = None;
+        self.pages.pop();
+        match self.pages.last() {
+            Some(&(page, selected)) => {
+                let (title, items, _) = self.page_items(page);
+                let mut menu = Menu::new(&title, items);
+                menu.select(selected);
+                self.video_driver.overlay().open_menu(menu);
+            }
+            None => {
+                self.video_driver.overlay().close_menu();
+            }
+        }
+    }
+
+    fn close_menu(&mut self) {
+        self.pages.clear();
+        self.binding = None;
+        self.video_driver.overlay().close_menu();
+    }
+
+    // Left and right step a value, select steps it forward
+    fn adjust(&mut self, page: Page, item: usize, step: i32) {
+        match (page, item) {
+            (Page::Speed, 0) if self.chip8.timing() == Timing::Ticks => {
+                self.ticks_per_frame = (self.ticks_per_frame as i32 + step).max(1) as u16;
+                self.save_setting("speed", &self.ticks_per_frame.to_string(), false);
+            }
+            (Page::Speed, 1) => {
+                let timing = match self.chip8.timing() {
+                    Timing::Ticks => Timing::Vip,
+                    Timing::Vip => Timing::Ticks,
+                };
+                self.chip8.set_timing(timing);
+                self.save_setting("timing", timing.name(), true);
+            }
+            (Page::Audio, 0) => {
+                let volume = (self.audio_driver.volume() as i32 + step * 10).clamp(0, 100);
+                self.audio_driver.set_volume(volume as u8);
+                self.save_setting("volume", &volume.to_string(), false);
+            }
+            (Page::Audio, 1) => {
+                let tone = (self.audio_driver.tone() as i32 + step * 50).max(0);
+                self.audio_driver.set_tone(tone as u32);
+                self.save_setting("tone", &self.audio_driver.tone().to_string(), false);
+            }
+            _ => return,
+        }
+        self.refresh_page();
+    }
+
+    fn choose(&mut self, page: Page, item: usize) {
+        match page {
+            Page::Main => self.open_page(Page::SETTINGS[item].1),
+            Page::Speed | Page::Audio => self.adjust(page, item, 1),
+            Page::Quirks => {
+                let (name, quirks) = Quirks::PROFILES[item];
+                self.chip8.set_quirks(quirks);
+                self.save_setting("quirks", name, true);
+                self.refresh_page();
+            }
+            Page::Palette => {
+                let name = self.palettes.names().nth(item).unwrap_or_default().to_string();
+                self.palettes.select(&name);
+                self.apply_palette();
+                self.save_setting("palette", &name, false);
+                self.refresh_page();
+            }
+            Page::Keys => {
+                match keymap::LAYOUT.get(item) {
+                    Some(&k) => self.binding = Some(k),
+                    None => self.set_keymap(Keymap::default()),
+                }
+                self.refresh_page();
+            }
+            Page::Slots if item.is_multiple_of(2) => {
+                self.save_slot(item / 2 + 1);
+                self.refresh_page();
+            }
+            Page::Slots => {
+                if self.load_slot(item / 2 + 1) {
+                    self.close_menu();
+                }
+            }
+            Page::Roms => {
+                let Some(rom) = self.rom_list.get(item).cloned() else {
+                    return;
+                };
+                self.close_menu();
+                match self.open(&rom) {
+                    Ok(()) => self.notify(&format!("Loaded {}", self.rom_name)),
+                    Err(e) => self.error(&e),
+                }
+            }
+        }
+    }
+
+    // Keyboard or gamepad in an open menu
+    fn menu_input(&mut self, input: Input) {
+        let Some(&(page, _)) = self.pages.last() else {
+            return;
+        };
+        let Some(menu) = self.video_driver.overlay().menu() else {
+            return;
+        };
+        let item = menu.selected();
+        match input {
+            Input::Up => menu.move_by(-1),
+            Input::Down => menu.move_by(1),
+            Input::PageUp => menu.move_by(-(menu::VISIBLE_ITEMS as isize)),
+            Input::PageDown => menu.move_by(menu::VISIBLE_ITEMS as isize),
+            Input::Left => self.adjust(page, item, -1),
+            Input::Right => self.adjust(page, item, 1),
+            Input::Select => self.choose(page, item),
+            Input::Back => self.back(),
+        }
+    }
+
+    // Keys while a menu is open, the game doesn't see them
+    fn menu_key(&mut self, key: Keycode) {
+        if let Some(k) = self.binding {
+            if keymap::is_reserved(key) && key != Keycode::Escape {
+                // Still waiting, Esc cancels
+                self.error(&format!("{} is a hotkey, pick another", key.name()));
+                return;
+            }
+            if key != Keycode::Escape {
+                let mut keymap = self.keymap;
+                keymap.bind(k, key);
+                self.set_keymap(keymap);
+            }
+            self.binding = None;
+            self.refresh_page();
+        } else if matches!(key, Keycode::Tab | Keycode::F4) {
+            self.close_menu();
+        } else if let Some(input) = Input::from_key(key) {
+            self.menu_input(input);
+        }
+        self.video_driver.draw(self.chip8.framebuffer());
     }
 
     pub fn run(&mut self, rom:&str) {
-        self.chip8.load_rom(&rom).expect("Unable to load rom");
+        if let Err(e) = self.open(rom) {
+            println!("{}", e);
+            return;
+        }
         // let mut clock_hertz = Instant::now();
         let mut timer = Instant::now();
-        let mut ticks_per_frame = 9;
         let mut counter = 0;
 
-        println!("Ticks per frame: {ticks_per_frame}");
+        match self.chip8.timing() {
+            Timing::Ticks => println!("Ticks per frame: {}", self.ticks_per_frame),
+            Timing::Vip => println!("VIP timing"),
+        }
         println!("Clock Delay: {}", BUFFER_DELAY);
         let mut clock_counter = 0;
         let mut timer_counter = 0;
         
 
         let mut rewind_ctr = 0;
-        self.rewind.capture(&self.chip8);
 
         let mut frame_buffer = Instant::now();
+        self.update_title();
         'runner: loop {
+            // Debugger commands from the terminal
+            while let Some(line) = self.debugger.poll() {
+                let was_paused = self.paused;
+                let output = self.debugger.command(&line, &mut self.chip8, &mut self.paused);
+                println!("{}", output);
+                if self.paused != was_paused {
+                    self.update_title();
+                    self.update_status();
+                }
+            }
+
+            self.poll_gdb();
+
+            if let Some(program) = self.watcher.as_mut().and_then(RomWatcher::poll) {
+                self.reload(&program);
+            }
+
             // Get input
-            for event in self.input_driver.poll() {
+            let events: Vec<Event> = self.input_driver.poll().collect();
+            for event in events {
                 match event {
-                    Event::Quit { .. }
-                    | Event::KeyDown {
+                    Event::Quit { .. } => break 'runner,
+                    Event::KeyDown {
+                        keycode: Some(key), ..
+                    } if self.menu_open() => {
+                        self.menu_key(key);
+                        self.update_title();
+                        self.update_status();
+                    }
+                    Event::KeyDown {
                         keycode: Some(Keycode::Escape),
                         ..
                     } => {
@@ -61,40 +869,131 @@ impl Emulator {
                         keycode: Some(key), ..
                     } => {
                         // Interpret keycode for chip8
-                        if let Some(k) = match_key(key) {
+                        if let Some(k) = self.keymap.key(key) {
                             if !self.paused {
                                 self.chip8.set_key(k, true);
                             }
                         }
-                        // Increment ticks per frame
-                        if key == Keycode::Up {
-                            ticks_per_frame += 1;
-                        }
-                        // Decrement ticks per frame
-                        if key == Keycode::Down {
-                            if ticks_per_frame > 1 {
-                                ticks_per_frame -= 1;
-                            }
+                        // Ticks per frame up and down, VIP timing has its own speed
+                        let vip = self.chip8.timing() == Timing::Vip;
+                        if matches!(key, Keycode::Up | Keycode::Down) && vip {
+                            self.notify("Speed is fixed with VIP timing");
+                        } else if key == Keycode::Up {
+                            self.ticks_per_frame += 1;
+                            self.notify(&format!("Speed {} ticks/frame", self.ticks_per_frame));
+                        } else if key == Keycode::Down && self.ticks_per_frame > 1 {
+                            self.ticks_per_frame -= 1;
+                            self.notify(&format!("Speed {} ticks/frame", self.ticks_per_frame));
                         }
                         // Pause game
                         if key == Keycode::Space {
-                            if self.paused {
-                                self.paused = false;
-                            } else {
-                                self.paused = true;
-                            }
+                            self.paused = !self.paused;
+                            self.notify(if self.paused { "Paused" } else { "Running" });
                         }
                         // Rewind
                         if key == Keycode::Left {
                             self.chip8 = self.rewind.step_back();
+                            self.faulted = false;
                             rewind_ctr = 0;
+                            self.notify("Rewind");
+                        }
+                        // Next palette
+                        if key == Keycode::P {
+                            self.palettes.cycle();
+                            let name = self.palettes.current().name.clone();
+                            self.notify(&format!("Palette {}", name));
+                            self.apply_palette();
+                        }
+                        // Overlay status line
+                        if key == Keycode::F1 {
+                            self.video_driver.overlay().toggle_status();
+                        }
+                        // Soft and hard reset, the rom browser and the menu
+                        match key {
+                            Keycode::F2 => self.reset(false),
+                            Keycode::F3 => self.reset(true),
+                            Keycode::F4 => self.open_page(Page::Roms),
+                            Keycode::Tab => self.open_page(Page::Main),
+                            _ => (),
+                        }
+                        // Toggle pixel persistence
+                        if key == Keycode::G {
+                            let decay = match self.video_driver.persistence() {
+                                Some(_) => None,
+                                None => Some(
+                                    self.settings_f32("persistence")
+                                        .filter(|&d| d > 0.0)
+                                        .unwrap_or(PERSISTENCE_DECAY),
+                                ),
+                            };
+                            self.video_driver.set_persistence(decay);
+                            self.video_driver.draw(self.chip8.framebuffer());
                         }
+                        // Post processing effects
+                        let mut effects = self.video_driver.effects();
+                        match key {
+                            Keycode::F5 => effects.scanlines = !effects.scanlines,
+                            Keycode::F6 => effects.grid = !effects.grid,
+                            Keycode::F7 => effects.bloom = !effects.bloom,
+                            Keycode::F8 => effects.curvature = !effects.curvature,
+                            _ => (),
+                        }
+                        if effects != self.video_driver.effects() {
+                            self.video_driver.set_effects(effects);
+                            self.video_driver.draw(self.chip8.framebuffer());
+                        }
+                        // Cheats on or off
+                        if key == Keycode::F9 {
+                            let cheats = self.debugger.cheats();
+                            cheats.enabled = !cheats.enabled;
+                            let message = match (cheats.enabled, cheats.list().len()) {
+                                (_, 0) => "No cheats, add some with cz".to_string(),
+                                (true, n) => format!("{} cheats on", n),
+                                (false, _) => "Cheats off".to_string(),
+                            };
+                            self.notify(&message);
+                        }
+                        // Fullscreen
+                        if key == Keycode::F11 {
+                            self.video_driver.toggle_fullscreen();
+                        }
+                        // Screenshot
+                        if key == Keycode::F12 {
+                            self.screenshot();
+                        }
+                        self.update_title();
+                        self.update_status();
+                    }
+                    Event::ControllerDeviceAdded { which, .. } => self.input_driver.add_pad(which),
+                    Event::ControllerDeviceRemoved { which, .. } => self.input_driver.remove_pad(which),
+                    // Start opens and closes the menu, the pad works it like the arrow keys
+                    Event::ControllerButtonDown { button, .. } => {
+                        if button == Button::Start {
+                            if self.menu_open() {
+                                self.close_menu();
+                            } else {
+                                self.open_page(Page::Main);
+                            }
+                        } else if self.menu_open() && self.binding.is_none() {
+                            if let Some(input) = Input::from_button(button) {
+                                self.menu_input(input);
+                            }
+                        }
+                        self.video_driver.draw(self.chip8.framebuffer());
+                        self.update_title();
+                        self.update_status();
+                    }
+                    Event::Window {
+                        win_event: WindowEvent::SizeChanged(..) | WindowEvent::Exposed,
+                        ..
+                    } => {
+                        self.video_driver.present();
                     }
 
                     Event::KeyUp {
                         keycode: Some(key), ..
                     } => {
-                        if let Some(k) = match_key(key) {
+                        if let Some(k) = self.keymap.key(key) {
                             if !self.paused {
                                 self.chip8.set_key(k, false);
                             }
@@ -103,7 +1002,7 @@ impl Emulator {
                     _ => (),
                 }
             }
-            if !self.paused {
+            if !self.paused && !self.menu_open() {
                 if rewind_ctr > 250 {
                     self.rewind.capture(&self.chip8);
                     rewind_ctr = 0;
@@ -116,19 +1015,52 @@ impl Emulator {
                         clock_counter as f32 / 1.0,
                         timer_counter as f32 / 1.0
                     );
+                    self.rates = (clock_counter, timer_counter);
+                    self.update_status();
                     clock_counter = 0;
                     timer_counter = 0;
                     timer = Instant::now();
                 }
     
-                self.chip8.cycle();
+                let pc = self.chip8.pc();
+                if self.gdb.as_mut().is_some_and(|stub| stub.hits(pc)) {
+                    self.paused = true;
+                    if let Some(stub) = self.gdb.as_mut() {
+                        stub.stopped(gdb::SIGTRAP);
+                    }
+                    self.notify(&format!("Breakpoint at {:03X}", pc));
+                    self.update_title();
+                    self.update_status();
+                    continue;
+                }
+
+                self.step();
+                if self.faulted {
+                    if let Some(stub) = self.gdb.as_mut() {
+                        stub.stopped(gdb::SIGILL);
+                    }
+                }
                 counter += 1;
-    
-                if counter >= ticks_per_frame {
-                    self.chip8.timer_tick();
-                    if self.chip8.vram_change {
-                        self.video_driver.draw(&self.chip8.vram);
-                        self.chip8.vram_change = false;
+
+                // With VIP timing the chip ticks its own timers in the interrupt
+                let frame_end = match self.chip8.timing() {
+                    Timing::Ticks => counter >= self.ticks_per_frame,
+                    Timing::Vip => self.chip8.take_vblank(),
+                };
+                if frame_end {
+                    if self.chip8.timing() == Timing::Ticks {
+                        self.chip8.timer_tick();
+                    }
+                    self.audio_driver.set_beeping(self.chip8.sound_timer() > 0);
+                    self.debugger.cheats().apply(&mut self.chip8);
+                    self.script_hook(Script::frame_end);
+                    self.video_driver.end_frame(self.chip8.framebuffer());
+                    let changed = self.chip8.take_vram_change();
+                    if changed || self.video_driver.needs_redraw() {
+                        self.video_driver.draw(self.chip8.framebuffer());
+                    }
+                    if self.script_actions() {
+                        break 'runner;
                     }
                     timer_counter += 1;
                     counter = 0;
@@ -140,29 +1072,28 @@ impl Emulator {
                 }
     
                 clock_counter += 1;
+            } else {
+                // Keep notifications and pixels fading and menus drawn while nothing runs
+                self.audio_driver.set_beeping(false);
+                if self.video_driver.needs_redraw() {
+                    self.video_driver.end_frame(self.chip8.framebuffer());
+                    self.video_driver.draw(self.chip8.framebuffer());
+                }
+                thread::sleep(time::Duration::from_millis(BUFFER_DELAY));
+                frame_buffer = Instant::now();
             }
             }
+
+        if let Err(e) = self.write_profile() {
+            println!("Unable to write profile: {}", e);
+        }
     }
 }
 
-fn match_key(key: Keycode) -> Option<usize> {
-    match key {
-        Keycode::Num1 => Some(0x1),
-        Keycode::Num2 => Some(0x2),
-        Keycode::Num3 => Some(0x3),
-        Keycode::Num4 => Some(0xC),
-        Keycode::Q => Some(0x4),
-        Keycode::W => Some(0x5),
-        Keycode::E => Some(0x6),
-        Keycode::R => Some(0xD),
-        Keycode::A => Some(0x7),
-        Keycode::S => Some(0x8),
-        Keycode::D => Some(0x9),
-        Keycode::F => Some(0xE),
-        Keycode::Z => Some(0xA),
-        Keycode::X => Some(0x0),
-        Keycode::C => Some(0xB),
-        Keycode::V => Some(0xF), 
-        _ => None,
-    }
+// Seeds each run differently
+fn time_seed() -> u32 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or(0)
 }
@@ -0,0 +1,541 @@
+use chip8_core::Chip8;
+use std::collections::BTreeSet;
+use std::fmt::Write as _;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+
+// Register numbers as gdb sees them, V0-VF then these, I and pc are
+// 16 bit little endian, the rest 8 bit
+const REG_I: usize = 16;
+const REG_PC: usize = 17;
+const REG_SP: usize = 18;
+const REG_DT: usize = 19;
+const REG_ST: usize = 20;
+const REGISTERS: usize = 21;
+
+const MEMORY_SIZE: usize = 4096;
+const PACKET_SIZE: usize = 0x1000;
+
+// Signals in stop replies
+pub const SIGINT: u8 = 2;
+pub const SIGILL: u8 = 4;
+pub const SIGTRAP: u8 = 5;
+
+// What the stub wants the emulator to do after a poll
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Request {
+    // Client attached or interrupted, pause
+    Halt,
+    // Run until a breakpoint, also sent on detach
+    Continue,
+    // Run one instruction then report with stopped()
+    Step,
+}
+
+// GDB remote serial protocol server on localhost, one client at a time,
+// polled from the emulator loop so it never blocks
+pub struct GdbStub {
+    listener: TcpListener,
+    client: Option<TcpStream>,
+    input: Vec<u8>,
+    breakpoints: BTreeSet<usize>,
+    // Resumed by c or s and no stop reply sent yet
+    running: bool,
+    // Where the last continue started, its breakpoint is stepped over
+    resumed_from: Option<usize>,
+}
+
+impl GdbStub {
+    pub fn bind(port: u16) -> io::Result<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        listener.set_nonblocking(true)?;
+        Ok(Self {
+            listener,
+            client: None,
+            input: Vec::new(),
+            breakpoints: BTreeSet::new(),
+            running: false,
+            resumed_from: None,
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    pub fn is_attached(&self) -> bool {
+        self.client.is_some()
+    }
+
+    // Accept a client or handle what it sent, one request at a time
+    pub fn poll(&mut self, chip: &mut Chip8) -> Option<Request> {
+        if self.client.is_none() {
+            let (stream, _) = self.listener.accept().ok()?;
+            if stream.set_nonblocking(true).is_err() {
+                return None;
+            }
+            let _ = stream.set_nodelay(true);
+            self.client = Some(stream);
+            self.input.clear();
+            self.running = false;
+            return Some(Request::Halt);
+        }
+
+        let mut buffer = [0u8; 1024];
+        loop {
+            let read = match self.client.as_mut()?.read(&mut buffer) {
+                Ok(read) => read,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(_) => 0,
+            };
+            if read == 0 {
+                // Gone without a detach, let the game carry on
+                self.disconnect();
+                return Some(Request::Continue);
+            }
+            self.input.extend_from_slice(&buffer[..read]);
+        }
+
+        while let Some(&first) = self.input.first() {
+            match first {
+                b'$' => {
+                    let Some(end) = self.input.iter().position(|&b| b == b'#') else { break };
+                    if self.input.len() < end + 3 {
+                        break;
+                    }
+                    let packet: Vec<u8> = self.input.drain(..end + 3).collect();
+                    let body = &packet[1..end];
+                    let sum = std::str::from_utf8(&packet[end + 1..])
+                        .ok()
+                        .and_then(|s| u8::from_str_radix(s, 16).ok());
+                    if sum != Some(checksum(body)) {
+                        self.send_raw(b"-");
+                        continue;
+                    }
+                    self.send_raw(b"+");
+                    let body = String::from_utf8_lossy(body).into_owned();
+                    if let Some(request) = self.handle(&body, chip) {
+                        return Some(request);
+                    }
+                }
+                // Ctrl-C from the client
+                0x03 => {
+                    self.input.remove(0);
+                    if self.running {
+                        self.stopped(SIGINT);
+                        return Some(Request::Halt);
+                    }
+                }
+                // Acks and anything between packets
+                _ => {
+                    self.input.remove(0);
+                }
+            }
+        }
+        None
+    }
+
+    // Check before running the instruction at pc while continuing
+    pub fn hits(&mut self, pc: usize) -> bool {
+        let resumed_here = self.resumed_from.take() == Some(pc);
+        self.running && !resumed_here && self.breakpoints.contains(&pc)
+    }
+
+    // Tell the client the machine stopped after a continue or step
+    pub fn stopped(&mut self, signal: u8) {
+        if self.running {
+            self.running = false;
+            self.send(&format!("S{:02x}", signal));
+        }
+    }
+
+    fn handle(&mut self, packet: &str, chip: &mut Chip8) -> Option<Request> {
+        let (command, args) = packet.split_at(packet.chars().next().map_or(0, |c| c.len_utf8()));
+        let reply = match command {
+            "?" => format!("S{:02x}", SIGTRAP),
+            "g" => (0..REGISTERS).map(|n| read_register(chip, n)).collect(),
+            "G" => {
+                let Some(bytes) = from_hex(args) else {
+                    self.send("E01");
+                    return None;
+                };
+                let mut offset = 0;
+                for n in 0..REGISTERS {
+                    let width = register_width(n);
+                    let Some(value) = bytes.get(offset..offset + width) else { break };
+                    // sp only moves with CALL and RET
+                    if n != REG_SP {
+                        write_register(chip, n, value);
+                    }
+                    offset += width;
+                }
+                "OK".to_string()
+            }
+            "p" => match usize::from_str_radix(args, 16) {
+                Ok(n) if n < REGISTERS => read_register(chip, n),
+                _ => "E01".to_string(),
+            },
+            "P" => {
+                let parsed = args.split_once('=').and_then(|(n, value)| {
+                    Some((usize::from_str_radix(n, 16).ok()?, from_hex(value)?))
+                });
+                match parsed {
+                    Some((n, value)) if n < REGISTERS && n != REG_SP && value.len() == register_width(n) => {
+                        write_register(chip, n, &value);
+                        "OK".to_string()
+                    }
+                    _ => "E01".to_string(),
+                }
+            }
+            "m" => match parse_range(args) {
+                Some((address, len)) if address < MEMORY_SIZE => {
+                    let end = address.saturating_add(len).min(MEMORY_SIZE);
+                    to_hex(&chip.memory()[address..end])
+                }
+                _ => "E01".to_string(),
+            },
+            "M" => {
+                let parsed = args.split_once(':').and_then(|(range, data)| Some((parse_range(range)?, from_hex(data)?)));
+                match parsed {
+                    Some(((address, len), data)) if data.len() == len && address.saturating_add(len) <= MEMORY_SIZE => {
+                        for (offset, &byte) in data.iter().enumerate() {
+                            chip.poke(address + offset, byte);
+                        }
+                        "OK".to_string()
+                    }
+                    _ => "E01".to_string(),
+                }
+            }
+            // Software and hardware breakpoints are the same thing here
+            "Z" | "z" => {
+                let mut fields = args.split(',');
+                let kind = fields.next();
+                let address = fields.next().and_then(|a| usize::from_str_radix(a, 16).ok());
+                match (kind, address) {
+                    (Some("0") | Some("1"), Some(address)) => {
+                        if command == "Z" {
+                            self.breakpoints.insert(address);
+                        } else {
+                            self.breakpoints.remove(&address);
+                        }
+                        "OK".to_string()
+                    }
+                    _ => String::new(),
+                }
+            }
+            "c" | "s" => {
+                if let Ok(address) = usize::from_str_radix(args, 16) {
+                    chip.set_pc(address);
+                }
+                self.running = true;
+                self.resumed_from = Some(chip.pc());
+                // Reply comes from stopped()
+                return Some(if command == "c" { Request::Continue } else { Request::Step });
+            }
+            "D" => {
+                self.send("OK");
+                self.disconnect();
+                return Some(Request::Continue);
+            }
+            "k" => {
+                self.disconnect();
+                return Some(Request::Continue);
+            }
+            "H" => "OK".to_string(),
+            "q" => self.query(args),
+            _ => String::new(),
+        };
+        self.send(&reply);
+        None
+    }
+
+    fn query(&self, query: &str) -> String {
+        if query.starts_with("Supported") {
+            format!("PacketSize={:x};qXfer:features:read+", PACKET_SIZE)
+        } else if query == "Attached" {
+            "1".to_string()
+        } else if let Some(range) = query.strip_prefix("Xfer:features:read:target.xml:") {
+            match parse_range(range) {
+                Some((offset, len)) => {
+                    let xml = target_xml();
+                    let end = offset.saturating_add(len).min(xml.len());
+                    let chunk = xml.get(offset.min(end)..end).unwrap_or("");
+                    format!("{}{}", if end < xml.len() { "m" } else { "l" }, chunk)
+                }
+                None => "E01".to_string(),
+            }
+        } else {
+            String::new()
+        }
+    }
+
+    fn disconnect(&mut self) {
+        self.client = None;
+        self.input.clear();
+        self.breakpoints.clear();
+        self.running = false;
+        self.resumed_from = None;
+    }
+
+    fn send(&mut self, body: &str) {
+        self.send_raw(frame(body).as_bytes());
+    }
+
+    fn send_raw(&mut self, bytes: &[u8]) {
+        if let Some(client) = self.client.as_mut() {
+            // Replies are small, spin through a full send buffer
+            let mut rest = bytes;
+            while !rest.is_empty() {
+                match client.write(rest) {
+                    Ok(0) => break,
+                    Ok(written) => rest = &rest[written..],
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => std::thread::yield_now(),
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+                    Err(_) => break,
+                }
+            }
+        }
+    }
+}
+
+fn register_width(n: usize) -> usize {
+    if n == REG_I || n == REG_PC {
+        2
+    } else {
+        1
+    }
+}
+
+fn read_register(chip: &Chip8, n: usize) -> String {
+    match n {
+        REG_I => to_hex(&(chip.index() as u16).to_le_bytes()),
+        REG_PC => to_hex(&(chip.pc() as u16).to_le_bytes()),
+        REG_SP => format!("{:02x}", chip.sp()),
+        REG_DT => format!("{:02x}", chip.delay_timer()),
+        REG_ST => format!("{:02x}", chip.sound_timer()),
+        _ => format!("{:02x}", chip.register(n)),
+    }
+}
+
+fn write_register(chip: &mut Chip8, n: usize, value: &[u8]) {
+    let wide = || u16::from_le_bytes([value[0], value[1]]) as usize;
+    match n {
+        REG_I => chip.set_index(wide()),
+        REG_PC => chip.set_pc(wide()),
+        REG_SP => (),
+        REG_DT => chip.set_delay_timer(value[0]),
+        REG_ST => chip.set_sound_timer(value[0]),
+        _ => chip.set_register(n, value[0]),
+    }
+}
+
+// Register layout for gdb, served through qXfer
+fn target_xml() -> String {
+    let mut xml = String::from(
+        "<?xml version=\"1.0\"?>\n<!DOCTYPE target SYSTEM \"gdb-target.dtd\">\n\
+         <target version=\"1.0\">\n<feature name=\"org.chip8.core\">\n",
+    );
+    for n in 0..16 {
+        let _ = writeln!(xml, "<reg name=\"v{:x}\" bitsize=\"8\" type=\"uint8\"/>", n);
+    }
+    xml.push_str(
+        "<reg name=\"i\" bitsize=\"16\" type=\"data_ptr\"/>\n\
+         <reg name=\"pc\" bitsize=\"16\" type=\"code_ptr\"/>\n\
+         <reg name=\"sp\" bitsize=\"8\" type=\"uint8\"/>\n\
+         <reg name=\"dt\" bitsize=\"8\" type=\"uint8\"/>\n\
+         <reg name=\"st\" bitsize=\"8\" type=\"uint8\"/>\n\
+         </feature>\n</target>\n",
+    );
+    xml
+}
+
+fn checksum(body: &[u8]) -> u8 {
+    body.iter().fold(0u8, |sum, &b| sum.wrapping_add(b))
+}
+
+// $body#checksum
+fn frame(body: &str) -> String {
+    format!("${}#{:02x}", body, checksum(body.as_bytes()))
+}
+
+// "addr,len" in hex
+fn parse_range(text: &str) -> Option<(usize, usize)> {
+    let (address, len) = text.split_once(',')?;
+    Some((usize::from_str_radix(address, 16).ok()?, usize::from_str_radix(len, 16).ok()?))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    // A gdb stand-in on the other end of a loopback socket
+    struct Client {
+        stream: TcpStream,
+        input: Vec<u8>,
+    }
+
+    impl Client {
+        fn connect(stub: &GdbStub) -> Self {
+            let stream = TcpStream::connect(stub.local_addr().unwrap()).unwrap();
+            stream.set_nonblocking(true).unwrap();
+            Self { stream, input: Vec::new() }
+        }
+
+        fn send(&mut self, body: &str) {
+            self.stream.write_all(frame(body).as_bytes()).unwrap();
+        }
+
+        // Next packet body, acks skipped
+        fn reply(&mut self) -> Option<String> {
+            let mut buffer = [0u8; 4096];
+            while let Ok(read) = self.stream.read(&mut buffer) {
+                if read == 0 {
+                    break;
+                }
+                self.input.extend_from_slice(&buffer[..read]);
+            }
+            while self.input.first().is_some_and(|&b| b == b'+') {
+                self.input.remove(0);
+            }
+            let end = self.input.iter().position(|&b| b == b'#')?;
+            if self.input.len() < end + 3 {
+                return None;
+            }
+            let packet: Vec<u8> = self.input.drain(..end + 3).collect();
+            assert_eq!(packet[0], b'$');
+            Some(String::from_utf8(packet[1..end].to_vec()).unwrap())
+        }
+    }
+
+    // Poll until the client has a reply or the stub a request
+    fn exchange(stub: &mut GdbStub, chip: &mut Chip8, client: &mut Client) -> (Option<String>, Option<Request>) {
+        let start = Instant::now();
+        while start.elapsed() < Duration::from_secs(2) {
+            let request = stub.poll(chip);
+            let reply = client.reply();
+            if reply.is_some() || request.is_some() {
+                return (reply, request);
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        panic!("no reply");
+    }
+
+    fn command(stub: &mut GdbStub, chip: &mut Chip8, client: &mut Client, packet: &str) -> String {
+        client.send(packet);
+        exchange(stub, chip, client).0.expect("reply")
+    }
+
+    fn attach() -> (GdbStub, Chip8, Client) {
+        let mut stub = GdbStub::bind(0).unwrap();
+        let mut chip = Chip8::new();
+        // 200 LD V0, 5; 202 LD I, 0x300; 204 ADD V0, 1; 206 JP 0x204
+        chip.load_program(&[0x60, 0x05, 0xA3, 0x00, 0x70, 0x01, 0x12, 0x04]).unwrap();
+        let mut client = Client::connect(&stub);
+        let (_, request) = exchange(&mut stub, &mut chip, &mut client);
+        assert_eq!(request, Some(Request::Halt));
+        (stub, chip, client)
+    }
+
+    #[test]
+    fn framing() {
+        assert_eq!(frame("OK"), "$OK#9a");
+        assert_eq!(frame(""), "$#00");
+        assert_eq!(from_hex("0aFf"), Some(vec![0x0A, 0xFF]));
+        assert_eq!(from_hex("abc"), None);
+        assert!(target_xml().contains("<reg name=\"vf\" bitsize=\"8\""));
+    }
+
+    #[test]
+    fn registers_and_memory() {
+        let (mut stub, mut chip, mut client) = attach();
+        assert!(stub.is_attached());
+        assert_eq!(command(&mut stub, &mut chip, &mut client, "?"), "S05");
+        assert!(command(&mut stub, &mut chip, &mut client, "qSupported:xmlRegisters=i386").contains("qXfer"));
+        let xml = target_xml();
+        let rest = command(&mut stub, &mut chip, &mut client, "qXfer:features:read:target.xml:1,ffffffffffffffff");
+        assert_eq!(rest, format!("l{}", &xml[1..]));
+
+        // V0-VF, I, pc 0x200 little endian, sp, DT, ST
+        let registers = command(&mut stub, &mut chip, &mut client, "g");
+        assert_eq!(registers, format!("{}00000002000000", "00".repeat(16)));
+        assert_eq!(command(&mut stub, &mut chip, &mut client, "P3=7f"), "OK");
+        assert_eq!(command(&mut stub, &mut chip, &mut client, "P10=3412"), "OK");
+        assert_eq!(chip.register(3), 0x7F);
+        assert_eq!(chip.index(), 0x1234);
+        assert_eq!(command(&mut stub, &mut chip, &mut client, "p11"), "0002");
+        assert_eq!(command(&mut stub, &mut chip, &mut client, "P12=01"), "E01");
+
+        assert_eq!(command(&mut stub, &mut chip, &mut client, "m200,4"), "6005a300");
+        assert_eq!(command(&mut stub, &mut chip, &mut client, "mffe,8"), "0000");
+        // Lengths past the end of the address space are cut short
+        assert_eq!(command(&mut stub, &mut chip, &mut client, "mffe,ffffffffffffffff"), "0000");
+        assert_eq!(command(&mut stub, &mut chip, &mut client, "M300,2:beef"), "OK");
+        assert_eq!(chip.peek(0x301), 0xEF);
+        assert_eq!(command(&mut stub, &mut chip, &mut client, "M300,2:be"), "E01");
+        assert_eq!(command(&mut stub, &mut chip, &mut client, "vMustReplyEmpty"), "");
+    }
+
+    #[test]
+    fn breakpoints_step_and_continue() {
+        let (mut stub, mut chip, mut client) = attach();
+        assert_eq!(command(&mut stub, &mut chip, &mut client, "Z0,204,2"), "OK");
+
+        // The emulator's side, run until the stub says stop
+        client.send("c");
+        assert_eq!(exchange(&mut stub, &mut chip, &mut client), (None, Some(Request::Continue)));
+        let mut ran = 0;
+        while !stub.hits(chip.pc()) {
+            chip.cycle().unwrap();
+            ran += 1;
+        }
+        assert_eq!((chip.pc(), ran), (0x204, 2));
+        stub.stopped(SIGTRAP);
+        assert_eq!(client.reply().as_deref(), Some("S05"));
+
+        // Continuing from a breakpoint runs it once before stopping again
+        client.send("c");
+        assert_eq!(exchange(&mut stub, &mut chip, &mut client).1, Some(Request::Continue));
+        ran = 0;
+        while !stub.hits(chip.pc()) {
+            chip.cycle().unwrap();
+            ran += 1;
+        }
+        assert_eq!((ran, chip.register(0)), (2, 6));
+        stub.stopped(SIGTRAP);
+        assert_eq!(client.reply().as_deref(), Some("S05"));
+
+        client.send("s");
+        assert_eq!(exchange(&mut stub, &mut chip, &mut client).1, Some(Request::Step));
+        chip.cycle().unwrap();
+        stub.stopped(SIGTRAP);
+        assert_eq!(client.reply().as_deref(), Some("S05"));
+        assert_eq!(chip.register(0), 7);
+
+        // Interrupt while running, then detach
+        assert_eq!(command(&mut stub, &mut chip, &mut client, "z0,204,2"), "OK");
+        client.send("c");
+        assert_eq!(exchange(&mut stub, &mut chip, &mut client).1, Some(Request::Continue));
+        client.stream.write_all(&[0x03]).unwrap();
+        assert_eq!(exchange(&mut stub, &mut chip, &mut client), (Some("S02".to_string()), Some(Request::Halt)));
+        client.send("D");
+        assert_eq!(exchange(&mut stub, &mut chip, &mut client), (Some("OK".to_string()), Some(Request::Continue)));
+        assert!(!stub.is_attached());
+    }
+}
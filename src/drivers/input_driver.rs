@@ -1,19 +1,42 @@
 use sdl2;
+use sdl2::controller::GameController;
 use sdl2::event::{EventPollIterator};
 
 pub struct InputDriver {
     events: sdl2::EventPump,
+    controllers: Option<sdl2::GameControllerSubsystem>,
+    // Open pads, they only send events while kept open
+    pads: Vec<GameController>,
 }
 
 impl InputDriver {
     pub fn new(sdl_context: &sdl2::Sdl) -> Self {
         InputDriver {
             events: sdl_context.event_pump().unwrap(),
+            controllers: sdl_context.game_controller().map_err(|e| println!("No gamepads: {}", e)).ok(),
+            pads: Vec::new(),
         }
     }
 
-    pub fn poll(&mut self) -> EventPollIterator {
-        return self.events.poll_iter();
+    pub fn poll(&mut self) -> EventPollIterator<'_> {
+        self.events.poll_iter()
+    }
+
+    // Pads already plugged in are announced as added when polling starts
+    pub fn add_pad(&mut self, which: u32) {
+        if let Some(controllers) = &self.controllers {
+            match controllers.open(which) {
+                Ok(pad) => {
+                    println!("Gamepad {}", pad.name());
+                    self.pads.push(pad);
+                }
+                Err(e) => println!("Unable to open gamepad {}: {}", which, e),
+            }
+        }
+    }
+
+    pub fn remove_pad(&mut self, which: u32) {
+        self.pads.retain(|pad| pad.instance_id() != which);
     }
 
 }
@@ -0,0 +1,124 @@
+use crate::drivers::settings::Settings;
+use sdl2::keyboard::Keycode;
+
+// Keypad keys as they sit on the COSMAC VIP, row by row
+pub const LAYOUT: [usize; 16] = [0x1, 0x2, 0x3, 0xC, 0x4, 0x5, 0x6, 0xD, 0x7, 0x8, 0x9, 0xE, 0xA, 0x0, 0xB, 0xF];
+
+// The left hand block of a qwerty keyboard, in keypad order 0-F
+const DEFAULT: [Keycode; 16] = [
+    Keycode::X,
+    Keycode::Num1,
+    Keycode::Num2,
+    Keycode::Num3,
+    Keycode::Q,
+    Keycode::W,
+    Keycode::E,
+    Keycode::A,
+    Keycode::S,
+    Keycode::D,
+    Keycode::Z,
+    Keycode::C,
+    Keycode::Num4,
+    Keycode::R,
+    Keycode::F,
+    Keycode::V,
+];
+
+// Emulator hotkeys and the F keys kept for them, a keypad key bound to
+// one would trigger both
+pub const RESERVED: [Keycode; 20] = [
+    Keycode::Escape,
+    Keycode::Tab,
+    Keycode::Space,
+    Keycode::Up,
+    Keycode::Down,
+    Keycode::Left,
+    Keycode::P,
+    Keycode::G,
+    Keycode::F1,
+    Keycode::F2,
+    Keycode::F3,
+    Keycode::F4,
+    Keycode::F5,
+    Keycode::F6,
+    Keycode::F7,
+    Keycode::F8,
+    Keycode::F9,
+    Keycode::F10,
+    Keycode::F11,
+    Keycode::F12,
+];
+
+// Keyboard key for each keypad key, `key.5 = W` in the settings rebinds one
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Keymap {
+    keys: [Keycode; 16],
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self { keys: DEFAULT }
+    }
+}
+
+impl Keymap {
+    pub fn from_settings(settings: &Settings) -> Self {
+        let mut keymap = Self::default();
+        for (key, name) in settings.with_prefix("key.") {
+            let index = usize::from_str_radix(key, 16).ok().filter(|&k| k < 16);
+            match (index, Keycode::from_name(name)) {
+                (Some(_), Some(keycode)) if is_reserved(keycode) => {
+                    println!("Ignoring key.{} = {}, it's a hotkey", key, name)
+                }
+                (Some(index), Some(keycode)) => keymap.bind(index, keycode),
+                _ => println!("Ignoring key.{} = {}", key, name),
+            }
+        }
+        keymap
+    }
+
+    pub fn get(&self, index: usize) -> Keycode {
+        self.keys[index]
+    }
+
+    // Keypad key a keyboard key is bound to
+    pub fn key(&self, keycode: Keycode) -> Option<usize> {
+        self.keys.iter().position(|&k| k == keycode)
+    }
+
+    // A keyboard key already bound elsewhere swaps with this one, so no
+    // keypad key is left without one
+    pub fn bind(&mut self, index: usize, keycode: Keycode) {
+        if let Some(other) = self.key(keycode) {
+            self.keys[other] = self.keys[index];
+        }
+        self.keys[index] = keycode;
+    }
+}
+
+pub fn is_reserved(keycode: Keycode) -> bool {
+    RESERVED.contains(&keycode)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn binding_swaps() {
+        let mut keymap = Keymap::default();
+        assert_eq!(keymap.key(Keycode::Num4), Some(0xC));
+        assert_eq!(keymap.key(Keycode::X), Some(0x0));
+        assert_eq!(keymap.key(Keycode::Up), None);
+
+        keymap.bind(0x5, Keycode::Up);
+        assert_eq!((keymap.key(Keycode::Up), keymap.key(Keycode::W)), (Some(0x5), None));
+        keymap.bind(0x8, Keycode::Up);
+        assert_eq!(keymap.key(Keycode::Up), Some(0x8));
+        assert_eq!(keymap.get(0x5), Keycode::S);
+
+        // The default layout stays clear of the hotkeys
+        assert!(!DEFAULT.iter().any(|&k| is_reserved(k)));
+        assert!(is_reserved(Keycode::Space) && is_reserved(Keycode::F12));
+    }
+}
@@ -0,0 +1,222 @@
+use crate::drivers::overlay::{draw_text, GLYPH_HEIGHT};
+use crate::drivers::palette::Rgb;
+use crate::drivers::postfx::Image;
+use sdl2::controller::Button;
+use sdl2::keyboard::Keycode;
+use std::fs;
+use std::io;
+
+const TITLE: Rgb = [255, 220, 120];
+const ITEM: Rgb = [190, 190, 190];
+const SELECTED: Rgb = [255, 255, 255];
+// Items shown at once, the list scrolls to keep the selection in view
+pub const VISIBLE_ITEMS: usize = 10;
+
+// Pages of the settings menu
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Page {
+    Main,
+    Speed,
+    Quirks,
+    Palette,
+    Keys,
+    Audio,
+    Slots,
+    Roms,
+}
+
+impl Page {
+    // Listed on the main page in this order
+    pub const SETTINGS: [(&'static str, Page); 7] = [
+        ("Speed", Page::Speed),
+        ("Quirks", Page::Quirks),
+        ("Palette", Page::Palette),
+        ("Keys", Page::Keys),
+        ("Audio", Page::Audio),
+        ("Save slots", Page::Slots),
+        ("Roms", Page::Roms),
+    ];
+}
+
+// What keyboard and gamepad do in a menu
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Input {
+    Up,
+    Down,
+    Left,
+    Right,
+    PageUp,
+    PageDown,
+    Select,
+    Back,
+}
+
+impl Input {
+    pub fn from_key(key: Keycode) -> Option<Self> {
+        match key {
+            Keycode::Up => Some(Input::Up),
+            Keycode::Down => Some(Input::Down),
+            Keycode::Left => Some(Input::Left),
+            Keycode::Right => Some(Input::Right),
+            Keycode::PageUp => Some(Input::PageUp),
+            Keycode::PageDown => Some(Input::PageDown),
+            Keycode::Return | Keycode::Space => Some(Input::Select),
+            Keycode::Escape | Keycode::Backspace => Some(Input::Back),
+            _ => None,
+        }
+    }
+
+    pub fn from_button(button: Button) -> Option<Self> {
+        match button {
+            Button::DPadUp => Some(Input::Up),
+            Button::DPadDown => Some(Input::Down),
+            Button::DPadLeft => Some(Input::Left),
+            Button::DPadRight => Some(Input::Right),
+            Button::LeftShoulder => Some(Input::PageUp),
+            Button::RightShoulder => Some(Input::PageDown),
+            Button::A => Some(Input::Select),
+            Button::B | Button::Back => Some(Input::Back),
+            _ => None,
+        }
+    }
+}
+
+// A list drawn over the frame, walked through with the arrow keys
+pub struct Menu {
+    title: String,
+    items: Vec<String>,
+    selected: usize,
+}
+
+impl Menu {
+    pub fn new(title: &str, items: Vec<String>) -> Self {
+        Self { title: title.to_string(), items, selected: 0 }
+    }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    pub fn items(&self) -> &[String] {
+        &self.items
+    }
+
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    // New items for the same page, the selection stays put
+    pub fn set_items(&mut self, title: &str, items: Vec<String>) {
+        self.title = title.to_string();
+        self.items = items;
+        self.select(self.selected);
+    }
+
+    pub fn select(&mut self, index: usize) {
+        self.selected = index.min(self.items.len().saturating_sub(1));
+    }
+
+    // Up and down wrap around the ends
+    pub fn move_by(&mut self, delta: isize) {
+        if !self.items.is_empty() {
+            let len = self.items.len() as isize;
+            self.selected = (self.selected as isize + delta).rem_euclid(len) as usize;
+        }
+    }
+
+    // First item on screen
+    fn first_visible(&self) -> usize {
+        let last_start = self.items.len().saturating_sub(VISIBLE_ITEMS);
+        self.selected.saturating_sub(VISIBLE_ITEMS / 2).min(last_start)
+    }
+
+    // Title then the items, centred, long lists show where they are in
+    // the title
+    pub fn render(&self, image: &mut Image) {
+        let scale = (image.width / 256).max(1);
+        let line = (GLYPH_HEIGHT + 3) * scale;
+        let first = self.first_visible();
+        let shown = &self.items[first..(first + VISIBLE_ITEMS).min(self.items.len())];
+
+        let height = line * (shown.len() + 1);
+        let mut y = image.height.saturating_sub(height) / 2;
+        let x = image.width / 8;
+        let title = if self.items.len() > VISIBLE_ITEMS {
+            format!("{} {}/{}", self.title, self.selected + 1, self.items.len())
+        } else {
+            self.title.clone()
+        };
+        draw_text(image, x, y, &title, scale, TITLE, 1.0);
+        for (i, item) in shown.iter().enumerate() {
+            y += line;
+            let (marker, color) = if first + i == self.selected { ("> ", SELECTED) } else { ("  ", ITEM) };
+            draw_text(image, x, y, &format!("{}{}", marker, item), scale, color, 1.0);
+        }
+    }
+}
+
+// Files in the rom directory, sorted, as paths to load
+pub fn rom_files(dir: &str) -> io::Result<Vec<String>> {
+    let mut files: Vec<String> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_file())
+        .map(|entry| entry.path().to_string_lossy().into_owned())
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::drivers::testing::scratch_dir;
+
+    fn menu(len: usize) -> Menu {
+        Menu::new("Roms", (0..len).map(|i| format!("rom {}", i)).collect())
+    }
+
+    #[test]
+    fn moves_and_scrolls() {
+        let mut menu = menu(25);
+        menu.move_by(-1);
+        assert_eq!(menu.selected(), 24);
+        assert_eq!(menu.first_visible(), 15);
+        menu.move_by(1);
+        assert_eq!((menu.selected(), menu.first_visible()), (0, 0));
+        menu.select(12);
+        assert_eq!(menu.first_visible(), 7);
+        menu.select(99);
+        assert_eq!(menu.selected(), 24);
+
+        let mut empty = Menu::new("Roms", Vec::new());
+        empty.move_by(1);
+        assert_eq!(empty.selected(), 0);
+
+        menu.set_items("Quirks", vec!["chip8".to_string(), "vip".to_string()]);
+        assert_eq!((menu.title(), menu.selected()), ("Quirks", 1));
+        assert_eq!(Input::from_key(Keycode::Return), Input::from_button(Button::A));
+    }
+
+    #[test]
+    fn draws_the_selection_brighter() {
+        let mut image = Image::new(512, 256);
+        let mut menu = menu(3);
+        menu.select(1);
+        menu.render(&mut image);
+        let brightest = image.pixels.iter().map(|p| p[0]).max().unwrap();
+        assert_eq!(brightest, 255);
+        assert!(image.pixels.contains(&ITEM));
+    }
+
+    #[test]
+    fn lists_rom_files() {
+        let dir = scratch_dir("roms");
+        fs::create_dir(dir.join("nested")).unwrap();
+        fs::write(dir.join("b.ch8"), [0]).unwrap();
+        fs::write(dir.join("a.ch8"), [0]).unwrap();
+        let files = rom_files(dir.to_str().unwrap()).unwrap();
+        let names: Vec<&str> = files.iter().map(|f| f.rsplit(['/', '\\']).next().unwrap()).collect();
+        assert_eq!(names, ["a.ch8", "b.ch8"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,6 +1,22 @@
-pub mod chip8;
+pub mod args;
 pub mod video_driver;
 pub mod input_driver;
 pub mod configs;
 pub mod emulator;
-pub mod rewind;
\ No newline at end of file
+pub mod palette;
+pub mod persistence;
+pub mod postfx;
+pub mod settings;
+pub mod overlay;
+pub mod debugger;
+pub mod sprites;
+pub mod gdb;
+pub mod script;
+pub mod cheats;
+pub mod differential;
+pub mod reload;
+pub mod menu;
+pub mod keymap;
+pub mod audio_driver;
+#[cfg(test)]
+pub mod testing;
@@ -0,0 +1,274 @@
+use crate::drivers::menu::Menu;
+use crate::drivers::palette::Rgb;
+use crate::drivers::postfx::Image;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+// 5x7 glyphs, one byte per row, bit 4 is the leftmost column
+// Lower case is drawn as upper case, anything else missing as '?'
+const GLYPHS: [(char, [u8; 7]); 59] = [
+    (' ', [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]),
+    ('A', [0x0E, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11]),
+    ('B', [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E]),
+    ('C', [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E]),
+    ('D', [0x1E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x1E]),
+    ('E', [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F]),
+    ('F', [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10]),
+    ('G', [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F]),
+    ('H', [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11]),
+    ('I', [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E]),
+    ('J', [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C]),
+    ('K', [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11]),
+    ('L', [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F]),
+    ('M', [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11]),
+    ('N', [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11]),
+    ('O', [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E]),
+    ('P', [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10]),
+    ('Q', [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D]),
+    ('R', [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11]),
+    ('S', [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E]),
+    ('T', [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04]),
+    ('U', [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E]),
+    ('V', [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04]),
+    ('W', [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A]),
+    ('X', [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11]),
+    ('Y', [0x11, 0x11, 0x11, 0x0A, 0x04, 0x04, 0x04]),
+    ('Z', [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F]),
+    ('0', [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E]),
+    ('1', [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E]),
+    ('2', [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F]),
+    ('3', [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E]),
+    ('4', [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02]),
+    ('5', [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E]),
+    ('6', [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E]),
+    ('7', [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08]),
+    ('8', [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E]),
+    ('9', [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C]),
+    ('.', [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C]),
+    (',', [0x00, 0x00, 0x00, 0x00, 0x0C, 0x04, 0x08]),
+    (':', [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x0C, 0x00]),
+    ('-', [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00]),
+    ('+', [0x00, 0x04, 0x04, 0x1F, 0x04, 0x04, 0x00]),
+    ('/', [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00]),
+    ('!', [0x04, 0x04, 0x04, 0x04, 0x04, 0x00, 0x04]),
+    ('?', [0x0E, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04]),
+    ('(', [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02]),
+    (')', [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08]),
+    ('%', [0x18, 0x19, 0x02, 0x04, 0x08, 0x13, 0x03]),
+    ('=', [0x00, 0x00, 0x1F, 0x00, 0x1F, 0x00, 0x00]),
+    ('_', [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1F]),
+    ('[', [0x0E, 0x08, 0x08, 0x08, 0x08, 0x08, 0x0E]),
+    (']', [0x0E, 0x02, 0x02, 0x02, 0x02, 0x02, 0x0E]),
+    ('\'', [0x04, 0x04, 0x08, 0x00, 0x00, 0x00, 0x00]),
+    ('>', [0x08, 0x04, 0x02, 0x01, 0x02, 0x04, 0x08]),
+    ('<', [0x02, 0x04, 0x08, 0x10, 0x08, 0x04, 0x02]),
+    ('#', [0x0A, 0x0A, 0x1F, 0x0A, 0x1F, 0x0A, 0x0A]),
+    ('*', [0x00, 0x04, 0x15, 0x0E, 0x15, 0x04, 0x00]),
+    ('"', [0x0A, 0x0A, 0x00, 0x00, 0x00, 0x00, 0x00]),
+    ('|', [0x04, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04]),
+];
+
+pub const GLYPH_WIDTH: usize = 5;
+pub const GLYPH_HEIGHT: usize = 7;
+
+const TEXT: Rgb = [255, 255, 255];
+const ERROR: Rgb = [255, 80, 80];
+const MESSAGE_TIME: Duration = Duration::from_secs(3);
+const FADE_TIME: Duration = Duration::from_secs(1);
+const MAX_MESSAGES: usize = 4;
+
+pub fn glyph(c: char) -> [u8; 7] {
+    let c = c.to_ascii_uppercase();
+    GLYPHS
+        .iter()
+        .find(|(g, _)| *g == c)
+        .or_else(|| GLYPHS.iter().find(|(g, _)| *g == '?'))
+        .map(|(_, rows)| *rows)
+        .unwrap()
+}
+
+// Text drawn on top of the frame, a status line toggled by hotkey,
+// notifications that fade out after a few seconds and an open menu
+pub struct Overlay {
+    show_status: bool,
+    status: String,
+    messages: VecDeque<(String, Rgb, Instant)>,
+    menu: Option<Menu>,
+}
+
+impl Default for Overlay {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Overlay {
+    pub fn new() -> Self {
+        Self {
+            show_status: false,
+            status: String::new(),
+            messages: VecDeque::new(),
+            menu: None,
+        }
+    }
+
+    pub fn toggle_status(&mut self) {
+        self.show_status = !self.show_status;
+    }
+
+    pub fn set_status(&mut self, status: &str) {
+        self.status = status.to_string();
+    }
+
+    pub fn notify(&mut self, message: &str) {
+        self.push(message, TEXT);
+    }
+
+    pub fn error(&mut self, message: &str) {
+        self.push(message, ERROR);
+    }
+
+    pub fn open_menu(&mut self, menu: Menu) {
+        self.menu = Some(menu);
+    }
+
+    pub fn menu(&mut self) -> Option<&mut Menu> {
+        self.menu.as_mut()
+    }
+
+    pub fn close_menu(&mut self) -> Option<Menu> {
+        self.menu.take()
+    }
+
+    fn push(&mut self, message: &str, color: Rgb) {
+        if self.messages.len() >= MAX_MESSAGES {
+            self.messages.pop_front();
+        }
+        self.messages.push_back((message.to_string(), color, Instant::now()));
+    }
+
+    // Anything to draw, the frame needs redrawing while this is true
+    pub fn is_active(&mut self, now: Instant) -> bool {
+        self.messages
+            .retain(|(_, _, at)| now.saturating_duration_since(*at) < MESSAGE_TIME);
+        self.show_status || !self.messages.is_empty() || self.menu.is_some()
+    }
+
+    // Status at the top left, messages stacked at the bottom left
+    pub fn render(&self, image: &mut Image, now: Instant) {
+        let scale = (image.width / 256).max(1);
+        let line = (GLYPH_HEIGHT + 3) * scale;
+
+        if self.show_status && !self.status.is_empty() {
+            draw_text(image, scale, scale, &self.status, scale, TEXT, 1.0);
+        }
+
+        let mut y = image.height.saturating_sub(line * self.messages.len() + scale);
+        for (message, color, at) in self.messages.iter() {
+            let age = now.saturating_duration_since(*at);
+            let left = MESSAGE_TIME.saturating_sub(age);
+            let alpha = (left.as_secs_f32() / FADE_TIME.as_secs_f32()).min(1.0);
+            if alpha > 0.0 {
+                draw_text(image, scale, y, message, scale, *color, alpha);
+            }
+            y += line;
+        }
+
+        if let Some(menu) = &self.menu {
+            menu.render(image);
+        }
+    }
+}
+
+// Text over a dimmed backdrop so it reads on any palette
+pub fn draw_text(image: &mut Image, x: usize, y: usize, text: &str, scale: usize, color: Rgb, alpha: f32) {
+    let advance = (GLYPH_WIDTH + 1) * scale;
+    let width = text.chars().count() * advance + scale;
+    let height = (GLYPH_HEIGHT + 2) * scale;
+
+    for py in y..(y + height).min(image.height) {
+        for px in x..(x + width).min(image.width) {
+            let rgb = image.get(px, py);
+            image.set(px, py, mix(rgb, [0, 0, 0], alpha * 0.6));
+        }
+    }
+
+    for (i, c) in text.chars().enumerate() {
+        let rows = glyph(c);
+        let gx = x + scale + i * advance;
+        let gy = y + scale;
+        for (row, bits) in rows.iter().enumerate() {
+            for col in 0..GLYPH_WIDTH {
+                if bits & (0x10 >> col) == 0 {
+                    continue;
+                }
+                for sy in 0..scale {
+                    for sx in 0..scale {
+                        let (px, py) = (gx + col * scale + sx, gy + row * scale + sy);
+                        if px < image.width && py < image.height {
+                            let rgb = image.get(px, py);
+                            image.set(px, py, mix(rgb, color, alpha));
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn mix(from: Rgb, to: Rgb, amount: f32) -> Rgb {
+    let channel = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * amount).round() as u8;
+    [channel(from[0], to[0]), channel(from[1], to[1]), channel(from[2], to[2])]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glyph_lookup() {
+        assert_eq!(glyph('a'), glyph('A'));
+        assert_eq!(glyph('~'), glyph('?'));
+        // Every glyph fits in five columns
+        assert!(GLYPHS.iter().all(|(_, rows)| rows.iter().all(|&r| r < 0x20)));
+    }
+
+    #[test]
+    fn draws_text_pixels() {
+        let mut image = Image::new(64, 16);
+        draw_text(&mut image, 0, 0, "1", 1, [255, 255, 255], 1.0);
+        // Top row of '1' is 00100, glyph starts one pixel in
+        assert_eq!(image.get(3, 1), [255, 255, 255]);
+        assert_eq!(image.get(2, 1), [0, 0, 0]);
+    }
+
+    #[test]
+    fn messages_expire() {
+        let mut overlay = Overlay::new();
+        let start = Instant::now();
+        assert!(!overlay.is_active(start));
+
+        overlay.notify("Paused");
+        assert!(overlay.is_active(start));
+        assert!(!overlay.is_active(start + MESSAGE_TIME + Duration::from_millis(10)));
+
+        overlay.toggle_status();
+        assert!(overlay.is_active(start + MESSAGE_TIME * 2));
+    }
+
+    #[test]
+    fn messages_fade_out() {
+        let mut overlay = Overlay::new();
+        overlay.notify("X");
+        let at = overlay.messages[0].2;
+
+        let mut fresh = Image::new(512, 256);
+        overlay.render(&mut fresh, at);
+        let mut fading = Image::new(512, 256);
+        overlay.render(&mut fading, at + MESSAGE_TIME - FADE_TIME / 2);
+
+        let brightest = |image: &Image| image.pixels.iter().map(|p| p[0]).max().unwrap();
+        assert_eq!(brightest(&fresh), 255);
+        assert!(brightest(&fading) < 200 && brightest(&fading) > 0);
+    }
+}
@@ -0,0 +1,129 @@
+pub type Rgb = [u8; 3];
+
+// Colours for pixel values 0 to 3, one bit per plane
+// Single plane games only use the first two
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Palette {
+    pub name: String,
+    pub colors: [Rgb; 4],
+}
+
+impl Palette {
+    pub fn new(name: &str, colors: [Rgb; 4]) -> Self {
+        Self { name: name.to_string(), colors }
+    }
+
+    // Two to four hex colours separated by spaces or commas, "#" optional
+    // Missing plane colours repeat the foreground
+    pub fn from_hex(name: &str, text: &str) -> Option<Self> {
+        let colors = text
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|s| !s.is_empty())
+            .map(parse_hex)
+            .collect::<Option<Vec<Rgb>>>()?;
+        if colors.len() < 2 || colors.len() > 4 {
+            return None;
+        }
+        let fg = colors[1];
+        let mut planes = [colors[0], fg, fg, fg];
+        planes[..colors.len()].copy_from_slice(&colors);
+        Some(Self::new(name, planes))
+    }
+
+    pub fn color(&self, pixel: u8) -> Rgb {
+        self.colors[(pixel & 3) as usize]
+    }
+}
+
+pub fn parse_hex(text: &str) -> Option<Rgb> {
+    let hex = text.trim_start_matches('#');
+    if hex.len() != 6 {
+        return None;
+    }
+    let value = u32::from_str_radix(hex, 16).ok()?;
+    Some([(value >> 16) as u8, (value >> 8) as u8, value as u8])
+}
+
+pub fn builtin() -> Vec<Palette> {
+    vec![
+        Palette::new("classic", [[0, 0, 0], [0, 250, 0], [0, 120, 0], [180, 255, 180]]),
+        Palette::new("amber", [[20, 12, 0], [255, 176, 0], [150, 90, 0], [255, 220, 140]]),
+        Palette::new("lcd", [[155, 188, 15], [15, 56, 15], [48, 98, 48], [139, 172, 15]]),
+        Palette::new("contrast", [[0, 0, 0], [255, 255, 255], [255, 255, 0], [0, 255, 255]]),
+    ]
+}
+
+// Built in palettes followed by user defined ones, with a current selection
+pub struct Palettes {
+    list: Vec<Palette>,
+    current: usize,
+}
+
+impl Palettes {
+    pub fn new(user: Vec<Palette>) -> Self {
+        let mut list = builtin();
+        for palette in user {
+            match list.iter_mut().find(|p| p.name == palette.name) {
+                Some(existing) => *existing = palette,
+                None => list.push(palette),
+            }
+        }
+        Self { list, current: 0 }
+    }
+
+    pub fn current(&self) -> &Palette {
+        &self.list[self.current]
+    }
+
+    pub fn cycle(&mut self) -> &Palette {
+        self.current = (self.current + 1) % self.list.len();
+        self.current()
+    }
+
+    // Returns false and keeps the current palette if the name is unknown
+    pub fn select(&mut self, name: &str) -> bool {
+        match self.list.iter().position(|p| p.name == name) {
+            Some(i) => {
+                self.current = i;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.list.iter().map(|p| p.name.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex_palettes() {
+        let palette = Palette::from_hex("mine", "#102030, A0B0C0").unwrap();
+        assert_eq!(palette.color(0), [0x10, 0x20, 0x30]);
+        assert_eq!(palette.color(1), [0xA0, 0xB0, 0xC0]);
+        assert_eq!(palette.color(3), [0xA0, 0xB0, 0xC0]);
+
+        let planes = Palette::from_hex("planes", "000000 ff0000 00ff00 0000ff").unwrap();
+        assert_eq!(planes.color(2), [0, 255, 0]);
+        assert_eq!(planes.color(3), [0, 0, 255]);
+
+        assert!(Palette::from_hex("bad", "000000").is_none());
+        assert!(Palette::from_hex("bad", "000000 fffff").is_none());
+    }
+
+    #[test]
+    fn selection_and_user_palettes() {
+        let mine = Palette::from_hex("amber", "000000 ffffff").unwrap();
+        let mut palettes = Palettes::new(vec![mine.clone()]);
+        assert_eq!(palettes.current().name, "classic");
+        assert!(palettes.select("amber"));
+        assert_eq!(palettes.current(), &mine);
+        assert!(!palettes.select("missing"));
+        assert_eq!(palettes.cycle().name, "lcd");
+        assert_eq!(palettes.names().count(), 4);
+    }
+}
@@ -0,0 +1,130 @@
+use crate::drivers::palette::{Palette, Rgb};
+
+// Phosphor style fade, a pixel that turns off keeps a share of its
+// brightness each frame instead of going dark at once
+// Smooths out the erase and redraw flicker of XOR drawing
+pub struct Persistence {
+    // Share of brightness kept per frame, 0 is no fade
+    decay: f32,
+    levels: [[f32; 64]; 32],
+    // Last lit pixel value, so plane colours fade in their own colour
+    lit: [[u8; 64]; 32],
+}
+
+impl Persistence {
+    pub fn new(decay: f32) -> Self {
+        Self {
+            decay: decay.clamp(0.0, 0.99),
+            levels: [[0.0; 64]; 32],
+            lit: [[1; 64]; 32],
+        }
+    }
+
+    pub fn decay(&self) -> f32 {
+        self.decay
+    }
+
+    // Advance one frame, lit pixels go to full and the rest fade. Called
+    // once per frame so the fade doesn't speed up with extra draws
+    pub fn advance(&mut self, pixels: &[[u8; 64]; 32]) {
+        for (y, row) in pixels.iter().enumerate() {
+            for (x, &pixel) in row.iter().enumerate() {
+                let level = &mut self.levels[y][x];
+                if pixel != 0 {
+                    *level = 1.0;
+                    self.lit[y][x] = pixel;
+                } else {
+                    *level *= self.decay;
+                    // Below one step of an 8 bit channel
+                    if *level < 1.0 / 255.0 {
+                        *level = 0.0;
+                    }
+                }
+            }
+        }
+    }
+
+    // Colours for pixels, those turned off blend towards the background by
+    // their level
+    pub fn render(&self, pixels: &[[u8; 64]; 32], palette: &Palette) -> [[Rgb; 64]; 32] {
+        let background = palette.color(0);
+        let mut out = [[background; 64]; 32];
+        for (y, row) in pixels.iter().enumerate() {
+            for (x, &pixel) in row.iter().enumerate() {
+                out[y][x] = match pixel {
+                    0 => blend(background, palette.color(self.lit[y][x]), self.levels[y][x]),
+                    pixel => palette.color(pixel),
+                };
+            }
+        }
+        out
+    }
+
+    // True while some pixel is still between on and off
+    pub fn is_fading(&self) -> bool {
+        self.levels.iter().flatten().any(|&l| l > 0.0 && l < 1.0)
+    }
+}
+
+fn blend(from: Rgb, to: Rgb, level: f32) -> Rgb {
+    let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * level).round() as u8;
+    [mix(from[0], to[0]), mix(from[1], to[1]), mix(from[2], to[2])]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn palette() -> Palette {
+        Palette::from_hex("test", "000000 c8c8c8 640000").unwrap()
+    }
+
+    fn update(persistence: &mut Persistence, pixels: &[[u8; 64]; 32]) -> [[Rgb; 64]; 32] {
+        persistence.advance(pixels);
+        persistence.render(pixels, &palette())
+    }
+
+    #[test]
+    fn pixels_fade_after_turning_off() {
+        let mut persistence = Persistence::new(0.5);
+        let mut pixels = [[0u8; 64]; 32];
+        pixels[3][4] = 1;
+
+        let frame = update(&mut persistence, &pixels);
+        assert_eq!(frame[3][4], [200, 200, 200]);
+        assert_eq!(frame[0][0], [0, 0, 0]);
+        assert!(!persistence.is_fading());
+
+        pixels[3][4] = 0;
+        assert_eq!(update(&mut persistence, &pixels)[3][4], [100, 100, 100]);
+        assert!(persistence.is_fading());
+        assert_eq!(update(&mut persistence, &pixels)[3][4], [50, 50, 50]);
+        // Drawing again without a new frame doesn't fade further
+        assert_eq!(persistence.render(&pixels, &palette())[3][4], [50, 50, 50]);
+
+        for _ in 0..16 {
+            update(&mut persistence, &pixels);
+        }
+        assert!(!persistence.is_fading());
+    }
+
+    #[test]
+    fn plane_colours_fade_in_their_own_colour() {
+        let mut persistence = Persistence::new(0.5);
+        let mut pixels = [[0u8; 64]; 32];
+        pixels[0][0] = 2;
+        update(&mut persistence, &pixels);
+
+        pixels[0][0] = 0;
+        assert_eq!(update(&mut persistence, &pixels)[0][0], [50, 0, 0]);
+    }
+
+    #[test]
+    fn zero_decay_turns_off_at_once() {
+        let mut persistence = Persistence::new(0.0);
+        let mut pixels = [[1u8; 64]; 32];
+        update(&mut persistence, &pixels);
+        pixels[0][0] = 0;
+        assert_eq!(update(&mut persistence, &pixels)[0][0], [0, 0, 0]);
+    }
+}
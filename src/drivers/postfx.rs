@@ -0,0 +1,281 @@
+use crate::drivers::palette::Rgb;
+use std::fs::File;
+use std::io::{self, BufWriter};
+
+// Cpu side frame, what gets uploaded to the texture and what a
+// screenshot saves
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Rgb>,
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self { width, height, pixels: vec![[0; 3]; width * height] }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Rgb {
+        self.pixels[y * self.width + x]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, rgb: Rgb) {
+        self.pixels[y * self.width + x] = rgb;
+    }
+
+    // Every pixel a factor x factor block
+    pub fn scaled(&self, factor: usize) -> Image {
+        let mut image = Image::new(self.width * factor, self.height * factor);
+        for y in 0..image.height {
+            for x in 0..image.width {
+                image.set(x, y, self.get(x / factor, y / factor));
+            }
+        }
+        image
+    }
+
+    pub fn to_rgb24(&self) -> Vec<u8> {
+        self.pixels.iter().flatten().copied().collect()
+    }
+
+    pub fn save_png(&self, path: &str) -> io::Result<()> {
+        let file = BufWriter::new(File::create(path)?);
+        let mut encoder = png::Encoder::new(file, self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().map_err(io::Error::other)?;
+        writer.write_image_data(&self.to_rgb24()).map_err(io::Error::other)
+    }
+}
+
+// Retro presentation passes, applied in field order
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct Effects {
+    pub grid: bool,
+    pub scanlines: bool,
+    pub bloom: bool,
+    pub curvature: bool,
+}
+
+impl Effects {
+    // Comma separated names, "effects = scanlines, bloom" in the settings
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut effects = Effects::default();
+        for name in text.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            match name {
+                "grid" => effects.grid = true,
+                "scanlines" => effects.scanlines = true,
+                "bloom" => effects.bloom = true,
+                "curvature" => effects.curvature = true,
+                "none" => (),
+                other => return Err(format!("unknown effect {}", other)),
+            }
+        }
+        Ok(effects)
+    }
+
+    pub fn any(&self) -> bool {
+        self.grid || self.scanlines || self.bloom || self.curvature
+    }
+}
+
+// Upscale the chip8 frame by a whole factor and run the enabled passes
+pub fn apply(colors: &[[Rgb; 64]; 32], scale: usize, effects: Effects) -> Image {
+    let mut image = upscale(colors, scale);
+    if effects.grid {
+        grid(&mut image, scale);
+    }
+    if effects.scanlines {
+        scanlines(&mut image);
+    }
+    if effects.bloom {
+        bloom(&mut image, (scale / 2).max(1));
+    }
+    if effects.curvature {
+        image = curvature(&image);
+    }
+    image
+}
+
+pub fn upscale(colors: &[[Rgb; 64]; 32], scale: usize) -> Image {
+    let mut image = Image::new(64 * scale, 32 * scale);
+    for y in 0..image.height {
+        for x in 0..image.width {
+            image.set(x, y, colors[y / scale][x / scale]);
+        }
+    }
+    image
+}
+
+// Darken the last row and column of every chip8 pixel
+fn grid(image: &mut Image, scale: usize) {
+    if scale < 3 {
+        return;
+    }
+    for y in 0..image.height {
+        for x in 0..image.width {
+            if x % scale == scale - 1 || y % scale == scale - 1 {
+                let rgb = image.get(x, y);
+                image.set(x, y, shade(rgb, 0.35));
+            }
+        }
+    }
+}
+
+// Darken every other output row
+fn scanlines(image: &mut Image) {
+    for y in (1..image.height).step_by(2) {
+        for x in 0..image.width {
+            let rgb = image.get(x, y);
+            image.set(x, y, shade(rgb, 0.6));
+        }
+    }
+}
+
+// Add a box blurred copy on top so lit pixels glow into their neighbours
+fn bloom(image: &mut Image, radius: usize) {
+    let blurred = box_blur(image, radius);
+    for (pixel, glow) in image.pixels.iter_mut().zip(blurred.pixels.iter()) {
+        for c in 0..3 {
+            pixel[c] = (pixel[c] as f32 + glow[c] as f32 * 0.4).min(255.0) as u8;
+        }
+    }
+}
+
+fn box_blur(image: &Image, radius: usize) -> Image {
+    // Separable, horizontal then vertical
+    let mut horizontal = Image::new(image.width, image.height);
+    for y in 0..image.height {
+        for x in 0..image.width {
+            let from = x.saturating_sub(radius);
+            let to = (x + radius).min(image.width - 1);
+            let sum = (from..=to).fold([0u32; 3], |acc, sx| add(acc, image.get(sx, y)));
+            horizontal.set(x, y, average(sum, to - from + 1));
+        }
+    }
+    let mut out = Image::new(image.width, image.height);
+    for y in 0..image.height {
+        let from = y.saturating_sub(radius);
+        let to = (y + radius).min(image.height - 1);
+        for x in 0..image.width {
+            let sum = (from..=to).fold([0u32; 3], |acc, sy| add(acc, horizontal.get(x, sy)));
+            out.set(x, y, average(sum, to - from + 1));
+        }
+    }
+    out
+}
+
+// Barrel distortion like a curved tube, corners fall off to black
+fn curvature(image: &Image) -> Image {
+    const AMOUNT: f32 = 0.08;
+    let mut out = Image::new(image.width, image.height);
+    let (w, h) = (image.width as f32, image.height as f32);
+
+    for y in 0..image.height {
+        for x in 0..image.width {
+            // -1 to 1 from the centre of the pixel
+            let u = (x as f32 + 0.5) / w * 2.0 - 1.0;
+            let v = (y as f32 + 0.5) / h * 2.0 - 1.0;
+            let bend = 1.0 + AMOUNT * (u * u + v * v);
+            let (su, sv) = (u * bend, v * bend);
+            if su.abs() > 1.0 || sv.abs() > 1.0 {
+                continue;
+            }
+            let sx = (((su + 1.0) / 2.0 * w) as usize).min(image.width - 1);
+            let sy = (((sv + 1.0) / 2.0 * h) as usize).min(image.height - 1);
+            out.set(x, y, image.get(sx, sy));
+        }
+    }
+    out
+}
+
+fn shade(rgb: Rgb, amount: f32) -> Rgb {
+    rgb.map(|c| (c as f32 * amount) as u8)
+}
+
+fn add(acc: [u32; 3], rgb: Rgb) -> [u32; 3] {
+    [acc[0] + rgb[0] as u32, acc[1] + rgb[1] as u32, acc[2] + rgb[2] as u32]
+}
+
+fn average(sum: [u32; 3], count: usize) -> Rgb {
+    sum.map(|c| (c / count as u32) as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::drivers::testing::scratch_dir;
+
+    const WHITE: Rgb = [200, 200, 200];
+
+    fn frame() -> [[Rgb; 64]; 32] {
+        let mut colors = [[[0; 3]; 64]; 32];
+        colors[10][10] = WHITE;
+        colors
+    }
+
+    #[test]
+    fn no_effects_is_a_plain_upscale() {
+        let image = apply(&frame(), 4, Effects::default());
+        assert_eq!((image.width, image.height), (256, 128));
+        assert_eq!(image.get(40, 40), WHITE);
+        assert_eq!(image.get(43, 43), WHITE);
+        assert_eq!(image.get(44, 40), [0; 3]);
+        assert_eq!(apply(&frame(), 1, Effects::default()).scaled(4), image);
+    }
+
+    #[test]
+    fn grid_and_scanlines_darken() {
+        let effects = Effects { grid: true, scanlines: true, ..Default::default() };
+        let image = apply(&frame(), 4, effects);
+        assert_eq!(image.get(40, 40), WHITE);
+        assert_eq!(image.get(40, 41), [120, 120, 120]);
+        assert_eq!(image.get(43, 40), [70, 70, 70]);
+    }
+
+    #[test]
+    fn bloom_spreads_light() {
+        let effects = Effects { bloom: true, ..Default::default() };
+        let image = apply(&frame(), 4, effects);
+        assert!(image.get(44, 41)[0] > 0);
+        assert_eq!(image.get(60, 60), [0; 3]);
+        assert!(image.get(41, 41)[0] > WHITE[0]);
+    }
+
+    #[test]
+    fn curvature_blanks_corners() {
+        let mut colors = [[WHITE; 64]; 32];
+        colors[16][32] = [1, 2, 3];
+        let effects = Effects { curvature: true, ..Default::default() };
+        let image = apply(&colors, 2, effects);
+        assert_eq!(image.get(0, 0), [0; 3]);
+        assert_eq!(image.get(64, 32), [1, 2, 3]);
+    }
+
+    #[test]
+    fn parses_effect_lists() {
+        let effects = Effects::parse("scanlines, curvature").unwrap();
+        assert!(effects.scanlines && effects.curvature && !effects.grid && !effects.bloom);
+        assert!(!Effects::parse("none").unwrap().any());
+        assert!(Effects::parse("sparkles").is_err());
+    }
+
+    #[test]
+    fn screenshot_round_trip() {
+        let effects = Effects { scanlines: true, ..Default::default() };
+        let image = apply(&frame(), 2, effects);
+        let dir = scratch_dir("postfx");
+        let path = dir.join("shot.png");
+        let path = path.to_str().unwrap();
+        image.save_png(path).unwrap();
+
+        let decoder = png::Decoder::new(File::open(path).unwrap());
+        let mut reader = decoder.read_info().unwrap();
+        let mut buf = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buf).unwrap();
+        assert_eq!((info.width, info.height), (128, 64));
+        assert_eq!(&buf[..info.buffer_size()], &image.to_rgb24()[..]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
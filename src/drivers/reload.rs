@@ -0,0 +1,104 @@
+use crate::drivers::configs::defaults::*;
+use chip8_core::{Chip8, Error, Timing};
+use std::fs;
+use std::time::{Duration, Instant, SystemTime};
+
+// Watches the rom file by polling its modification time and size, so an
+// assembler writing a new build is picked up without restarting
+pub struct RomWatcher {
+    path: String,
+    stamp: Option<(SystemTime, u64)>,
+    checked: Instant,
+}
+
+impl RomWatcher {
+    pub fn new(path: &str) -> Self {
+        Self { path: path.to_string(), stamp: stamp(path), checked: Instant::now() }
+    }
+
+    // The new rom once the file has changed and reads back whole, at most
+    // one look at the file every RELOAD_POLL_MS
+    pub fn poll(&mut self) -> Option<Vec<u8>> {
+        if self.checked.elapsed() < Duration::from_millis(RELOAD_POLL_MS) {
+            return None;
+        }
+        self.checked = Instant::now();
+        self.check()
+    }
+
+    fn check(&mut self) -> Option<Vec<u8>> {
+        let now = stamp(&self.path)?;
+        if Some(now) == self.stamp {
+            return None;
+        }
+        let program = fs::read(&self.path).ok()?;
+        // Still being written, try again next time
+        if program.len() as u64 != now.1 || program.is_empty() {
+            return None;
+        }
+        self.stamp = Some(now);
+        Some(program)
+    }
+}
+
+fn stamp(path: &str) -> Option<(SystemTime, u64)> {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+// Run a freshly loaded rom with no keys held until pc reaches target,
+// false if it doesn't within cycles
+pub fn run_to(chip: &mut Chip8, target: usize, cycles: u64) -> Result<bool, Error> {
+    for cycle in 1..=cycles {
+        chip.cycle()?;
+        if chip.timing() == Timing::Ticks && cycle % TICKS_PER_FRAME as u64 == 0 {
+            chip.timer_tick();
+        }
+        if chip.pc() == target && chip.key_wait().is_none() {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::drivers::testing::scratch_dir;
+
+    #[test]
+    fn notices_new_builds() {
+        let dir = scratch_dir("reload");
+        let path = dir.join("rom.ch8");
+        let path = path.to_str().unwrap();
+        fs::write(path, [0x12, 0x00]).unwrap();
+        let mut watcher = RomWatcher::new(path);
+        assert_eq!(watcher.check(), None);
+
+        // A different size shows up even if the modification time is the same
+        fs::write(path, [0x60, 0x01, 0x12, 0x02]).unwrap();
+        assert_eq!(watcher.check(), Some(vec![0x60, 0x01, 0x12, 0x02]));
+        assert_eq!(watcher.check(), None);
+        assert_eq!(watcher.poll(), None);
+
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(watcher.check(), None);
+    }
+
+    #[test]
+    fn runs_to_the_cursor() {
+        // LD V0, 5; ADD V0, -1; SE V0, 0; JP 0x202; JP 0x208
+        let program = [0x60, 0x05, 0x70, 0xFF, 0x30, 0x00, 0x12, 0x02, 0x12, 0x08];
+        let mut chip = Chip8::new();
+        chip.load_program(&program).unwrap();
+        assert_eq!(run_to(&mut chip, 0x208, 100), Ok(true));
+        assert_eq!(chip.register(0), 0);
+
+        let mut chip = Chip8::new();
+        chip.load_program(&program).unwrap();
+        assert_eq!(run_to(&mut chip, 0x300, 100), Ok(false));
+        chip.load_program(&[0x00, 0xEE]).unwrap();
+        chip.set_pc(0x200);
+        assert_eq!(run_to(&mut chip, 0x300, 100), Err(Error::StackUnderflow));
+    }
+}
@@ -0,0 +1,395 @@
+use crate::drivers::configs::defaults::*;
+use crate::drivers::palette;
+use crate::drivers::postfx;
+use crate::drivers::settings::{self, Settings};
+use chip8_core::{state, Chip8, Timing};
+use rhai::{Dynamic, Engine, EvalAltResult, FnPtr, AST, INT};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fs;
+use std::mem;
+use std::rc::Rc;
+
+// What a script asks of whatever is running it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    Screenshot(String),
+    Exit,
+}
+
+// State the native functions share, the machine is swapped in for the
+// length of each call into the script
+#[derive(Default)]
+struct Host {
+    chip: Chip8,
+    frame: u64,
+    on_frame: Vec<FnPtr>,
+    on_exec: BTreeMap<usize, Vec<FnPtr>>,
+    on_write: Vec<FnPtr>,
+    actions: Vec<Action>,
+}
+
+type Shared = Rc<RefCell<Host>>;
+
+// A Rhai script with callbacks on frame end, on reaching an address and on
+// Fx33 / Fx55 stores
+pub struct Script {
+    engine: Engine,
+    ast: AST,
+    host: Shared,
+}
+
+impl Script {
+    pub fn load(path: &str, chip: &mut Chip8) -> Result<Self, String> {
+        let source = fs::read_to_string(path).map_err(|e| format!("Unable to read {}: {}", path, e))?;
+        Self::from_source(&source, chip)
+    }
+
+    // Compile and run the top level, which registers the callbacks
+    pub fn from_source(source: &str, chip: &mut Chip8) -> Result<Self, String> {
+        let host = Shared::default();
+        let engine = engine(&host);
+        let ast = engine.compile(source).map_err(|e| e.to_string())?;
+        let script = Self { engine, ast, host };
+        script.with_chip(chip, |s| s.engine.run_ast(&s.ast).map_err(|e| e.to_string()))?;
+        Ok(script)
+    }
+
+    // Before the instruction at pc runs
+    pub fn before(&mut self, chip: &mut Chip8) -> Result<(), String> {
+        let callbacks = match self.host.borrow().on_exec.get(&chip.pc()) {
+            Some(callbacks) => callbacks.clone(),
+            None => return Ok(()),
+        };
+        let pc = chip.pc() as INT;
+        self.with_chip(chip, |s| callbacks.iter().try_for_each(|f| s.call(f, (pc,))))
+    }
+
+    // After an instruction, once per byte it stored
+    pub fn after(&mut self, chip: &mut Chip8) -> Result<(), String> {
+        let Some((start, len)) = chip.last_write() else { return Ok(()) };
+        let callbacks = self.host.borrow().on_write.clone();
+        if callbacks.is_empty() {
+            return Ok(());
+        }
+        let writes: Vec<(INT, INT)> = (start..start + len)
+            .map(|address| (address as INT, chip.peek(address & 0xFFF) as INT))
+            .collect();
+        self.with_chip(chip, |s| {
+            for &write in writes.iter() {
+                callbacks.iter().try_for_each(|f| s.call(f, write))?;
+            }
+            Ok(())
+        })
+    }
+
+    // After the timers tick at the end of a frame
+    pub fn frame_end(&mut self, chip: &mut Chip8) -> Result<(), String> {
+        let callbacks = {
+            let mut host = self.host.borrow_mut();
+            host.frame += 1;
+            host.on_frame.clone()
+        };
+        self.with_chip(chip, |s| callbacks.iter().try_for_each(|f| s.call(f, ())))
+    }
+
+    pub fn take_actions(&mut self) -> Vec<Action> {
+        mem::take(&mut self.host.borrow_mut().actions)
+    }
+
+    fn call(&self, f: &FnPtr, args: impl rhai::FuncArgs) -> Result<(), String> {
+        f.call::<Dynamic>(&self.engine, &self.ast, args)
+            .map(|_| ())
+            .map_err(|e| format!("{}: {}", f.fn_name(), e))
+    }
+
+    fn with_chip<R>(&self, chip: &mut Chip8, f: impl FnOnce(&Self) -> R) -> R {
+        mem::swap(chip, &mut self.host.borrow_mut().chip);
+        let result = f(self);
+        mem::swap(chip, &mut self.host.borrow_mut().chip);
+        result
+    }
+}
+
+fn engine(host: &Shared) -> Engine {
+    let mut engine = Engine::new();
+
+    // Reads and writes wrap at the machine's sizes rather than fail
+    let h = host.clone();
+    engine.register_fn("peek", move |address: INT| h.borrow().chip.peek(address as usize & 0xFFF) as INT);
+    let h = host.clone();
+    engine.register_fn("poke", move |address: INT, value: INT| {
+        h.borrow_mut().chip.poke(address as usize & 0xFFF, value as u8)
+    });
+    let h = host.clone();
+    engine.register_fn("reg", move |x: INT| h.borrow().chip.register(x as usize & 0xF) as INT);
+    let h = host.clone();
+    engine.register_fn("set_reg", move |x: INT, value: INT| {
+        h.borrow_mut().chip.set_register(x as usize & 0xF, value as u8)
+    });
+    let h = host.clone();
+    engine.register_fn("index", move || h.borrow().chip.index() as INT);
+    let h = host.clone();
+    engine.register_fn("set_index", move |value: INT| h.borrow_mut().chip.set_index(value as usize & 0xFFFF));
+    let h = host.clone();
+    engine.register_fn("pc", move || h.borrow().chip.pc() as INT);
+    let h = host.clone();
+    engine.register_fn("set_pc", move |value: INT| h.borrow_mut().chip.set_pc(value as usize & 0xFFF));
+    let h = host.clone();
+    engine.register_fn("dt", move || h.borrow().chip.delay_timer() as INT);
+    let h = host.clone();
+    engine.register_fn("st", move || h.borrow().chip.sound_timer() as INT);
+    let h = host.clone();
+    engine.register_fn("frame", move || h.borrow().frame as INT);
+
+    // Keys stay down until released
+    let h = host.clone();
+    engine.register_fn("press", move |key: INT| h.borrow_mut().chip.set_key(key as usize & 0xF, true));
+    let h = host.clone();
+    engine.register_fn("release", move |key: INT| h.borrow_mut().chip.set_key(key as usize & 0xF, false));
+
+    let h = host.clone();
+    engine.register_fn("save_state", move |path: &str| -> Result<(), Box<EvalAltResult>> {
+        let bytes = state::serialize(&h.borrow().chip);
+        fs::write(path, bytes).map_err(|e| format!("Unable to save {}: {}", path, e).into())
+    });
+    let h = host.clone();
+    engine.register_fn("load_state", move |path: &str| -> Result<(), Box<EvalAltResult>> {
+        let bytes = fs::read(path).map_err(|e| format!("Unable to read {}: {}", path, e))?;
+        let mut chip = state::deserialize(&bytes).map_err(|e| format!("Unable to load {}: {}", path, e))?;
+        // States don't hold the settings, the rom's quirks and timing stay
+        let mut host = h.borrow_mut();
+        chip.set_quirks(host.chip.quirks());
+        chip.set_timing(host.chip.timing());
+        host.chip = chip;
+        Ok(())
+    });
+    let h = host.clone();
+    engine.register_fn("screenshot", move |path: &str| {
+        h.borrow_mut().actions.push(Action::Screenshot(path.to_string()))
+    });
+    let h = host.clone();
+    engine.register_fn("exit", move || h.borrow_mut().actions.push(Action::Exit));
+
+    let h = host.clone();
+    engine.register_fn("on_frame", move |f: FnPtr| h.borrow_mut().on_frame.push(f));
+    let h = host.clone();
+    engine.register_fn("on_exec", move |address: INT, f: FnPtr| {
+        h.borrow_mut().on_exec.entry(address as usize & 0xFFF).or_default().push(f)
+    });
+    let h = host.clone();
+    engine.register_fn("on_write", move |f: FnPtr| h.borrow_mut().on_write.push(f));
+
+    engine
+}
+
+// --headless, no window or sound, runs until the script calls exit() or
+// the program faults. The rom gets its quirks and timing from the
+// settings file like it does in the window
+pub fn run_headless(rom: &str, path: &str) -> Result<(), String> {
+    let settings = Settings::load(CONFIG_FILE).unwrap_or_else(|e| {
+        println!("Unable to read {}: {}", CONFIG_FILE, e);
+        Settings::default()
+    });
+    run_headless_with(rom, path, &settings)
+}
+
+fn run_headless_with(rom: &str, path: &str, settings: &Settings) -> Result<(), String> {
+    let program = fs::read(rom).map_err(|e| format!("Unable to read {}: {}", rom, e))?;
+    let name = settings::rom_name(rom);
+    let mut chip = Chip8::new();
+    chip.set_quirks(settings.quirks(&name));
+    chip.set_timing(settings.timing(&name));
+    chip.load_program(&program).map_err(|e| e.to_string())?;
+    let mut script = Script::load(path, &mut chip)?;
+    let palette = palette::builtin().remove(0);
+
+    loop {
+        // A frame is TICKS_PER_FRAME instructions, or up to the display
+        // interrupt with VIP timing, which ticks the timers itself
+        let mut cycles = 0;
+        loop {
+            // Halted on Fx0A pc is already past it, nothing runs there yet
+            if chip.key_wait().is_none() {
+                script.before(&mut chip)?;
+            }
+            chip.cycle().map_err(|fault| format!("Fault at {:03X}: {}", chip.pc(), fault))?;
+            script.after(&mut chip)?;
+            cycles += 1;
+            let frame_end = match chip.timing() {
+                Timing::Ticks => cycles >= TICKS_PER_FRAME,
+                Timing::Vip => chip.take_vblank(),
+            };
+            if frame_end {
+                break;
+            }
+        }
+        if chip.timing() == Timing::Ticks {
+            chip.timer_tick();
+        }
+        script.frame_end(&mut chip)?;
+
+        for action in script.take_actions() {
+            match action {
+                Action::Screenshot(path) => {
+                    let colors = chip.framebuffer().map(|row| row.map(|p| palette.color(p)));
+                    postfx::upscale(&colors, POSTFX_SCALE)
+                        .save_png(&path)
+                        .map_err(|e| format!("Unable to save {}: {}", path, e))?;
+                }
+                Action::Exit => return Ok(()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::drivers::testing::scratch_dir;
+    use chip8_core::Quirks;
+
+    // 200 LD V0, 3; 202 LD I, 0x300; 204 LD [I], V0; 206 ADD V0, 1; 208 JP 0x204
+    const PROGRAM: [u8; 10] = [0x60, 0x03, 0xA3, 0x00, 0xF0, 0x55, 0x70, 0x01, 0x12, 0x04];
+
+    fn machine() -> Chip8 {
+        let mut chip = Chip8::new();
+        chip.load_program(&PROGRAM).unwrap();
+        chip
+    }
+
+    fn run(script: &mut Script, chip: &mut Chip8, cycles: usize) {
+        for _ in 0..cycles {
+            script.before(chip).unwrap();
+            chip.cycle().unwrap();
+            script.after(chip).unwrap();
+        }
+    }
+
+    #[test]
+    fn reads_and_writes_the_machine() {
+        let mut chip = machine();
+        let source = "poke(0x300, peek(0x200) + 1); set_reg(5, 0x1FF); press(0xA); set_index(pc());";
+        Script::from_source(source, &mut chip).unwrap();
+        assert_eq!(chip.peek(0x300), 0x61);
+        assert_eq!(chip.register(5), 0xFF);
+        assert!(chip.keypad()[0xA]);
+        assert_eq!(chip.index(), 0x200);
+    }
+
+    #[test]
+    fn exec_and_write_callbacks() {
+        let mut chip = machine();
+        let source = "
+            on_exec(0x206, |pc| { poke(0x400, peek(0x400) + 1); });
+            on_write(|address, value| { poke(0x401, value); if value == 5 { exit(); } });
+        ";
+        let mut script = Script::from_source(source, &mut chip).unwrap();
+        // LD V0, LD I, then the loop three times
+        run(&mut script, &mut chip, 2 + 3 * 3);
+        assert_eq!(chip.peek(0x400), 3);
+        assert_eq!(chip.peek(0x401), 5);
+        assert_eq!(script.take_actions(), [Action::Exit]);
+        assert!(script.take_actions().is_empty());
+    }
+
+    #[test]
+    fn frame_callbacks_and_states() {
+        let dir = scratch_dir("script");
+        let path = dir.join("state.bin").to_string_lossy().replace('\\', "/");
+
+        let mut chip = machine();
+        chip.set_quirks(Quirks::SCHIP);
+        chip.set_timing(Timing::Vip);
+        let source = format!(
+            r#"
+            on_frame(|| {{
+                if frame() == 1 {{ save_state("{0}"); set_reg(0, 0x42); }}
+                if frame() == 2 {{ load_state("{0}"); screenshot("shot.png"); }}
+            }});
+            "#,
+            path
+        );
+        let mut script = Script::from_source(&source, &mut chip).unwrap();
+        run(&mut script, &mut chip, 1);
+        script.frame_end(&mut chip).unwrap();
+        assert_eq!(chip.register(0), 0x42);
+        script.frame_end(&mut chip).unwrap();
+        assert_eq!(chip.register(0), 3);
+        assert_eq!((chip.quirks(), chip.timing()), (Quirks::SCHIP, Timing::Vip));
+        assert_eq!(script.take_actions(), [Action::Screenshot("shot.png".to_string())]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn headless_run() {
+        let dir = scratch_dir("headless");
+        let path = |name: &str| dir.join(name).to_string_lossy().replace('\\', "/");
+        fs::write(path("loop.ch8"), PROGRAM).unwrap();
+        let source = format!(r#"on_frame(|| if frame() == 3 {{ screenshot("{}"); exit(); }});"#, path("shot.png"));
+        fs::write(path("bot.rhai"), source).unwrap();
+
+        run_headless(&path("loop.ch8"), &path("bot.rhai")).unwrap();
+        assert!(fs::metadata(path("shot.png")).unwrap().len() > 0);
+        assert!(run_headless(&path("loop.ch8"), &path("missing.rhai")).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn headless_key_wait() {
+        let dir = scratch_dir("key_wait");
+        let path = |name: &str| dir.join(name).to_string_lossy().replace('\\', "/");
+        // 200 LD V0, K; 202 LD V1, 1; 204 JP 0x204
+        fs::write(path("wait.ch8"), [0xF0, 0x0A, 0x61, 0x01, 0x12, 0x04]).unwrap();
+        let source = format!(
+            r#"
+            on_exec(0x202, |pc| poke(0x400, peek(0x400) + 1));
+            on_frame(|| {{
+                if frame() == 5 {{ press(7); }}
+                if frame() == 6 {{ release(7); }}
+                if frame() == 10 {{ save_state("{}"); exit(); }}
+            }});
+            "#,
+            path("state.bin")
+        );
+        fs::write(path("bot.rhai"), source).unwrap();
+
+        run_headless(&path("wait.ch8"), &path("bot.rhai")).unwrap();
+        let chip = state::deserialize(&fs::read(path("state.bin")).unwrap()).unwrap();
+        assert_eq!((chip.register(0), chip.register(1)), (7, 1));
+        assert_eq!(chip.peek(0x400), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn headless_uses_rom_settings() {
+        let dir = scratch_dir("headless_settings");
+        let path = |name: &str| dir.join(name).to_string_lossy().replace('\\', "/");
+        // 200 LD V0, 1; 202 LD V1, 4; 204 SHR V0, V1; 206 JP 0x206
+        fs::write(path("shift.ch8"), [0x60, 0x01, 0x61, 0x04, 0x80, 0x16, 0x12, 0x06]).unwrap();
+        let source = format!(r#"on_frame(|| {{ save_state("{}"); exit(); }});"#, path("state.bin"));
+        fs::write(path("bot.rhai"), source).unwrap();
+        let shifted = |settings: &Settings| {
+            run_headless_with(&path("shift.ch8"), &path("bot.rhai"), settings).unwrap();
+            state::deserialize(&fs::read(path("state.bin")).unwrap()).unwrap().register(0)
+        };
+
+        // CHIP-8 shifts V1 into V0, SUPER-CHIP shifts V0 in place
+        assert_eq!(shifted(&Settings::default()), 2);
+        assert_eq!(shifted(&Settings::parse("[shift.ch8]\nquirks = schip\n")), 0);
+        // With VIP timing the frame ends on the display interrupt
+        assert_eq!(shifted(&Settings::parse("[shift.ch8]\ntiming = vip\n")), 2);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reports_errors() {
+        let mut chip = machine();
+        assert!(Script::from_source("let x = ;", &mut chip).is_err());
+        let mut script = Script::from_source("on_frame(|| undefined_thing());", &mut chip).unwrap();
+        let error = script.frame_end(&mut chip).unwrap_err();
+        assert!(error.contains("undefined_thing"), "{}", error);
+        // The machine is handed back even when the script fails
+        assert_eq!(chip.pc(), 0x200);
+    }
+}
@@ -0,0 +1,256 @@
+use chip8_core::{Quirks, Timing};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// Settings file, one "key = value" per line. # starts a comment at the
+// start of a line or when a space follows it, so "#102030" colours are
+// values. Keys before the first [section] are global, a [rom name] section
+// overrides them for that rom
+//
+//   palette = amber
+//   palette.mine = 101010 e0e0e0
+//
+//   [pong.ch8]
+//   palette = lcd
+#[derive(Default, Clone)]
+pub struct Settings {
+    global: BTreeMap<String, String>,
+    roms: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+impl Settings {
+    // A missing file gives empty settings
+    pub fn load(path: &str) -> io::Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(text) => Ok(Self::parse(&text)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn parse(text: &str) -> Self {
+        let mut settings = Self::default();
+        let mut section: Option<String> = None;
+
+        for line in text.lines() {
+            let line = split_comment(line).0.trim();
+            if line.is_empty() {
+                continue;
+            }
+            if line.starts_with('[') && line.ends_with(']') {
+                section = Some(line[1..line.len() - 1].trim().to_string());
+                continue;
+            }
+            if let Some((key, value)) = line.split_once('=') {
+                settings.set(section.as_deref(), key.trim(), value.trim());
+            }
+        }
+        settings
+    }
+
+    // Rom section value first, then the global one
+    pub fn get(&self, rom: Option<&str>, key: &str) -> Option<&str> {
+        rom.and_then(|rom| self.roms.get(rom))
+            .and_then(|section| section.get(key))
+            .or_else(|| self.global.get(key))
+            .map(String::as_str)
+    }
+
+    pub fn set(&mut self, rom: Option<&str>, key: &str, value: &str) {
+        let section = match rom {
+            Some(rom) => self.roms.entry(rom.to_string()).or_default(),
+            None => &mut self.global,
+        };
+        section.insert(key.to_string(), value.to_string());
+    }
+
+    // Interpreter quirks, per rom since games were written for different ones
+    pub fn quirks(&self, rom: &str) -> Quirks {
+        let Some(name) = self.get(Some(rom), "quirks") else {
+            return Quirks::default();
+        };
+        Quirks::by_name(name).unwrap_or_else(|| {
+            let names: Vec<&str> = Quirks::PROFILES.iter().map(|&(name, _)| name).collect();
+            println!("Unknown quirks {}, available: {:?}", name, names);
+            Quirks::default()
+        })
+    }
+
+    // Cycle costs of the original VIP, frames end on its display interrupt
+    pub fn timing(&self, rom: &str) -> Timing {
+        let Some(name) = self.get(Some(rom), "timing") else {
+            return Timing::default();
+        };
+        Timing::by_name(name).unwrap_or_else(|| {
+            println!("Unknown timing {}, available: ticks, vip", name);
+            Timing::default()
+        })
+    }
+
+    // Whether the rom's section sets key itself
+    pub fn overrides(&self, rom: &str, key: &str) -> bool {
+        self.roms.get(rom).is_some_and(|section| section.contains_key(key))
+    }
+
+    // Write the settings back over the file they came from. Lines and
+    // comments stay where they were, changed values are replaced in place
+    // and new keys go at the end of their section
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+        fs::write(path, self.merge(&text))
+    }
+
+    fn merge(&self, text: &str) -> String {
+        let mut out: Vec<String> = Vec::new();
+        let mut section: Option<String> = None;
+        let mut written: BTreeSet<(Option<String>, String)> = BTreeSet::new();
+
+        for line in text.lines() {
+            let (content, comment) = split_comment(line);
+            let content = content.trim();
+            if content.starts_with('[') && content.ends_with(']') {
+                self.append_missing(section.as_deref(), &written, &mut out);
+                section = Some(content[1..content.len() - 1].trim().to_string());
+                out.push(line.to_string());
+                continue;
+            }
+            let key = content.split_once('=').map(|(key, _)| key.trim().to_string());
+            let value = key.as_deref().and_then(|key| self.section(section.as_deref())?.get(key));
+            match (key, value) {
+                (Some(key), Some(value)) => {
+                    let comment = comment.map(|comment| format!(" {}", comment)).unwrap_or_default();
+                    out.push(format!("{} = {}{}", key, value, comment));
+                    written.insert((section.clone(), key));
+                }
+                _ => out.push(line.to_string()),
+            }
+        }
+        self.append_missing(section.as_deref(), &written, &mut out);
+
+        // Sections the file didn't have yet
+        let present: BTreeSet<&str> = text
+            .lines()
+            .map(|line| split_comment(line).0.trim())
+            .filter(|line| line.starts_with('[') && line.ends_with(']'))
+            .map(|line| line[1..line.len() - 1].trim())
+            .collect();
+        for rom in self.roms.keys().filter(|rom| !present.contains(rom.as_str())) {
+            if !out.is_empty() {
+                out.push(String::new());
+            }
+            out.push(format!("[{}]", rom));
+            self.append_missing(Some(rom), &written, &mut out);
+        }
+        let mut text = out.join("\n");
+        text.push('\n');
+        text
+    }
+
+    fn section(&self, rom: Option<&str>) -> Option<&BTreeMap<String, String>> {
+        match rom {
+            Some(rom) => self.roms.get(rom),
+            None => Some(&self.global),
+        }
+    }
+
+    // Keys of a section not in the file, added after its last non blank line
+    fn append_missing(&self, rom: Option<&str>, written: &BTreeSet<(Option<String>, String)>, out: &mut Vec<String>) {
+        let Some(section) = self.section(rom) else {
+            return;
+        };
+        let mut at = out.len();
+        while at > 0 && out[at - 1].trim().is_empty() {
+            at -= 1;
+        }
+        let missing = section
+            .iter()
+            .filter(|(key, _)| !written.contains(&(rom.map(str::to_string), key.to_string())))
+            .map(|(key, value)| format!("{} = {}", key, value));
+        let tail = out.split_off(at);
+        out.extend(missing);
+        out.extend(tail);
+    }
+
+    // Global keys starting with prefix, with the prefix removed
+    pub fn with_prefix<'a>(&'a self, prefix: &'a str) -> impl Iterator<Item = (&'a str, &'a str)> {
+        self.global
+            .iter()
+            .filter_map(move |(k, v)| k.strip_prefix(prefix).map(|name| (name, v.as_str())))
+    }
+}
+
+// Section name for a rom, its file name
+pub fn rom_name(path: &str) -> String {
+    Path::new(path)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.to_string())
+}
+
+// The line before its comment and the comment, # included
+fn split_comment(line: &str) -> (&str, Option<&str>) {
+    let at = line.char_indices().find(|&(at, c)| {
+        c == '#' && (line[..at].trim().is_empty() || line[at + 1..].chars().next().is_none_or(char::is_whitespace))
+    });
+    match at {
+        Some((at, _)) => (&line[..at], Some(&line[at..])),
+        None => (line, None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rom_sections_override_global_keys() {
+        let settings = Settings::parse(
+            "palette = amber # comment\npalette.mine = 000000 ffffff\n\n[pong.ch8]\npalette = lcd\n",
+        );
+        assert_eq!(settings.get(None, "palette"), Some("amber"));
+        assert_eq!(settings.get(Some("pong.ch8"), "palette"), Some("lcd"));
+        assert_eq!(settings.get(Some("tetris.ch8"), "palette"), Some("amber"));
+        assert_eq!(settings.get(None, "missing"), None);
+        assert!(settings.overrides("pong.ch8", "palette"));
+        assert!(!settings.overrides("tetris.ch8", "palette"));
+        assert_eq!(rom_name("roms/pong.ch8"), "pong.ch8");
+        assert_eq!(settings.with_prefix("palette.").collect::<Vec<_>>(), [("mine", "000000 ffffff")]);
+
+        let settings = Settings::parse("#102030 first\npalette.mine = #102030 #A0B0C0 # mine\n");
+        assert_eq!(settings.with_prefix("palette.").collect::<Vec<_>>(), [("mine", "#102030 #A0B0C0")]);
+        assert_eq!(settings.get(None, "#102030 first"), None);
+
+        let settings = Settings::parse("timing = vip\n[pong.ch8]\nquirks = schip\n[brix.ch8]\nquirks = nope\n");
+        assert_eq!((settings.quirks("pong.ch8"), settings.timing("pong.ch8")), (Quirks::SCHIP, Timing::Vip));
+        assert_eq!(settings.quirks("brix.ch8"), Quirks::default());
+    }
+
+    #[test]
+    fn saving_keeps_the_file_layout() {
+        let text = "# my settings\npalette = amber # warm\n\n[pong.ch8]\nquirks = vip\n\n[tetris.ch8]\n";
+        let mut settings = Settings::parse(text);
+        settings.set(None, "palette", "lcd");
+        settings.set(None, "volume", "30");
+        settings.set(Some("pong.ch8"), "timing", "vip");
+        settings.set(Some("brix.ch8"), "quirks", "schip");
+
+        let merged = settings.merge(text);
+        assert_eq!(
+            merged,
+            "# my settings\npalette = lcd # warm\nvolume = 30\n\n[pong.ch8]\nquirks = vip\ntiming = vip\n\n\
+             [tetris.ch8]\n\n[brix.ch8]\nquirks = schip\n"
+        );
+        assert_eq!(Settings::parse(&merged).get(Some("brix.ch8"), "quirks"), Some("schip"));
+        assert_eq!(settings.merge(&merged), merged);
+
+        let mut fresh = Settings::default();
+        fresh.set(Some("pong.ch8"), "speed", "12");
+        assert_eq!(fresh.merge(""), "[pong.ch8]\nspeed = 12\n");
+    }
+}
@@ -0,0 +1,263 @@
+use crate::drivers::args::SpriteArgs;
+use crate::drivers::configs::defaults::*;
+use crate::drivers::palette::Rgb;
+use crate::drivers::postfx::Image;
+use chip8_core::decode::{decode, Instruction};
+use chip8_core::Chip8;
+use std::fs;
+
+// Sprites per text line and per sheet row
+pub const COLUMNS: usize = 8;
+pub const SHEET_SCALE: usize = 4;
+
+const DRAWN_COLOR: &str = "\x1b[32m";
+const RESET: &str = "\x1b[0m";
+
+const BACKGROUND: Rgb = [24, 24, 32];
+const LIT: Rgb = [220, 220, 220];
+const DRAWN_LIT: Rgb = [90, 230, 110];
+const DRAWN_BACKGROUND: Rgb = [24, 56, 32];
+
+// Which bytes Dxyn has read sprite data from
+pub struct DrawLog {
+    drawn: Vec<bool>,
+}
+
+impl Default for DrawLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DrawLog {
+    pub fn new() -> Self {
+        Self { drawn: vec![false; 4096] }
+    }
+
+    // Call after every executed instruction, I is left alone by Dxyn so it
+    // still points at the sprite
+    pub fn observe(&mut self, chip: &Chip8) {
+        if let Instruction::Drw(_, _, n) = decode(chip.opcode()) {
+            let start = chip.index();
+            let end = (start + n as usize).min(self.drawn.len());
+            for drawn in self.drawn[start.min(end)..end].iter_mut() {
+                *drawn = true;
+            }
+        }
+    }
+
+    pub fn is_drawn(&self, address: usize) -> bool {
+        self.drawn.get(address).copied().unwrap_or(false)
+    }
+
+    // Any byte in address..address + len
+    pub fn any_drawn(&self, address: usize, len: usize) -> bool {
+        (address..address + len).any(|a| self.is_drawn(a))
+    }
+
+    // Drawn regions as (start, len), in address order
+    pub fn regions(&self) -> Vec<(usize, usize)> {
+        let mut regions: Vec<(usize, usize)> = Vec::new();
+        for (address, _) in self.drawn.iter().enumerate().filter(|(_, &d)| d) {
+            match regions.last_mut() {
+                Some((start, len)) if *start + *len == address => *len += 1,
+                _ => regions.push((address, 1)),
+            }
+        }
+        regions
+    }
+}
+
+// Start address of every sprite in memory[start..start + len]
+fn sprite_addresses(memory: &[u8], start: usize, len: usize, height: usize) -> Vec<usize> {
+    let end = start.saturating_add(len).min(memory.len());
+    (start..end).step_by(height.max(1)).collect()
+}
+
+// Row bytes of one sprite, zero past the range or the end of memory
+fn sprite_rows(memory: &[u8], address: usize, height: usize, end: usize) -> Vec<u8> {
+    (address..address + height)
+        .map(|a| if a < end.min(memory.len()) { memory[a] } else { 0 })
+        .collect()
+}
+
+// Sprites side by side in half blocks, two rows per line, under a header
+// with each start address, drawn sprites marked with * and in colour
+pub fn render_text(
+    memory: &[u8],
+    start: usize,
+    len: usize,
+    height: usize,
+    drawn: &DrawLog,
+    color: bool,
+) -> Vec<String> {
+    let end = start.saturating_add(len).min(memory.len());
+    let addresses = sprite_addresses(memory, start, len, height);
+    let mut lines = Vec::new();
+
+    for group in addresses.chunks(COLUMNS) {
+        let marked: Vec<bool> = group.iter().map(|&a| drawn.any_drawn(a, height.min(end - a))).collect();
+        let header = group
+            .iter()
+            .zip(marked.iter())
+            .map(|(a, &m)| format!("{:03X}{:<6}", a, if m { "*" } else { "" }))
+            .collect::<Vec<_>>()
+            .join(" ");
+        lines.push(header.trim_end().to_string());
+
+        let sprites: Vec<Vec<u8>> = group.iter().map(|&a| sprite_rows(memory, a, height, end)).collect();
+        for pair in (0..height).step_by(2) {
+            let cells = sprites
+                .iter()
+                .zip(marked.iter())
+                .map(|(rows, &m)| {
+                    let top = rows[pair];
+                    let bottom = rows.get(pair + 1).copied().unwrap_or(0);
+                    let text: String = (0..8)
+                        .map(|bit| {
+                            let mask = 0x80 >> bit;
+                            match (top & mask != 0, bottom & mask != 0) {
+                                (true, true) => '█',
+                                (true, false) => '▀',
+                                (false, true) => '▄',
+                                (false, false) => ' ',
+                            }
+                        })
+                        .collect();
+                    if color && m {
+                        format!("{}{}{} ", DRAWN_COLOR, text, RESET)
+                    } else {
+                        format!("{} ", text)
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join(" ");
+            lines.push(cells.trim_end().to_string());
+        }
+    }
+    lines
+}
+
+// PNG sheet, COLUMNS sprites a row with a one pixel gap, drawn rows tinted
+pub fn render_sheet(memory: &[u8], start: usize, len: usize, height: usize, drawn: &DrawLog) -> Image {
+    let end = start.saturating_add(len).min(memory.len());
+    let addresses = sprite_addresses(memory, start, len, height);
+    let rows = addresses.len().div_ceil(COLUMNS).max(1);
+    let (cell_w, cell_h) = (8 + 1, height + 1);
+    let mut image = Image::new(
+        COLUMNS * cell_w * SHEET_SCALE,
+        rows * cell_h * SHEET_SCALE,
+    );
+
+    for (i, &address) in addresses.iter().enumerate() {
+        let (ox, oy) = ((i % COLUMNS) * cell_w, (i / COLUMNS) * cell_h);
+        for (y, byte) in sprite_rows(memory, address, height, end).iter().enumerate() {
+            let marked = drawn.is_drawn(address + y);
+            for x in 0..8 {
+                let lit = byte & (0x80 >> x) != 0;
+                let rgb = match (lit, marked) {
+                    (true, true) => DRAWN_LIT,
+                    (true, false) => LIT,
+                    (false, true) => DRAWN_BACKGROUND,
+                    (false, false) => BACKGROUND,
+                };
+                for sy in 0..SHEET_SCALE {
+                    for sx in 0..SHEET_SCALE {
+                        image.set((ox + x) * SHEET_SCALE + sx, (oy + y) * SHEET_SCALE + sy, rgb);
+                    }
+                }
+            }
+        }
+    }
+    image
+}
+
+// The sprites subcommand, run the rom without a window for a while with
+// no keys pressed, then show what it holds and what got drawn
+pub fn inspect(rom: &str, args: &SpriteArgs) -> Result<(), String> {
+    let program = fs::read(rom).map_err(|e| format!("Unable to read {}: {}", rom, e))?;
+    let mut chip = Chip8::new();
+    chip.load_program(&program).map_err(|e| e.to_string())?;
+
+    let mut log = DrawLog::new();
+    for cycle in 0..args.cycles {
+        if let Err(fault) = chip.cycle() {
+            println!("Stopped after {} cycles: {}", cycle, fault);
+            break;
+        }
+        log.observe(&chip);
+        if cycle % TICKS_PER_FRAME as u64 == 0 {
+            chip.timer_tick();
+        }
+    }
+
+    let rom_end = START_ADDRESS as usize + program.len();
+    let len = args.len.unwrap_or(rom_end.saturating_sub(args.from));
+    for line in render_text(chip.memory(), args.from, len, args.height, &log, true) {
+        println!("{}", line);
+    }
+    let regions: Vec<String> = log
+        .regions()
+        .iter()
+        .map(|(start, len)| format!("{:03X}-{:03X}", start, start + len - 1))
+        .collect();
+    println!("Drawn: {}", if regions.is_empty() { "nothing".to_string() } else { regions.join(" ") });
+
+    if let Some(path) = &args.png {
+        render_sheet(chip.memory(), args.from, len, args.height, &log)
+            .save_png(path)
+            .map_err(|e| format!("Unable to save {}: {}", path, e))?;
+        println!("Saved {}", path);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn logs_drawn_bytes() {
+        let mut chip = Chip8::new();
+        // LD I, 0x20A; DRW V0, V0, 3
+        chip.load_program(&[0xA2, 0x0A, 0xD0, 0x03]).unwrap();
+        let mut log = DrawLog::new();
+        for _ in 0..2 {
+            chip.cycle().unwrap();
+            log.observe(&chip);
+        }
+        assert_eq!(log.regions(), [(0x20A, 3)]);
+        assert!(log.any_drawn(0x208, 3));
+        assert!(!log.any_drawn(0x200, 4));
+    }
+
+    #[test]
+    fn text_layout() {
+        let memory = [0xF0, 0x90, 0x90, 0xF0, 0x20, 0x60];
+        let mut log = DrawLog::new();
+        log.drawn[4] = true;
+        let lines = render_text(&memory, 0, 6, 4, &log, false);
+        assert_eq!(
+            lines,
+            [
+                "000       004*",
+                "█▀▀█       ▄█",
+                "█▄▄█",
+            ]
+        );
+        assert_eq!(render_text(&memory, 0, usize::MAX, 4, &log, false), lines);
+    }
+
+    #[test]
+    fn sheet_size_and_tint() {
+        let memory = [0x80; 20];
+        let mut log = DrawLog::new();
+        log.drawn[0] = true;
+        let sheet = render_sheet(&memory, 0, 20, 2, &log);
+        // Ten sprites, two rows of eight
+        assert_eq!((sheet.width, sheet.height), (8 * 9 * SHEET_SCALE, 2 * 3 * SHEET_SCALE));
+        assert_eq!(sheet.get(0, 0), DRAWN_LIT);
+        assert_eq!(sheet.get(0, SHEET_SCALE), LIT);
+        assert_eq!(sheet.get(SHEET_SCALE, 0), DRAWN_BACKGROUND);
+    }
+}
@@ -0,0 +1,11 @@
+use std::fs;
+use std::path::PathBuf;
+
+// An empty directory for a test's files, named by the test and the
+// process so parallel runs keep apart. The test removes it when done
+pub fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("chip8_{}_{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
@@ -1,47 +1,188 @@
 use sdl2;
-use sdl2::pixels;
+use sdl2::pixels::{self, PixelFormatEnum};
 use sdl2::rect::Rect;
-use sdl2::render::Canvas;
-use sdl2::video::Window;
+use sdl2::render::{Canvas, Texture};
+use sdl2::video::{FullscreenType, Window};
 use crate::drivers::configs::defaults::*;
+use crate::drivers::overlay::Overlay;
+use crate::drivers::palette::{Palette, Rgb};
+use crate::drivers::persistence::Persistence;
+use crate::drivers::postfx::{self, Effects, Image};
+use std::io;
+use std::time::Instant;
+
+// Frames are built on the cpu, optionally upscaled and post processed,
+// uploaded to one streaming texture and scaled into the window by the
+// software renderer. Plain frames go up at the chip8 resolution, only
+// effects and overlay text need POSTFX_SCALE times that, the texture is
+// recreated when the size changes. Screenshots are always the larger size
 pub struct VideoDriver {
+    texture: Texture,
     canvas: Canvas<Window>,
+    palette: Palette,
+    persistence: Option<Persistence>,
+    effects: Effects,
+    frame: Image,
+    integer_scale: bool,
+    overlay: Overlay,
 }
 
 impl VideoDriver {
-    pub fn new(sdl_context: &sdl2::Sdl) -> Self {
+    pub fn new(sdl_context: &sdl2::Sdl, palette: Palette) -> Self {
         let video_subsys = sdl_context.video().unwrap();
+        // Nearest neighbour scaling keeps pixels square
+        sdl2::hint::set("SDL_RENDER_SCALE_QUALITY", "0");
         let window = video_subsys
             .window(
-                "rust-sdl2_gfx: draw line & FPSManager",
+                WINDOW_TITLE,
                 SDL_WIDTH,
                 SDL_HEIGHT,
             )
             .position_centered()
-            .opengl()
+            .resizable()
             .build()
             .unwrap();
             
-            let mut canvas = window.into_canvas().build().unwrap();
+            let mut canvas = window.into_canvas().software().build().unwrap();
+            let frame = Image::new(VIDEO_WIDTH as usize, VIDEO_HEIGHT as usize);
+            let texture = create_texture(&canvas, &frame);
 
-            canvas.set_draw_color(pixels::Color::RGB(0, 0, 0));
+            canvas.set_draw_color(color(palette.color(0)));
             canvas.clear();
             canvas.present();
     
-            VideoDriver { canvas: canvas }
+            VideoDriver {
+                texture,
+                canvas,
+                palette,
+                persistence: None,
+                effects: Effects::default(),
+                frame,
+                integer_scale: true,
+                overlay: Overlay::new(),
+            }
+    }
+
+    // Takes effect on the next draw
+    pub fn set_palette(&mut self, palette: &Palette) {
+        self.palette = palette.clone();
+    }
+
+    // Fade filter for flicker, None draws pixels as they are
+    pub fn set_persistence(&mut self, decay: Option<f32>) {
+        self.persistence = decay.map(Persistence::new);
+    }
+
+    pub fn persistence(&self) -> Option<f32> {
+        self.persistence.as_ref().map(Persistence::decay)
+    }
+
+    // Fading pixels need a draw every frame even if vram didn't change
+    pub fn is_fading(&self) -> bool {
+        self.persistence.as_ref().is_some_and(Persistence::is_fading)
+    }
+
+    // Status line and notifications, drawn over the frame on the next draw
+    pub fn overlay(&mut self) -> &mut Overlay {
+        &mut self.overlay
+    }
+
+    // Fading pixels or overlay text need a draw every frame even if vram
+    // didn't change
+    pub fn needs_redraw(&mut self) -> bool {
+        self.is_fading() || self.overlay.is_active(Instant::now())
+    }
+
+    // Whole multiples of the chip8 resolution only, or any size that
+    // keeps the aspect ratio
+    pub fn set_integer_scale(&mut self, integer_scale: bool) {
+        self.integer_scale = integer_scale;
+        self.present();
+    }
+
+    // Takes effect on the next draw
+    pub fn set_effects(&mut self, effects: Effects) {
+        self.effects = effects;
+    }
+
+    pub fn effects(&self) -> Effects {
+        self.effects
+    }
+
+    // Save the last frame with its effects, the overlay is left out
+    pub fn screenshot(&self, path: &str) -> io::Result<()> {
+        match self.frame.width == VIDEO_WIDTH as usize {
+            true => self.frame.scaled(POSTFX_SCALE).save_png(path),
+            false => self.frame.save_png(path),
+        }
+    }
+
+    pub fn set_title(&mut self, title: &str) {
+        let _ = self.canvas.window_mut().set_title(title);
+    }
+
+    pub fn toggle_fullscreen(&mut self) {
+        let window = self.canvas.window_mut();
+        let state = match window.fullscreen_state() {
+            FullscreenType::Off => FullscreenType::Desktop,
+            _ => FullscreenType::Off,
+        };
+        let _ = window.set_fullscreen(state);
+        self.present();
+    }
+
+    // Once per emulated frame, before its draw
+    pub fn end_frame(&mut self, pixels: &[[u8; 64]; 32]) {
+        if let Some(persistence) = self.persistence.as_mut() {
+            persistence.advance(pixels);
+        }
     }
 
     pub fn draw(&mut self, pixels: &[[u8; 64]; 32]) {
-        for (y, row) in pixels.iter().enumerate() {
-            for (x, &col) in row.iter().enumerate() {
-                let x = (x as u32) * SCALE_FACTOR;
-                let y = (y as u32) * SCALE_FACTOR;
-
-                self.canvas.set_draw_color(color(col));
-                let _ = self.canvas
-                    .fill_rect(Rect::new(x as i32, y as i32, SCALE_FACTOR, SCALE_FACTOR));
-            }
+        let colors = match self.persistence.as_ref() {
+            Some(persistence) => persistence.render(pixels, &self.palette),
+            None => pixels.map(|row| row.map(|p| self.palette.color(p))),
+        };
+
+        let now = Instant::now();
+        let scale = if self.effects.any() { POSTFX_SCALE } else { 1 };
+        self.frame = postfx::apply(&colors, scale, self.effects);
+
+        // Text needs more than 64x32 to be readable, screenshots keep the
+        // frame without the overlay
+        let pixels = if self.overlay.is_active(now) {
+            let mut shown = self.frame.scaled(POSTFX_SCALE / scale);
+            self.overlay.render(&mut shown, now);
+            shown
+        } else {
+            self.frame.clone()
+        };
+
+        let query = self.texture.query();
+        if (query.width as usize, query.height as usize) != (pixels.width, pixels.height) {
+            let old = std::mem::replace(&mut self.texture, create_texture(&self.canvas, &pixels));
+            // Safety: the old texture is no longer referenced and the
+            // renderer that created it is still alive
+            unsafe { old.destroy() };
         }
+        let _ = self.texture.update(None, &pixels.to_rgb24(), pixels.width * 3);
+        self.present();
+    }
+
+    // Show the last drawn frame again, after a resize or expose
+    pub fn present(&mut self) {
+        let (width, height) = self.canvas.output_size().unwrap_or((SDL_WIDTH, SDL_HEIGHT));
+        let (x, y, w, h) = fit(
+            (width, height),
+            (VIDEO_WIDTH as u32, VIDEO_HEIGHT as u32),
+            self.integer_scale,
+        );
+
+        self.canvas.set_draw_color(pixels::Color::RGB(0, 0, 0));
+        self.canvas.clear();
+        let _ = self
+            .canvas
+            .copy(&self.texture, None, Rect::new(x, y, w, h));
         self.canvas.present();
     }
 
@@ -49,10 +190,43 @@ impl VideoDriver {
 
 }
 
-fn color(value: u8) -> pixels::Color {
-    if value == 0 {
-        pixels::Color::RGB(0, 0, 0)
-    } else {
-        pixels::Color::RGB(0, 250, 0)
+fn create_texture(canvas: &Canvas<Window>, frame: &Image) -> Texture {
+    canvas
+        .texture_creator()
+        .create_texture_streaming(PixelFormatEnum::RGB24, frame.width as u32, frame.height as u32)
+        .unwrap()
+}
+
+// Largest rect with the source aspect ratio that fits the window, centered
+// Integer scaling uses whole multiples unless the window is too small
+fn fit(window: (u32, u32), source: (u32, u32), integer_scale: bool) -> (i32, i32, u32, u32) {
+    let (ww, wh) = window;
+    let (sw, sh) = source;
+    let scale = (ww as f32 / sw as f32).min(wh as f32 / sh as f32);
+    let scale = if integer_scale && scale >= 1.0 { scale.floor() } else { scale };
+
+    let w = ((sw as f32 * scale) as u32).max(1);
+    let h = ((sh as f32 * scale) as u32).max(1);
+    (((ww - w.min(ww)) / 2) as i32, ((wh - h.min(wh)) / 2) as i32, w, h)
+}
+
+fn color(rgb: Rgb) -> pixels::Color {
+    pixels::Color::RGB(rgb[0], rgb[1], rgb[2])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fit_letterboxes() {
+        assert_eq!(fit((1280, 640), (64, 32), true), (0, 0, 1280, 640));
+        // Wide window, bars left and right
+        assert_eq!(fit((1920, 640), (64, 32), true), (320, 0, 1280, 640));
+        // Integer scale rounds down to 15x
+        assert_eq!(fit((1000, 700), (64, 32), true), (20, 110, 960, 480));
+        assert_eq!(fit((1000, 700), (64, 32), false), (0, 100, 1000, 500));
+        // Smaller than the source still keeps the aspect ratio
+        assert_eq!(fit((32, 32), (64, 32), true), (0, 8, 32, 16));
     }
-}
\ No newline at end of file
+}
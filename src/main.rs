@@ -1,25 +1,100 @@
 extern crate sdl2;
 pub mod drivers;
+use crate::drivers::args::Args;
+use crate::drivers::configs::defaults::ROM_DIR;
 use crate::drivers::emulator::Emulator;
-use std::fs;
+use std::env;
 use std::io;
+use std::path::Path;
+use std::process;
 
 fn main() {
+    let args = Args::parse(env::args().skip(1)).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(2);
+    });
+
+    if let Some(sprites) = &args.sprites {
+        let rom = args.rom.as_deref().unwrap_or_else(|| {
+            eprintln!("sprites needs a rom");
+            process::exit(2);
+        });
+        if let Err(e) = drivers::sprites::inspect(rom, sprites) {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(diff) = &args.diff {
+        let rom = args.rom.as_deref().unwrap_or_else(|| {
+            eprintln!("diff needs a rom");
+            process::exit(2);
+        });
+        if let Err(e) = drivers::differential::run(rom, diff) {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+        return;
+    }
+
+    // Pick the rom first, the debugger reads commands from stdin once the
+    // emulator is up
+    if args.headless {
+        let (Some(rom), Some(script)) = (args.rom.as_deref(), args.script.as_deref()) else {
+            eprintln!("--headless needs a rom and --script");
+            process::exit(2);
+        };
+        if let Err(e) = drivers::script::run_headless(rom, script) {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+        return;
+    }
+
+    let rom = match args.rom.clone() {
+        Some(rom) => rom,
+        None => choose_rom(),
+    };
+
     let mut emulator = Emulator::new();
-    let files = fs::read_dir("./roms").unwrap();
+    if let Some(path) = &args.trace {
+        emulator
+            .trace_to(path, args.trace_filter)
+            .expect("Unable to create trace file");
+    }
+    if let Some(prefix) = &args.profile {
+        emulator.profile_to(prefix);
+    }
+    if args.sanitize {
+        emulator.sanitize();
+    }
+    if let Some(path) = &args.script {
+        emulator.script(path);
+    }
+    if let Some(port) = args.gdb {
+        emulator.gdb_listen(port).expect("Unable to start gdb server");
+    }
+    emulator.run(&rom);
+}
+
+fn choose_rom() -> String {
+    let files = drivers::menu::rom_files(ROM_DIR).unwrap_or_else(|e| {
+        eprintln!("Unable to list {}: {}", ROM_DIR, e);
+        process::exit(1);
+    });
 
     println!("Available files: ");
-    for file in files {
-        println!("{}", file.unwrap().path().display());
+    for file in &files {
+        println!("{}", file);
     }
 
     let mut input = String::new();
-    let prepend = "./roms/";
     let stdin = io::stdin();
 
     stdin.read_line(&mut input).expect("Input error");
 
-    let file_name = prepend.to_owned() + &input;
-    println!("{}", file_name);
-    emulator.run(file_name.trim());
+    let file_name = Path::new(ROM_DIR).join(input.trim());
+    println!("{}", file_name.display());
+    file_name.to_string_lossy().into_owned()
 }